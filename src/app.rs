@@ -1,10 +1,13 @@
 use crate::core::consts;
 use crate::core::midi::Midi;
+use crate::core::midi_input::MidiIn;
+use crate::core::midi_output::{self, Output};
 use crate::core::regex::RegExpHandler;
 use crate::core::timing::metronome::{Message, Metronome};
+use crate::core::timing::source::{RealTime, TimeSource};
 use crate::core::{command_handler::CommandManager, midi};
 use crate::view::common::menubar::Menubar;
-use crate::view::common::playhead_controller::Marker;
+use crate::view::common::playhead_controller::{self, Marker};
 #[cfg(feature = "microcontroller")]
 use crate::view::microcontroller::console::RegexFlag;
 use cursive::theme::{BorderStyle, Palette};
@@ -12,6 +15,8 @@ use cursive::views::TextView;
 use cursive::Cursive;
 use num::rational::Ratio;
 use num::FromPrimitive;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::iterator::Signals;
 use std::rc::Rc;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
@@ -30,12 +35,14 @@ pub type UserData = Rc<UserDataInner>;
 pub struct UserDataInner {
   pub cmd: CommandManager,
   pub midi_tx: Sender<midi::Message>,
+  pub marker_tx: Sender<playhead_controller::Message>,
 }
 
 /// Application components bundle
 pub struct AppComponents {
   pub cursive: Cursive,
   pub midi: Midi,
+  pub midi_in: MidiIn,
   pub regex_handler: RegExpHandler,
   pub anu: Anu,
   pub marker: Marker,
@@ -59,20 +66,39 @@ pub fn initialize_components() -> AppComponents {
   let mut cursive = Cursive::new();
   init_cursive_theme(&mut cursive);
 
-  let mut midi = Midi::new();
-  midi.init().unwrap();
+  let time_source: Arc<dyn TimeSource> = Arc::new(RealTime);
+  let mut midi = Midi::new(cursive.cb_sink().clone());
+  // no output port is fine, notes go nowhere until one is plugged in
+  let _ = midi.init();
 
   let regex_handler = RegExpHandler::new(cursive.cb_sink().clone());
   let last_key_time = Arc::new(Mutex::new(None));
   let current_tempo = Arc::new(Mutex::new(DEFAULT_TEMPO));
   let anu = Anu::new();
 
-  let marker = Marker::new(cursive.cb_sink().clone(), midi.tx.clone());
-  let metronome = Metronome::new(cursive.cb_sink().clone(), marker.tx.clone());
+  let marker = Marker::new(
+    cursive.cb_sink().clone(),
+    midi.tx.clone(),
+    midi.sends_controls(),
+  );
+  let metronome = Metronome::new(
+    cursive.cb_sink().clone(),
+    marker.tx.clone(),
+    midi.tx.clone(),
+    Arc::clone(&current_tempo),
+    time_source,
+  );
+  let midi_in = MidiIn::new(
+    metronome.tx.clone(),
+    marker.tx.clone(),
+    midi.tx.clone(),
+    cursive.cb_sink().clone(),
+  );
 
   AppComponents {
     cursive,
     midi,
+    midi_in,
     regex_handler,
     anu,
     marker,
@@ -106,14 +132,23 @@ pub fn setup_ui(components: &mut AppComponents) {
   components.cursive.set_user_data(Rc::new(UserDataInner {
     cmd: command_manager,
     midi_tx: midi_tx.clone(),
+    marker_tx: marker_tx.clone(),
   }));
 
   let main_view = components
     .anu
     .build(components.regex_handler.tx.clone(), marker_tx);
 
-  let devices = components.midi.get_available_devices();
-  let menu_app = Menubar::build_menu_app(&devices, midi_tx.clone());
+  let midi_state = components.midi.menu_state();
+  let input_devices = components.midi_in.get_available_devices();
+  let menu_app = Menubar::build_menu_app(
+    &midi_state,
+    &input_devices,
+    components.midi_in.controls_port_index(),
+    midi_tx.clone(),
+    components.midi_in.tx.clone(),
+    metronome_tx.clone(),
+  );
   let menu_help = Menubar::build_menu_help();
 
   components
@@ -129,11 +164,35 @@ pub fn setup_ui(components: &mut AppComponents) {
   components
     .cursive
     .call_on_name(consts::midi_status_unit_view, |view: &mut TextView| {
-      view.set_content(components.midi.out_device_name());
+      view.set_content(components.midi.outputs_label());
     })
     .unwrap();
 }
 
+/// Quit through the UI on SIGINT/SIGTERM/SIGHUP, so the regular shutdown silences the outputs
+pub fn spawn_signal_handler(cb_sink: cursive::CbSink) {
+  let Ok(mut signals) = Signals::new([SIGINT, SIGTERM, SIGHUP]) else {
+    return;
+  };
+  thread::spawn(move || {
+    if signals.forever().next().is_some() {
+      let _ = cb_sink.send(Box::new(|s| s.quit()));
+    }
+  });
+}
+
+/// Silence every output on a panic, release builds abort right after without any clean-up
+pub fn install_panic_hook(outputs: Arc<Mutex<Vec<Output>>>) {
+  let default_hook = std::panic::take_hook();
+  std::panic::set_hook(Box::new(move |info| {
+    // the panicking thread may be the one holding the lock, never wait for it
+    if let Ok(mut outputs) = outputs.try_lock() {
+      midi_output::all_notes_off(&mut outputs);
+    }
+    default_hook(info);
+  }));
+}
+
 /// Spawn a background thread to monitor key press timing and reset tempo
 fn spawn_tempo_monitor_thread(
   last_key_time: Arc<Mutex<Option<Instant>>>,
@@ -0,0 +1,231 @@
+//! Channel messages a match sends next to its notes: control changes, pitch bend and
+//! program changes, with their values taken from where the playhead is.
+
+use super::regex::Match;
+
+// 14-bit pitch bend
+static PITCH_BEND_CENTER: u16 = 0x2000;
+static PITCH_BEND_MAX: u16 = 0x3FFF;
+
+// in place of a group name, scopes a program change to the marker
+const MARKER_GROUP: &str = "marker";
+
+/// Where a control change takes its value from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CcValue {
+  X,         // column of the cell, 0 on the left to 127 on the right
+  Y,         // row of the cell, 127 on the top row to 0 at the bottom, like pitches
+  Char,      // character code of the cell (or of the group), 127 past ASCII
+  Fixed(u8), // always the same value
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelMsgKind {
+  Cc(u8, CcValue), // (controller number, value)
+  PitchBend,       // bends up across the match, from the center on its first cell
+  ProgramChange(u8),
+}
+
+/// A configured channel message, sent on every step inside a match.
+///
+/// Without a group it follows every match under the marker, with one only the matches where
+/// that named group took part, eg. `cc74:chr@vowel` for `(?P<vowel>[aeiou])`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelMsg {
+  pub kind: ChannelMsgKind,
+  pub channel: u8,
+  pub group: Option<String>,
+}
+
+/// The step a match is played on, with only what its controls are made of
+#[derive(Clone, Debug)]
+pub struct StepContext {
+  pub x: usize,
+  pub y: usize,
+  pub grid_width: usize,
+  pub grid_height: usize,
+  pub offset: usize, // cells from the start of the match
+  pub start: usize,  // grid index of the first cell of the match
+  pub span: usize,   // cells covered by the match
+  pub char: Option<char>,
+  pub groups: Vec<(String, char)>, // named groups that took part, with their first char
+  pub program: Option<(u8, u8)>,   // (channel, program) of the marker playing the step
+}
+
+impl StepContext {
+  pub fn new(
+    matched: &Match,
+    offset: usize,
+    (x, y): (usize, usize),
+    (grid_width, grid_height): (usize, usize),
+    program: Option<(u8, u8)>,
+  ) -> Self {
+    Self {
+      x,
+      y,
+      grid_width,
+      grid_height,
+      offset,
+      start: matched.start(),
+      span: matched.span(),
+      char: matched.char_at(offset),
+      groups: matched.group_chars(),
+      program,
+    }
+  }
+
+  fn group(&self, name: &str) -> Option<char> {
+    self
+      .groups
+      .iter()
+      .find(|(group, _)| group == name)
+      .map(|(_, char)| *char)
+  }
+}
+
+impl ChannelMsg {
+  pub fn new(kind: ChannelMsgKind, channel: u8, group: Option<String>) -> Self {
+    Self {
+      kind,
+      channel,
+      group,
+    }
+  }
+
+  /// `pc12@marker`: the program of the marker being edited, instead of one sent from the matches
+  pub fn marker_program(&self) -> Option<(u8, u8)> {
+    match (self.kind, self.group.as_deref()) {
+      (ChannelMsgKind::ProgramChange(program), Some(MARKER_GROUP)) => Some((self.channel, program)),
+      _ => None,
+    }
+  }
+
+  /// The bytes to send for `step`, `None` when the step is not for this message
+  pub fn to_bytes(&self, step: &StepContext) -> Option<Vec<u8>> {
+    let group = match &self.group {
+      Some(name) => Some(step.group(name)?),
+      None => None,
+    };
+    let channel = self.channel & 0x0F;
+
+    match self.kind {
+      ChannelMsgKind::Cc(controller, value) => {
+        let value = match value {
+          CcValue::X => spread(step.x, step.grid_width),
+          CcValue::Y => 127 - spread(step.y, step.grid_height),
+          CcValue::Char => {
+            let char = group.or(step.char);
+            char.map_or(0, |char| (char as u32).min(127) as u8)
+          }
+          CcValue::Fixed(value) => value.min(127),
+        };
+        Some(vec![0xB0 | channel, controller & 0x7F, value])
+      }
+      ChannelMsgKind::PitchBend => {
+        Some(pitch_bend_msg(channel, pitch_bend(step.offset, step.span)))
+      }
+      // sent on the first cell only, the program holds for the rest of the match
+      ChannelMsgKind::ProgramChange(program) => {
+        (step.offset == 0).then(|| vec![0xC0 | channel, program & 0x7F])
+      }
+    }
+  }
+}
+
+/// `position` out of `len` cells onto 0-127
+pub fn spread(position: usize, len: usize) -> u8 {
+  match len {
+    0 | 1 => 0,
+    len => (position.min(len - 1) * 127 / (len - 1)) as u8,
+  }
+}
+
+/// A 14-bit pitch bend message
+pub fn pitch_bend_msg(channel: u8, bend: u16) -> Vec<u8> {
+  vec![
+    0xE0 | (channel & 0x0F),
+    (bend & 0x7F) as u8,
+    ((bend >> 7) & 0x7F) as u8,
+  ]
+}
+
+/// Bend for the cell `offset` into a match of `span` cells, from the center up to the top
+pub fn pitch_bend(offset: usize, span: usize) -> u16 {
+  let range = (PITCH_BEND_MAX - PITCH_BEND_CENTER) as usize;
+  match span {
+    0 | 1 => PITCH_BEND_CENTER,
+    span => PITCH_BEND_CENTER + (offset.min(span - 1) * range / (span - 1)) as u16,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::regex::{EventData, RegExpHandler};
+
+  fn step(pattern: &str, text: &str, grid_index: usize) -> StepContext {
+    let matches = RegExpHandler::find_matches(&EventData {
+      text: text.to_string(),
+      pattern: pattern.to_string(),
+      flags: String::new(),
+      grid_width: 10,
+    })
+    .unwrap();
+    let matched = matches
+      .values()
+      .find(|matched| matched.offset_of(grid_index).is_some())
+      .unwrap();
+    StepContext::new(
+      matched,
+      matched.offset_of(grid_index).unwrap(),
+      (grid_index % 10, grid_index / 10),
+      (10, 5),
+      None,
+    )
+  }
+
+  #[test]
+  fn test_cc_values() {
+    let step = step(r"b(?P<v>[aeiou])?\w+", "a brown fox", 3);
+    let cc = |value| ChannelMsg::new(ChannelMsgKind::Cc(74, value), 1, None);
+
+    assert_eq!(cc(CcValue::X).to_bytes(&step), Some(vec![0xB1, 74, 42]));
+    assert_eq!(cc(CcValue::Y).to_bytes(&step), Some(vec![0xB1, 74, 127]));
+    assert_eq!(
+      cc(CcValue::Char).to_bytes(&step),
+      Some(vec![0xB1, 74, b'r'])
+    );
+    assert_eq!(
+      cc(CcValue::Fixed(200)).to_bytes(&step),
+      Some(vec![0xB1, 74, 127])
+    );
+
+    // `brown` has no vowel right after the `b`
+    let by_group = ChannelMsg::new(ChannelMsgKind::Cc(74, CcValue::Char), 0, Some("v".into()));
+    assert_eq!(by_group.to_bytes(&step), None);
+  }
+
+  #[test]
+  fn test_pitch_bend_and_program_change() {
+    assert_eq!(pitch_bend(0, 5), 0x2000);
+    assert_eq!(pitch_bend(4, 5), 0x3FFF);
+    assert_eq!(pitch_bend(3, 1), 0x2000);
+
+    let last_cell = step(r"brown", "a brown fox", 6);
+    let bend = ChannelMsg::new(ChannelMsgKind::PitchBend, 0, None);
+    assert_eq!(bend.to_bytes(&last_cell), Some(vec![0xE0, 0x7F, 0x7F]));
+
+    let program = ChannelMsg::new(ChannelMsgKind::ProgramChange(5), 2, None);
+    assert_eq!(program.to_bytes(&last_cell), None);
+    assert_eq!(
+      program.to_bytes(&step(r"brown", "a brown fox", 2)),
+      Some(vec![0xC2, 5])
+    );
+
+    // `pc5@marker` belongs to the marker instead
+    assert_eq!(program.marker_program(), None);
+    let marker_program =
+      ChannelMsg::new(ChannelMsgKind::ProgramChange(5), 2, Some("marker".into()));
+    assert_eq!(marker_program.marker_program(), Some((2, 5)));
+  }
+}
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Clone, Serialize, Deserialize, Debug, Copy)]
+use super::midi_learn::Param;
+
+#[derive(Clone, Serialize, Deserialize, Debug, Copy, PartialEq)]
 pub enum MoveDirection {
   Up,
   Down,
@@ -9,25 +11,61 @@ pub enum MoveDirection {
   Right,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, Copy)]
+#[derive(Clone, Serialize, Deserialize, Debug, Copy, PartialEq)]
 pub enum Adjustment {
   Increase,
   Decrease,
 }
 
-#[derive(Clone, Serialize, Deserialize, Debug, Copy)]
+impl MoveDirection {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Up => "up",
+      Self::Down => "down",
+      Self::Left => "left",
+      Self::Right => "right",
+    }
+  }
+}
+
+impl Adjustment {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Increase => "increase",
+      Self::Decrease => "decrease",
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Copy, PartialEq)]
 pub enum Command {
   Quit,
   TogglePlay,
+  ResetPosition,
   ShowMenubar,
   ToggleInputRegexAndCanvas,
   AdjustMarker(MoveDirection),
   AdjustBPM(Adjustment),
   AdjustRatio(Adjustment),
+  AdjustLoopLength(Adjustment),
+  PinRegion,
+  RemoveRegion,
+  AdjustSwing(Adjustment),
+  TapTempo,
+  StartTempoRamp,
+  CancelTempoRamp,
+  EditSignature,
   ToggleReverse,
   ToggleArpeggiator,
   ToggleAccumulation,
   ToggleRandom,
+  CycleNoteSource,
+  ToggleGateMode,
+  AdjustGate(Adjustment),
+  Panic,
+  SetParam(Param, u8), // (param, controller value), from a learned MIDI control
+  StartRecording,
+  StopRecording,
 }
 
 impl fmt::Display for Command {
@@ -38,13 +76,29 @@ impl fmt::Display for Command {
       | Self::ToggleInputRegexAndCanvas
       | Self::ShowMenubar
       | Self::TogglePlay
-      | Self::AdjustBPM(_)
-      | Self::AdjustRatio(_)
-      | Self::AdjustMarker(_)
+      | Self::ResetPosition
+      | Self::PinRegion
+      | Self::RemoveRegion
+      | Self::TapTempo
+      | Self::StartTempoRamp
+      | Self::CancelTempoRamp
+      | Self::EditSignature
       | Self::ToggleReverse
       | Self::ToggleArpeggiator
       | Self::ToggleAccumulation
-      | Self::ToggleRandom => vec![],
+      | Self::ToggleRandom
+      | Self::CycleNoteSource
+      | Self::ToggleGateMode
+      | Self::Panic
+      | Self::StartRecording
+      | Self::StopRecording => vec![],
+      Self::AdjustMarker(direction) => vec![direction.name().to_owned()],
+      Self::AdjustBPM(adjustment)
+      | Self::AdjustRatio(adjustment)
+      | Self::AdjustLoopLength(adjustment)
+      | Self::AdjustSwing(adjustment)
+      | Self::AdjustGate(adjustment) => vec![adjustment.name().to_owned()],
+      Self::SetParam(param, value) => vec![param.name().to_owned(), value.to_string()],
     };
     repr_tokens.append(&mut extras_args);
     write!(f, "{}", repr_tokens.join(" "))
@@ -56,15 +110,31 @@ impl Command {
     match self {
       Self::Quit => "quit",
       Self::TogglePlay => "playpause",
+      Self::ResetPosition => "resetposition",
       Self::ShowMenubar => "showmenubar",
       Self::ToggleInputRegexAndCanvas => "toggleinputregexandcanvas",
       Self::AdjustMarker(_) => "adjustmarker",
       Self::AdjustBPM(_) => "adjustbpm",
       Self::AdjustRatio(_) => "adjustratio",
+      Self::AdjustLoopLength(_) => "adjustlooplength",
+      Self::PinRegion => "pinregion",
+      Self::RemoveRegion => "removeregion",
+      Self::AdjustSwing(_) => "adjustswing",
+      Self::TapTempo => "taptempo",
+      Self::StartTempoRamp => "starttemporamp",
+      Self::CancelTempoRamp => "canceltemporamp",
+      Self::EditSignature => "editsignature",
       Self::ToggleReverse => "togglereverse",
       Self::ToggleArpeggiator => "togglearpeggiator",
       Self::ToggleAccumulation => "toggleaccumulation",
       Self::ToggleRandom => "togglerandom",
+      Self::CycleNoteSource => "cyclenotesource",
+      Self::ToggleGateMode => "togglegatemode",
+      Self::AdjustGate(_) => "adjustgate",
+      Self::Panic => "panic",
+      Self::SetParam(..) => "setparam",
+      Self::StartRecording => "startrecording",
+      Self::StopRecording => "stoprecording",
     }
   }
 }
@@ -4,7 +4,7 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use crate::app::UserData;
-use crate::view::common::playhead_controller;
+use crate::view::common::{menubar, playhead_controller};
 
 #[cfg(feature = "desktop")]
 use crate::view::desktop::app::Anu;
@@ -13,8 +13,11 @@ use crate::view::desktop::app::Anu;
 use crate::view::microcontroller::app::Anu;
 
 use super::command::{Adjustment, Command, MoveDirection};
+use super::midi_learn::{scale_value, Param};
+use super::scale::ScaleMode;
+use super::timing::clock::Signature;
 use super::timing::metronome::Message;
-use super::{consts, utils};
+use super::{consts, midi, utils};
 
 use cursive::event::{Event, Key};
 use cursive::views::{LinearLayout, TextView};
@@ -22,6 +25,26 @@ use cursive::Cursive;
 use log::error;
 use std::cell::RefCell;
 
+// step ratios, from long to short steps, with dotted and triplet values in between
+static RATIOS: [(i64, usize); 16] = [
+  (1, 1),
+  (1, 2),
+  (3, 8),
+  (1, 3),
+  (1, 4),
+  (3, 16),
+  (1, 6),
+  (1, 8),
+  (3, 32),
+  (1, 12),
+  (1, 16),
+  (3, 64),
+  (1, 24),
+  (1, 32),
+  (1, 48),
+  (1, 64),
+];
+
 pub struct CommandManager {
   aliases: HashMap<String, String>,
   bindings: RefCell<HashMap<String, Vec<Command>>>,
@@ -30,6 +53,8 @@ pub struct CommandManager {
   cb_sink: cursive::CbSink,
   temp_tempo: Arc<Mutex<i64>>,
   temp_ratio: Arc<Mutex<(i64, usize)>>,
+  temp_loop_length: Arc<Mutex<usize>>,
+  temp_signature: Arc<Mutex<Signature>>,
   pub last_key_time: Arc<Mutex<Option<Instant>>>,
   marker_tx_cloned: Sender<playhead_controller::Message>,
 }
@@ -52,6 +77,8 @@ impl CommandManager {
       cb_sink,
       temp_tempo,
       temp_ratio: Arc::new(Mutex::new((1, 16))),
+      temp_loop_length: Arc::new(Mutex::new(0)),
+      temp_signature: Arc::new(Mutex::new(Signature::default())),
       last_key_time,
       marker_tx_cloned,
     }
@@ -80,11 +107,20 @@ impl CommandManager {
     cmd: &Command,
   ) -> Result<Option<String>, String> {
     match cmd {
-      Command::Quit => Ok(None),
+      Command::Quit => {
+        // outputs are silenced on the way out, see `main`
+        s.quit();
+        Ok(None)
+      }
       Command::TogglePlay => {
         let _ = self.metronome_sender.send(Message::StartStop);
         Ok(None)
       }
+      Command::ResetPosition => {
+        // back to the downbeat, clock followers get a song position of 0
+        let _ = self.metronome_sender.send(Message::Reset);
+        Ok(None)
+      }
       Command::ShowMenubar => {
         s.select_menubar();
         Ok(None)
@@ -130,41 +166,22 @@ impl CommandManager {
           Adjustment::Decrease => -1,
         };
 
-        let mut last_press = self.last_key_time.lock().unwrap();
-        *last_press = Some(Instant::now());
-
-        let mut tempo = self.temp_tempo.lock().unwrap();
-        *tempo += nudge;
-        let temp = *tempo as usize;
-
-        self
-          .marker_tx_cloned
-          .send(playhead_controller::Message::SetTempo(temp))
-          .unwrap();
-
-        self
-          .cb_sink
-          .send(Box::new(move |s| {
-            s.call_on_name(consts::bpm_status_unit_view, |view: &mut TextView| {
-              view.set_content(utils::build_bpm_status_str(temp));
-            })
-            .unwrap();
-          }))
-          .unwrap();
+        let tempo = *self.temp_tempo.lock().unwrap() + nudge;
+        self.set_tempo(tempo);
 
         Ok(None)
       }
       Command::AdjustRatio(direction) => {
-        let ratios = [1, 2, 4, 8, 16, 32, 64];
-
         let current_ratio = *self.temp_ratio.lock().unwrap();
-        let current_denom = current_ratio.1;
 
-        let current_idx = ratios.iter().position(|&d| d == current_denom).unwrap_or(4); // Default to 16 if not found
+        let current_idx = RATIOS
+          .iter()
+          .position(|&r| r == current_ratio)
+          .unwrap_or(10); // Default to 1/16 if not found
 
         let new_idx = match direction {
           Adjustment::Increase => {
-            if current_idx < ratios.len() - 1 {
+            if current_idx < RATIOS.len() - 1 {
               current_idx + 1
             } else {
               current_idx
@@ -179,19 +196,85 @@ impl CommandManager {
           }
         };
 
-        let new_ratio = (1, ratios[new_idx]);
+        self.set_ratio(RATIOS[new_idx]);
 
-        let mut ratio = self.temp_ratio.lock().unwrap();
-        *ratio = new_ratio;
-        drop(ratio);
+        Ok(None)
+      }
+      Command::AdjustLoopLength(direction) => {
+        // 0 follows the marker area, anything else restarts the marker every that many steps
+        let mut loop_length = self.temp_loop_length.lock().unwrap();
+        *loop_length = match direction {
+          Adjustment::Increase => (*loop_length + 1).min(consts::MAX_LOOP_LENGTH),
+          Adjustment::Decrease => loop_length.saturating_sub(1),
+        };
+        let new_loop_length = *loop_length;
+        drop(loop_length);
 
         self
           .marker_tx_cloned
-          .send(playhead_controller::Message::SetRatio(new_ratio))
+          .send(playhead_controller::Message::SetLoopLength(new_loop_length))
           .unwrap();
 
         Ok(None)
       }
+      Command::PinRegion => {
+        self
+          .marker_tx_cloned
+          .send(playhead_controller::Message::PinRegion())
+          .unwrap();
+        Ok(None)
+      }
+      Command::RemoveRegion => {
+        self
+          .marker_tx_cloned
+          .send(playhead_controller::Message::RemoveRegion())
+          .unwrap();
+        Ok(None)
+      }
+      Command::AdjustSwing(direction) => {
+        let nudge = match direction {
+          Adjustment::Increase => 1,
+          Adjustment::Decrease => -1,
+        };
+
+        let _ = self.metronome_sender.send(Message::NudgeSwing(nudge));
+        Ok(None)
+      }
+      Command::TapTempo => {
+        // the resulting tempo comes back through the metronome, like the BPM nudges
+        let _ = self.metronome_sender.send(Message::Tap);
+        Ok(None)
+      }
+      Command::StartTempoRamp => {
+        let tempo = *self.temp_tempo.lock().unwrap();
+        let metronome_sender = self.metronome_sender.clone();
+
+        s.add_layer(menubar::build_ramp_view(
+          tempo,
+          move |_, target, bars, curve| {
+            let _ = metronome_sender.send(Message::StartRamp(target, bars, curve));
+          },
+        ));
+        Ok(None)
+      }
+      Command::CancelTempoRamp => {
+        let _ = self.metronome_sender.send(Message::CancelRamp);
+        Ok(None)
+      }
+      Command::EditSignature => {
+        let signature = *self.temp_signature.lock().unwrap();
+        let temp_signature = Arc::clone(&self.temp_signature);
+        let metronome_sender = self.metronome_sender.clone();
+
+        s.add_layer(menubar::build_signature_view(
+          signature,
+          move |_, new_signature| {
+            *temp_signature.lock().unwrap() = new_signature;
+            let _ = metronome_sender.send(Message::Signature(new_signature));
+          },
+        ));
+        Ok(None)
+      }
       Command::ToggleReverse => {
         self
           .marker_tx_cloned
@@ -220,6 +303,112 @@ impl CommandManager {
           .unwrap();
         Ok(None)
       }
+      Command::CycleNoteSource => {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::CycleNoteSource());
+        }
+        Ok(None)
+      }
+      Command::ToggleGateMode => {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::ToggleGateMode());
+        }
+        Ok(None)
+      }
+      Command::AdjustGate(direction) => {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::AdjustGate(*direction));
+        }
+        Ok(None)
+      }
+      Command::Panic => {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::Panic());
+        }
+        Ok(None)
+      }
+      Command::StartRecording => {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::StartRecording());
+        }
+        Ok(None)
+      }
+      Command::StopRecording => {
+        // the file name is asked for once the take is stopped
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::StopRecording());
+        }
+        Ok(None)
+      }
+      Command::SetParam(param, value) => {
+        self.set_param(s, *param, *value);
+        Ok(None)
+      }
+    }
+  }
+
+  // the metronome picks the tempo up once it settles, see `spawn_tempo_monitor_thread`
+  fn set_tempo(&self, tempo: i64) {
+    *self.last_key_time.lock().unwrap() = Some(Instant::now());
+    *self.temp_tempo.lock().unwrap() = tempo;
+    let temp = tempo as usize;
+
+    self
+      .marker_tx_cloned
+      .send(playhead_controller::Message::SetTempo(temp))
+      .unwrap();
+
+    self
+      .cb_sink
+      .send(Box::new(move |s| {
+        s.call_on_name(consts::bpm_status_unit_view, |view: &mut TextView| {
+          view.set_content(utils::build_bpm_status_str(temp));
+        })
+        .unwrap();
+      }))
+      .unwrap();
+  }
+
+  fn set_ratio(&self, ratio: (i64, usize)) {
+    *self.temp_ratio.lock().unwrap() = ratio;
+
+    self
+      .marker_tx_cloned
+      .send(playhead_controller::Message::SetRatio(ratio))
+      .unwrap();
+  }
+
+  /// Sweep `param` through its range with a 0-127 controller value
+  fn set_param(&self, s: &mut Cursive, param: Param, value: u8) {
+    match param {
+      Param::Bpm => {
+        let tempo = scale_value(value, consts::MIN_LEARNED_BPM, consts::MAX_LEARNED_BPM);
+        self.set_tempo(tempo as i64);
+      }
+      Param::Ratio => {
+        self.set_ratio(RATIOS[scale_value(value, 0, RATIOS.len() - 1)]);
+      }
+      Param::MarkerX | Param::MarkerY | Param::MarkerW | Param::MarkerH => {
+        self
+          .marker_tx_cloned
+          .send(playhead_controller::Message::SetMarkerParam(param, value))
+          .unwrap();
+      }
+      Param::ScaleLeft | Param::ScaleTop => {
+        let scale_modes = ScaleMode::all();
+        let scale_mode = scale_modes[scale_value(value, 0, scale_modes.len() - 1)];
+        let msg = match param {
+          Param::ScaleLeft => playhead_controller::Message::SetScaleModeLeft(scale_mode),
+          _ => playhead_controller::Message::SetScaleModeTop(scale_mode),
+        };
+        self.marker_tx_cloned.send(msg).unwrap();
+      }
+      Param::Gate => {
+        let gate_percent = scale_value(value, consts::MIN_GATE_PERCENT, consts::MAX_GATE_PERCENT);
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          let _ = data.midi_tx.send(midi::Message::SetGate(gate_percent));
+        }
+      }
     }
   }
 
@@ -302,10 +491,34 @@ impl CommandManager {
     kb.insert("<".into(), vec![Command::AdjustBPM(Adjustment::Decrease)]);
     kb.insert("}".into(), vec![Command::AdjustRatio(Adjustment::Increase)]);
     kb.insert("{".into(), vec![Command::AdjustRatio(Adjustment::Decrease)]);
+    kb.insert(
+      "]".into(),
+      vec![Command::AdjustLoopLength(Adjustment::Increase)],
+    );
+    kb.insert(
+      "[".into(),
+      vec![Command::AdjustLoopLength(Adjustment::Decrease)],
+    );
+    kb.insert("n".into(), vec![Command::PinRegion]);
+    kb.insert("Backspace".into(), vec![Command::RemoveRegion]);
+    kb.insert(")".into(), vec![Command::AdjustSwing(Adjustment::Increase)]);
+    kb.insert("(".into(), vec![Command::AdjustSwing(Adjustment::Decrease)]);
+    kb.insert("t".into(), vec![Command::TapTempo]);
+    kb.insert("Home".into(), vec![Command::ResetPosition]);
+    kb.insert("Ctrl+t".into(), vec![Command::EditSignature]);
+    kb.insert("Ctrl+p".into(), vec![Command::StartTempoRamp]);
+    kb.insert("Ctrl+k".into(), vec![Command::CancelTempoRamp]);
     kb.insert("Ctrl+r".into(), vec![Command::ToggleReverse]);
     kb.insert("Ctrl+a".into(), vec![Command::ToggleArpeggiator]);
     kb.insert("Ctrl+u".into(), vec![Command::ToggleAccumulation]);
     kb.insert("Ctrl+d".into(), vec![Command::ToggleRandom]);
+    kb.insert("Ctrl+n".into(), vec![Command::CycleNoteSource]);
+    kb.insert("Ctrl+g".into(), vec![Command::ToggleGateMode]);
+    kb.insert("+".into(), vec![Command::AdjustGate(Adjustment::Increase)]);
+    kb.insert("-".into(), vec![Command::AdjustGate(Adjustment::Decrease)]);
+    kb.insert("!".into(), vec![Command::Panic]);
+    kb.insert("Ctrl+o".into(), vec![Command::StartRecording]);
+    kb.insert("Ctrl+x".into(), vec![Command::StopRecording]);
 
     kb
   }
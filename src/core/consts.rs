@@ -13,7 +13,7 @@ lazy_static! {
     ("SonicPi", 4559),
   ]);
   pub static ref APP_DOCS: StaticStrStr = Vec::from([
-    ("n", "add new marker"),
+    ("n", "pin marker as a region (own ratio/loop length)"),
     ("f", "focus only marker(s)"),
     ("r", "[*] reverse step"),
     ("e", "[*] rename marker"),
@@ -23,15 +23,26 @@ lazy_static! {
     ("x", "[*] mute"),
     ("'", "[*] replace marker block"),
     ("> | <", "incr/decr BPM "),
-    ("[ | ]", "[*] incr/decr note-ratio (default 1/16)"),
-    ("{ | }", "[*] incr/decr note-ratio for ratcheting"),
+    ("{ | }", "[*] incr/decr note-ratio (default 1/16)"),
+    ("[ | ]", "[*] incr/decr marker loop length (steps)"),
+    (") | (", "incr/decr swing"),
+    ("t", "tap tempo"),
+    ("Ctrl-t", "set time signature"),
+    ("Ctrl-p | Ctrl-k", "start/cancel tempo ramp"),
     ("?", "[*] show control informations"),
     (";", "toggle mono-step mode"),
-    ("Backspace", "[*] remove current marker"),
+    ("Ctrl-n", "cycle note source (scale/cycle/chord)"),
+    ("Ctrl-g", "toggle gate mode (match length = note length)"),
+    ("+ | -", "incr/decr gate percentage"),
+    ("Backspace", "remove last pinned region"),
     ("Spacebar", "play/pause"),
+    ("Home", "back to the first beat (song position 0)"),
+    ("!", "panic (all notes off)"),
+    ("Ctrl-o | Ctrl-x", "start/stop recording to a .mid file"),
     ("Cmd-Arrow", "[*] jump"),
     ("Cmd-(1..6)", "toggle regex flag respectively"),
     ("Cmd-/", "switch regex mode"),
+    ("(?P<vel|note|len>..)", "named groups set velocity/pitch/length"),
     ("Option-Tab", "change selected markers"),
     ("Shift-Arrow", "[*] incr/decr marker range"),
     ("Shift-Arrow-Cmd", "[*] jump incr/decr marker range"),
@@ -53,6 +64,12 @@ pub static APP_NAME: &str = "
 
 pub static DEFAULT_APP_DIRECTORY: &str = ".anupars";
 pub static DEFAULT_APP_FILENAME: &str = "contents";
+pub static DEFAULT_GROOVE_DIRNAME: &str = "grooves";
+pub static DEFAULT_TUNING_DIRNAME: &str = "tunings";
+pub static DEFAULT_TEMPO_LANE_FILENAME: &str = "tempo.lane";
+pub static DEFAULT_MIDI_MAP_FILENAME: &str = "midi.map";
+pub static DEFAULT_RECORDING_DIRNAME: &str = "recordings";
+pub static DEFAULT_RECORDING_FILENAME: &str = "performance.mid";
 
 // workaround since `format!` cannot be calculated at build-time (eg. for `static` or `const`)
 // https://users.rust-lang.org/t/how-to-avoid-recalculating-a-formatted-string-at-runtime/44895
@@ -78,12 +95,19 @@ pub static regex_input_unit_view: &str = "regex_input_unit_view";
 pub static input_status_unit_view: &str = "input_status_unit_view";
 pub static bpm_status_unit_view: &str = "bpm_status_unit_view";
 pub static ratio_status_unit_view: &str = "ratio_status_unit_view";
+pub static swing_status_unit_view: &str = "swing_status_unit_view";
+pub static bbt_status_unit_view: &str = "bbt_status_unit_view";
 pub static len_status_unit_view: &str = "len_status_unit_view";
 pub static pos_status_unit_view: &str = "pos_status_unit_view";
 pub static osc_status_unit_view: &str = "osc_status_unit_view";
 pub static midi_status_unit_view: &str = "midi_status_unit_view";
 pub static op_queue_status_unit_view: &str = "op_queue_status_unit_view";
 pub static ev_queue_status_unit_view: &str = "ev_queue_status_unit_view";
+pub static launch_status_unit_view: &str = "launch_status_unit_view";
+pub static ramp_status_unit_view: &str = "ramp_status_unit_view";
+pub static note_status_unit_view: &str = "note_status_unit_view";
+pub static gate_status_unit_view: &str = "gate_status_unit_view";
+pub static record_status_unit_view: &str = "record_status_unit_view";
 
 pub static input_controller_section_view: &str = "input_controller_section_view";
 pub static status_controller_section_view: &str = "status_controller_section_view";
@@ -102,6 +126,7 @@ pub static file_explorer_unit_view: &str = "file_explorer_unit_view";
 pub static file_contents_unit_view: &str = "file_contents_unit_view";
 
 pub static main_section_view: &str = "main_section_view";
+pub static midi_learn_section_view: &str = "midi_learn_section_view";
 
 // alias for `regex_display_unit_view`
 pub static display_view: &str = "display_view";
@@ -114,6 +139,26 @@ pub static GRID_COL_SPACING: usize = 9;
 pub const TEMPO_CHECK_INTERVAL_MS: u64 = 100;
 pub const TEMPO_RESET_DELAY_MS: u64 = 500;
 pub const DEFAULT_TEMPO: i64 = 120;
+pub const DEFAULT_SWING: i64 = 50;
+pub const MAX_LOOP_LENGTH: usize = 64; // marker loop length, in steps
+pub const MAX_REGIONS: usize = 8; // markers pinned in place, besides the one being edited
+
+// MIDI constants
+pub const DEFAULT_VELOCITY: u8 = 100;
+pub const MIDI_RESCAN_INTERVAL_MS: u64 = 2000;
+pub const NO_MIDI_OUTPUT_LABEL: &str = "none (silent)";
+pub const DEFAULT_VIRTUAL_PORT_NAME: &str = "anupars";
+pub const DEFAULT_GATE_PERCENT: usize = 100;
+pub const MIN_GATE_PERCENT: usize = 5;
+pub const MAX_GATE_PERCENT: usize = 200;
+pub const GATE_PERCENT_STEP: usize = 5;
+pub const MIN_LEARNED_BPM: usize = 40; // range a learned knob sweeps the tempo through
+pub const MAX_LEARNED_BPM: usize = 240;
+pub const MAX_MPE_MEMBERS: u8 = 15; // member channels of a zone, the master channel is the 16th
+pub const DEFAULT_MPE_PITCH_BEND_RANGE: u8 = 2; // semitones a note bends up to across its match
+pub const DEFAULT_PITCH_BEND_RANGE: u8 = 2; // semitones, the General MIDI default outside MPE
+pub const PITCH_BEND_RETUNE_WITHOUT_MPE_WARNING: &str =
+  "Retune: Pitch Bend bends the whole channel, notes still sounding are detuned too.\nTurn on MIDI > MPE to give every note a channel of its own.";
 
 // Keyboard visualization constants
 pub const KEYBOARD_MARGIN_TOP: usize = 3;
@@ -1,21 +1,43 @@
-use midir::{MidiOutput, MidiOutputConnection, MidiOutputPort};
-use std::collections::HashMap;
+use cursive::views::TextView;
+use midir::MidiOutput;
+use num::rational::Ratio;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
-use super::stack::{self, Stack};
-use super::utils::Throttler;
-use crate::core::consts::BASE_OCTAVE;
+use super::channel_msg::{pitch_bend, pitch_bend_msg, ChannelMsg, ChannelMsgKind, StepContext};
+use super::command::Adjustment;
+use super::midi_output::{self, Output};
+use super::mpe::{self, ChannelRotation, Zone};
+use super::recorder::{self, Recorder};
+use super::regex::NoteParams;
+use super::smf::Smf;
+use super::stack::Stack;
+use super::timing::clock::{Signature, Tick};
+use super::timing::groove;
+use super::transpose;
+use super::tuning::{self, RetuneMode, Tuning};
+use super::utils::{self, Throttler};
+use crate::core::consts::{self, BASE_OCTAVE, DEFAULT_VELOCITY};
+use crate::view::common::menubar::{self, MidiMenuState};
 
 #[derive(Clone, Debug)]
 pub enum Message {
-  Push(MidiMsg),
-  Trigger(MidiMsg, bool),
-  SetMsgConfig(MidiMsg), // ? maybe obsolete, TBD
-  ClearMsgConfig(),
+  Tick(usize),
+  FlushNotes(),
+  SetMsgConfig(MidiMsg),
+  SetControlConfig(ChannelMsg),
+  ClearMsgConfig(), // notes and controls
+  CycleNoteSource(),
+  ToggleGateMode(),
+  AdjustGate(Adjustment),
+  SetGate(usize), // percent
+  SetRoot(u8),
   TriggerWithPosition(
     (
       usize,
@@ -24,11 +46,93 @@ pub enum Message {
       usize,
       crate::core::scale::ScaleMode,
       usize,
+      f32,
+      NoteParams,
+      Tick,
     ),
-  ), // (grid_index, y_position, grid_width, grid_height, scale_mode, bpm)
-  SwitchDevice(usize),
+  ), // (grid_index, y_position, grid_width, grid_height, scale_mode, bpm, velocity_scale, note_params, step_ticks)
+  TriggerControls(StepContext), // every step inside a match
+  ReleaseControls(),            // the playhead left a match
+  SetMpe(Option<Zone>),         // MPE output over a zone, or off
+  SetTuning(Option<Tuning>),    // a Scala tuning for the scale notes, or back to 12-TET
+  SetRetuneMode(RetuneMode),
+  ToggleDevice(String),
+  ToggleVirtualPort(),
+  SetVirtualPortName(String),
+  ConfigureOutput(String, bool, Option<u8>), // (port_name, enabled, channel)
+  RescanDevices(),
   Panic(),
   SetTempo(usize),
+  SetSignature(Signature),
+  StartRecording(),
+  StopRecording(), // stops the take and asks where to save it
+  SaveRecording(PathBuf),
+  Clock(ClockMsg),
+  ToggleClockOut(String),
+}
+
+/// MIDI real-time/system-common messages emitted by the internal clock
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockMsg {
+  Pulse,
+  Start,
+  Continue,
+  Stop,
+  SongPosition(u16), // in MIDI beats (sixteenth notes)
+}
+
+impl ClockMsg {
+  pub fn to_bytes(self) -> Vec<u8> {
+    match self {
+      ClockMsg::Pulse => vec![0xF8],
+      ClockMsg::Start => vec![0xFA],
+      ClockMsg::Continue => vec![0xFB],
+      ClockMsg::Stop => vec![0xFC],
+      ClockMsg::SongPosition(pos) => {
+        vec![0xF2, (pos & 0x7F) as u8, ((pos >> 7) & 0x7F) as u8]
+      }
+    }
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+    match bytes {
+      [0xF8, ..] => Some(ClockMsg::Pulse),
+      [0xFA, ..] => Some(ClockMsg::Start),
+      [0xFB, ..] => Some(ClockMsg::Continue),
+      [0xFC, ..] => Some(ClockMsg::Stop),
+      [0xF2, lsb, msb, ..] => Some(ClockMsg::SongPosition(
+        (*lsb as u16 & 0x7F) | ((*msb as u16 & 0x7F) << 7),
+      )),
+      _ => None,
+    }
+  }
+}
+
+/// What a matched step plays
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NoteSource {
+  #[default]
+  Scale, // the note under the playhead, mapped through the scale
+  Cycle, // the configured messages, one per step
+  Chord, // every configured message at once
+}
+
+impl NoteSource {
+  pub fn next(self) -> Self {
+    match self {
+      NoteSource::Scale => NoteSource::Cycle,
+      NoteSource::Cycle => NoteSource::Chord,
+      NoteSource::Chord => NoteSource::Scale,
+    }
+  }
+
+  pub fn name(self) -> &'static str {
+    match self {
+      NoteSource::Scale => "scale",
+      NoteSource::Cycle => "cycle",
+      NoteSource::Chord => "chord",
+    }
+  }
 }
 
 #[derive(Clone, Debug)]
@@ -37,7 +141,7 @@ pub struct MidiMsg {
   velocity: u8,
   octave: u8,
   channel: u8,
-  pub length: u8,
+  pub length: u8, // in steps, see `gate_ticks`
   pub is_played: bool,
 }
 
@@ -59,143 +163,352 @@ impl MidiMsg {
       is_played,
     }
   }
+
+  /// Notes sharing a key cannot overlap on the receiving side
+  pub fn key(&self) -> (u8, u8) {
+    (
+      self.channel,
+      convert_to_midi_note_num(self.octave, self.note),
+    )
+  }
 }
 
 pub struct Midi {
   pub midi: Mutex<Option<MidiOutput>>,
   pub devices: Mutex<HashMap<String, String>>,
-  pub out_device: Mutex<Option<MidiOutputConnection>>,
-  pub out_device_name: Mutex<Option<String>>,
+  pub outputs: Arc<Mutex<Vec<Output>>>,
+  // ports picked by the user, kept across unplugging so they are picked up again once back
+  selected_ports: Mutex<Vec<String>>,
+  known_ports: Mutex<Vec<String>>,
+  virtual_port_name: Mutex<String>,
+  // the virtual port was only opened because nothing else was connected
+  virtual_is_fallback: AtomicBool,
   pub msg_config_list: Arc<Mutex<Vec<MidiMsg>>>,
+  control_config_list: Mutex<Vec<ChannelMsg>>,
+  programs: Mutex<HashMap<u8, u8>>, // last program sent per channel
+  // read by the marker, which only looks up the match under the playhead when something uses it
+  sends_controls: Arc<AtomicBool>,
+  mpe: Mutex<Option<Zone>>,
+  rotation: Mutex<ChannelRotation>,
+  mpe_voices: Mutex<Vec<(usize, u8, i16)>>, // (grid index the match starts on, member channel, detune bend)
+  tuning: Mutex<Option<Tuning>>,
+  retune_mode: Mutex<RetuneMode>,
+  note_source: Mutex<NoteSource>,
+  msg_config_cursor: AtomicUsize, // next message to play in `NoteSource::Cycle`
+  gate_mode: AtomicBool,          // hold notes for as many steps as their match spans
+  gate_percent: AtomicUsize,
+  current_tick: AtomicUsize,
+  root: AtomicU8, // transpose from the MIDI keyboard, in semitones
+  pub clock_out_ports: Mutex<HashSet<String>>,
   pub tx: Sender<Message>,
   pub rx: Receiver<Message>,
   throttler: Arc<Mutex<Throttler>>,
   tempo: Arc<Mutex<usize>>,
+  signature: Mutex<Signature>,
+  recorder: Mutex<Option<Recorder>>, // the take being recorded, or stopped and not saved yet
+  stack: Stack,
+  cb_sink: cursive::CbSink,
 }
 
 impl Midi {
-  pub fn new() -> Self {
+  pub fn new(cb_sink: cursive::CbSink) -> Self {
+    Self::with_output(MidiOutput::new("client-midi-output").ok(), cb_sink)
+  }
+
+  /// Without a MIDI client, for the offline render where messages only go to the recorder
+  pub fn offline(cb_sink: cursive::CbSink) -> Self {
+    Self::with_output(None, cb_sink)
+  }
+
+  fn with_output(midi_out: Option<MidiOutput>, cb_sink: cursive::CbSink) -> Self {
     let (tx, rx) = channel();
     let throttler = Arc::new(Mutex::new(Throttler::new(Duration::from_millis(100))));
     let tempo = Arc::new(Mutex::new(120));
-    let Ok(midi_out) = MidiOutput::new("client-midi-output") else {
-      return Self {
-        midi: None.into(),
-        devices: HashMap::new().into(),
-        out_device: None.into(),
-        out_device_name: None.into(),
-        tx,
-        rx,
-        msg_config_list: Arc::new(Mutex::new(Vec::new())),
-        throttler,
-        tempo,
-      };
-    };
     Midi {
-      midi: Some(midi_out).into(),
+      midi: midi_out.into(),
       devices: HashMap::new().into(),
-      out_device: None.into(),
-      out_device_name: None.into(),
+      outputs: Arc::new(Mutex::new(Vec::new())),
+      selected_ports: Vec::new().into(),
+      known_ports: Vec::new().into(),
+      virtual_port_name: consts::DEFAULT_VIRTUAL_PORT_NAME.to_string().into(),
+      virtual_is_fallback: AtomicBool::new(false),
       tx,
       rx,
       msg_config_list: Arc::new(Mutex::new(Vec::new())),
+      control_config_list: Vec::new().into(),
+      programs: HashMap::new().into(),
+      sends_controls: Arc::new(AtomicBool::new(false)),
+      mpe: None.into(),
+      rotation: ChannelRotation::new().into(),
+      mpe_voices: Vec::new().into(),
+      tuning: None.into(),
+      retune_mode: RetuneMode::default().into(),
+      note_source: NoteSource::default().into(),
+      msg_config_cursor: AtomicUsize::new(0),
+      gate_mode: AtomicBool::new(false),
+      gate_percent: AtomicUsize::new(consts::DEFAULT_GATE_PERCENT),
+      current_tick: AtomicUsize::new(0),
+      root: AtomicU8::new(0),
+      clock_out_ports: HashSet::new().into(),
       throttler,
       tempo,
+      signature: Signature::default().into(),
+      recorder: None.into(),
+      stack: Stack::new(),
+      cb_sink,
     }
   }
 }
 
 impl Midi {
   pub fn init(&mut self) -> Result<(), Box<dyn Error>> {
-    let midi_out = MidiOutput::new("MIDI Output")?;
+    let devices = self.get_available_devices();
+    *self.known_ports.lock().unwrap() = devices.iter().map(|(name, _)| name.clone()).collect();
 
-    let out_ports = midi_out.ports();
-    let out_port: &MidiOutputPort = match out_ports.len() {
-      0 => return Err("no output port found".into()),
-      1 => {
-        println!(
-          "Choosing the only available output port: {}",
-          midi_out.port_name(&out_ports[0]).unwrap()
-        );
-        &out_ports[0]
-      }
-      _ => {
-        println!("\nAvailable output ports:");
-        for (i, p) in out_ports.iter().enumerate() {
-          println!("{}: {}", i, midi_out.port_name(p).unwrap());
-        }
-        // print!("Please select output port: ");
-        // stdout().flush()?;
-        let input = String::from("0");
-        // stdin().read_line(&mut input)?;
-        out_ports
-          .get(input.trim().parse::<usize>()?)
-          .ok_or("invalid output port selected")?
-      }
+    // a through port only loops back into the system, the virtual port is the better default
+    let connected = match devices.iter().find(|(name, _)| !is_through_port(name)) {
+      Some((name, _)) => self.connect(name),
+      None => Ok(()),
     };
-
-    let conn_out_name = &midi_out.port_name(out_port).unwrap();
-    let conn_out = midi_out.connect(out_port, "midir-test")?;
-    self.out_device = Mutex::new(Some(conn_out));
-    self.out_device_name = Mutex::new(Some(conn_out_name.to_string()));
-    Ok(())
+    self.update_fallback();
+    connected
   }
 
   pub fn run(self) {
-    let midi_tx_1 = self.tx.clone();
-    let midi_tx_2 = self.tx.clone();
-    let stack = Arc::new(Stack::new());
-    let stack_clone_2 = Arc::clone(&stack);
-    let stack_tx = stack.run(midi_tx_1);
-    stack_clone_2.refresh(midi_tx_2);
+    Self::spawn_device_watcher(self.tx.clone());
 
     thread::spawn(move || {
       for control_message in &self.rx {
-        match control_message {
-          Message::Push(midi_msg) => {
-            let _ = stack_tx.send(stack::Message::Push(midi_msg));
-          }
-          Message::Trigger(msg, is_pressed) => {
-            self.trigger(&msg, is_pressed).unwrap();
-          }
-          Message::SetMsgConfig(msg) => {
-            self.set_msg_config_list(msg);
-          }
-          Message::ClearMsgConfig() => {
-            self.clear_msg_config_list();
-          }
-          Message::TriggerWithPosition((
-            grid_index,
-            y_position,
-            grid_width,
-            grid_height,
-            scale_mode,
-            bpm,
-          )) => {
-            self.trigger_w_position(
-              grid_index,
-              y_position,
-              grid_width,
-              grid_height,
-              scale_mode,
-              bpm,
-            );
-          }
-          Message::SetTempo(bpm) => {
-            let mut tempo = self.tempo.lock().unwrap();
-            *tempo = bpm;
+        self.handle(control_message);
+      }
+    });
+  }
+
+  /// Act on one control message, `run` feeds it from the channel and the offline render calls it directly
+  pub fn handle(&self, control_message: Message) {
+    match control_message {
+      Message::Tick(tick) => {
+        self.current_tick.store(tick, Ordering::Relaxed);
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+          recorder.advance(tick);
+        }
+        for note in self.stack.release_until(tick) {
+          let _ = self.trigger(&note, false);
+        }
+      }
+      Message::FlushNotes() => {
+        self.flush_notes();
+      }
+      Message::SetMsgConfig(msg) => {
+        self.set_msg_config_list(msg);
+        self.publish_note_source();
+      }
+      Message::SetControlConfig(msg) => {
+        self.control_config_list.lock().unwrap().push(msg);
+        self.update_sends_controls();
+        self.publish_note_source();
+      }
+      Message::ClearMsgConfig() => {
+        self.clear_msg_config_list();
+        self.update_sends_controls();
+        self.publish_note_source();
+      }
+      Message::CycleNoteSource() => {
+        let mut note_source = self.note_source.lock().unwrap();
+        *note_source = note_source.next();
+        drop(note_source);
+        self.msg_config_cursor.store(0, Ordering::Relaxed);
+        self.publish_note_source();
+      }
+      Message::SetRoot(root) => {
+        self.root.store(root, Ordering::Relaxed);
+      }
+      Message::ToggleGateMode() => {
+        self.gate_mode.fetch_xor(true, Ordering::Relaxed);
+        self.publish_gate();
+      }
+      Message::AdjustGate(direction) => {
+        let gate_percent = self.gate_percent.load(Ordering::Relaxed);
+        let gate_percent = match direction {
+          Adjustment::Increase => gate_percent + consts::GATE_PERCENT_STEP,
+          Adjustment::Decrease => gate_percent.saturating_sub(consts::GATE_PERCENT_STEP),
+        };
+        self.gate_percent.store(
+          gate_percent.clamp(consts::MIN_GATE_PERCENT, consts::MAX_GATE_PERCENT),
+          Ordering::Relaxed,
+        );
+        self.publish_gate();
+      }
+      Message::SetGate(gate_percent) => {
+        self.gate_percent.store(
+          gate_percent.clamp(consts::MIN_GATE_PERCENT, consts::MAX_GATE_PERCENT),
+          Ordering::Relaxed,
+        );
+        self.publish_gate();
+      }
+      Message::TriggerWithPosition((
+        grid_index,
+        y_position,
+        grid_width,
+        grid_height,
+        scale_mode,
+        bpm,
+        velocity_scale,
+        note_params,
+        step_ticks,
+      )) => {
+        self.trigger_w_position(
+          grid_index,
+          y_position,
+          grid_width,
+          grid_height,
+          scale_mode,
+          bpm,
+          velocity_scale,
+          note_params,
+          step_ticks,
+        );
+      }
+      Message::TriggerControls(step) => {
+        self.trigger_controls(&step);
+        self.trigger_expression(&step);
+      }
+      Message::ReleaseControls() => {
+        self.release_controls();
+      }
+      Message::SetMpe(zone) => {
+        self.set_mpe(zone);
+        self.update_sends_controls();
+        self.publish_devices();
+      }
+      Message::SetTuning(tuning) => {
+        let retune_mode = *self.retune_mode.lock().unwrap();
+        self.set_tuning(tuning, retune_mode);
+      }
+      Message::SetRetuneMode(retune_mode) => {
+        let tuning = self.tuning.lock().unwrap().clone();
+        self.set_tuning(tuning, retune_mode);
+      }
+      Message::SetTempo(bpm) => {
+        let mut tempo = self.tempo.lock().unwrap();
+        *tempo = bpm;
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+          recorder.set_tempo(bpm);
+        }
+      }
+      Message::SetSignature(signature) => {
+        *self.signature.lock().unwrap() = signature;
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+          recorder.set_signature(signature);
+        }
+      }
+      Message::StartRecording() => {
+        // an unsaved take is dropped
+        let signature = *self.signature.lock().unwrap();
+        let tempo = *self.tempo.lock().unwrap();
+        let mut recorder = Recorder::new(signature, tempo);
+        recorder.advance(self.current_tick.load(Ordering::Relaxed));
+        *self.recorder.lock().unwrap() = Some(recorder);
+        self.publish_recording();
+      }
+      Message::StopRecording() => {
+        let mut recorder = self.recorder.lock().unwrap();
+        let Some(recorder) = recorder.as_mut() else {
+          return;
+        };
+        recorder.stop();
+        self.publish_recording_locked(Some(recorder));
+
+        let tx = self.tx.clone();
+        let _ = self.cb_sink.send(Box::new(move |siv| {
+          siv.add_layer(menubar::build_recording_view(
+            consts::DEFAULT_RECORDING_FILENAME,
+            move |_, name| {
+              let _ = tx.send(Message::SaveRecording(recorder::recording_path(&name)));
+            },
+          ));
+        }));
+      }
+      Message::SaveRecording(path) => {
+        let mut recorder = self.recorder.lock().unwrap();
+        let Some(smf) = recorder.as_ref().map(Recorder::to_smf) else {
+          return;
+        };
+        match smf.save(&path) {
+          Ok(()) => {
+            *recorder = None;
+            self.publish_recording_locked(None);
           }
-          Message::SwitchDevice(port_index) => {
-            if let Err(e) = self.switch_device(port_index) {
-              eprintln!("Error switching MIDI device: {}", e);
-            }
+          // the take is kept, stopping again asks for another name
+          Err(e) => {
+            let error = format!("could not save {}: {}", path.display(), e);
+            let _ = self.cb_sink.send(Box::new(move |siv| {
+              siv.add_layer(cursive::views::Dialog::info(error));
+            }));
           }
-          Message::Panic() => {
-            self.send_all_notes_off();
+        }
+      }
+      Message::ToggleDevice(port_name) => {
+        if self.is_connected(&port_name) {
+          self.disconnect(&port_name);
+          self
+            .selected_ports
+            .lock()
+            .unwrap()
+            .retain(|name| *name != port_name);
+        } else if let Err(e) = self.connect(&port_name) {
+          eprintln!("Error connecting MIDI device: {}", e);
+        }
+        self.update_fallback();
+        self.publish_devices();
+      }
+      Message::ToggleVirtualPort() => {
+        if self.has_virtual() {
+          self.disconnect_virtual();
+        } else if let Err(e) = self.connect_virtual() {
+          eprintln!("Error creating virtual MIDI port: {}", e);
+        }
+        // picked by hand, keep it regardless of the other outputs
+        self.virtual_is_fallback.store(false, Ordering::Relaxed);
+        self.publish_devices();
+      }
+      Message::SetVirtualPortName(port_name) => {
+        let old_name = std::mem::replace(
+          &mut *self.virtual_port_name.lock().unwrap(),
+          port_name.clone(),
+        );
+        // the clock keeps going out of the renamed port
+        let mut clock_out_ports = self.clock_out_ports.lock().unwrap();
+        if clock_out_ports.remove(&old_name) {
+          clock_out_ports.insert(port_name);
+        }
+        drop(clock_out_ports);
+        // re-publish under the new name
+        if self.has_virtual() {
+          self.disconnect_virtual();
+          if let Err(e) = self.connect_virtual() {
+            eprintln!("Error creating virtual MIDI port: {}", e);
           }
         }
+        self.publish_devices();
       }
-    });
+      Message::ConfigureOutput(port_name, enabled, channel) => {
+        self.configure_output(&port_name, enabled, channel);
+        self.publish_devices();
+      }
+      Message::RescanDevices() => {
+        self.rescan_devices();
+      }
+      Message::Panic() => {
+        self.flush_notes();
+      }
+      Message::Clock(msg) => {
+        self.send_clock(msg);
+      }
+      Message::ToggleClockOut(port_name) => {
+        self.toggle_clock_out(port_name);
+      }
+    }
   }
 
   pub fn get_available_devices(&self) -> Vec<(String, usize)> {
@@ -217,34 +530,257 @@ impl Midi {
     }
   }
 
-  pub fn switch_device(&self, port_index: usize) -> Result<(), Box<dyn Error>> {
-    // Close existing connection
-    let mut out_device = self.out_device.lock().unwrap();
-    *out_device = None;
-    drop(out_device);
+  // ports come and go (USB hot-plug), poll for changes from a separate thread
+  fn spawn_device_watcher(midi_tx: Sender<Message>) {
+    thread::Builder::new()
+      .name("midi-device-watcher".to_string())
+      .spawn(move || loop {
+        thread::sleep(Duration::from_millis(consts::MIDI_RESCAN_INTERVAL_MS));
+        if midi_tx.send(Message::RescanDevices()).is_err() {
+          break;
+        }
+      })
+      .expect("Failed to spawn MIDI device watcher thread");
+  }
+
+  /// Add an output port by name, ports are re-indexed whenever a device is (un)plugged
+  pub fn connect(&self, port_name: &str) -> Result<(), Box<dyn Error>> {
+    let mut selected_ports = self.selected_ports.lock().unwrap();
+    if !selected_ports.iter().any(|name| name == port_name) {
+      selected_ports.push(port_name.to_string());
+    }
+    drop(selected_ports);
+
+    if self.is_connected(port_name) {
+      return Ok(());
+    }
 
-    // Create new connection
     let new_midi_out = MidiOutput::new("MIDI Output")?;
     let new_ports = new_midi_out.ports();
-    let new_port = new_ports.get(port_index).ok_or("Port not found")?;
+    let new_port = new_ports
+      .iter()
+      .find(|p| new_midi_out.port_name(p).ok().as_deref() == Some(port_name))
+      .ok_or("Port not found")?;
 
-    let port_name = new_midi_out.port_name(new_port)?;
     let conn_out = new_midi_out.connect(new_port, "midir-connection")?;
+    let mut output = Output::new(port_name.to_string(), conn_out, false);
+    self.set_up_output(&mut output);
+    self.outputs.lock().unwrap().push(output);
 
-    *self.out_device.lock().unwrap() = Some(conn_out);
-    *self.out_device_name.lock().unwrap() = Some(port_name.clone());
+    Ok(())
+  }
+
+  /// Publish our own output port that other applications (eg. a DAW) can connect to
+  #[cfg(target_os = "linux")]
+  pub fn connect_virtual(&self) -> Result<(), Box<dyn Error>> {
+    use midir::os::unix::VirtualOutput;
+
+    let port_name = self.virtual_port_name.lock().unwrap().clone();
+    let midi_out = MidiOutput::new("MIDI Output")?;
+    let conn_out = midi_out
+      .create_virtual(&port_name)
+      .map_err(|e| e.to_string())?;
+
+    let mut output = Output::new(port_name, conn_out, true);
+    self.set_up_output(&mut output);
+    self.outputs.lock().unwrap().push(output);
 
     Ok(())
   }
 
-  pub fn out_device_name(&self) -> String {
-    let out_device_name = self.out_device_name.lock().unwrap();
-    out_device_name.clone().unwrap()
+  #[cfg(not(target_os = "linux"))]
+  pub fn connect_virtual(&self) -> Result<(), Box<dyn Error>> {
+    Err("virtual MIDI ports are only supported on Linux".into())
+  }
+
+  // an output added while MPE is on (or a tuning is sent over MTS) gets what the others were
+  // configured with
+  fn set_up_output(&self, output: &mut Output) {
+    if let Some(zone) = *self.mpe.lock().unwrap() {
+      for bytes in zone.configuration() {
+        let _ = output.send_unmapped(&bytes);
+      }
+    }
+    if *self.retune_mode.lock().unwrap() == RetuneMode::Mts {
+      for bytes in self
+        .tuning
+        .lock()
+        .unwrap()
+        .iter()
+        .flat_map(Tuning::mts_messages)
+      {
+        let _ = output.send(&bytes);
+      }
+    }
+  }
+
+  fn disconnect(&self, port_name: &str) {
+    let mut outputs = self.outputs.lock().unwrap();
+    outputs.retain_mut(|output| {
+      let keep = output.is_virtual || output.name != port_name;
+      if !keep {
+        output.all_notes_off();
+      }
+      keep
+    });
+  }
+
+  fn disconnect_virtual(&self) {
+    let mut outputs = self.outputs.lock().unwrap();
+    outputs.retain_mut(|output| {
+      if output.is_virtual {
+        output.all_notes_off();
+      }
+      !output.is_virtual
+    });
+  }
+
+  fn is_connected(&self, port_name: &str) -> bool {
+    let outputs = self.outputs.lock().unwrap();
+    outputs.iter().any(|o| !o.is_virtual && o.name == port_name)
+  }
+
+  fn has_virtual(&self) -> bool {
+    self.outputs.lock().unwrap().iter().any(|o| o.is_virtual)
+  }
+
+  // with no port to play to, publish a virtual one (where supported), and close it again
+  // once a real port is back
+  fn update_fallback(&self) {
+    let outputs = self.outputs.lock().unwrap();
+    let has_port = outputs.iter().any(|o| !o.is_virtual);
+    let has_virtual = outputs.iter().any(|o| o.is_virtual);
+    drop(outputs);
+
+    if !has_port && !has_virtual && cfg!(target_os = "linux") {
+      if self.connect_virtual().is_ok() {
+        self.virtual_is_fallback.store(true, Ordering::Relaxed);
+      }
+    } else if has_port && has_virtual && self.virtual_is_fallback.load(Ordering::Relaxed) {
+      self.disconnect_virtual();
+      self.virtual_is_fallback.store(false, Ordering::Relaxed);
+    }
+  }
+
+  fn configure_output(&self, port_name: &str, enabled: bool, channel: Option<u8>) {
+    let mut outputs = self.outputs.lock().unwrap();
+    if let Some(output) = outputs.iter_mut().find(|o| o.name == port_name) {
+      // notes still held would be released on the wrong channel, or never
+      if output.channel != channel || (output.enabled && !enabled) {
+        output.all_notes_off();
+      }
+      output.enabled = enabled;
+      output.channel = channel;
+    }
+  }
+
+  fn rescan_devices(&self) {
+    let names: Vec<String> = self
+      .get_available_devices()
+      .into_iter()
+      .map(|(name, _)| name)
+      .collect();
+
+    let mut known_ports = self.known_ports.lock().unwrap();
+    if *known_ports == names {
+      return;
+    }
+    *known_ports = names.clone();
+    drop(known_ports);
+
+    // drop the unplugged ports, they stay selected
+    self
+      .outputs
+      .lock()
+      .unwrap()
+      .retain(|o| o.is_virtual || names.contains(&o.name));
+
+    // back to the selected ports, or the first one when nothing was ever selected
+    let selected_ports = self.selected_ports.lock().unwrap().clone();
+    let port_names = if selected_ports.is_empty() {
+      names.first().cloned().into_iter().collect()
+    } else {
+      selected_ports
+    };
+    for port_name in port_names.iter().filter(|name| names.contains(name)) {
+      let _ = self.connect(port_name);
+    }
+
+    self.update_fallback();
+    self.publish_devices();
+  }
+
+  pub fn menu_state(&self) -> MidiMenuState {
+    let outputs = self.outputs.lock().unwrap();
+    MidiMenuState {
+      devices: self.get_available_devices(),
+      connected: outputs
+        .iter()
+        .filter(|o| !o.is_virtual)
+        .map(|o| o.name.clone())
+        .collect(),
+      outputs: outputs
+        .iter()
+        .map(|o| (o.name.clone(), o.enabled, o.channel))
+        .collect(),
+      clock_out_ports: self.clock_out_ports.lock().unwrap().clone(),
+      virtual_port_name: self.virtual_port_name.lock().unwrap().clone(),
+      virtual_connected: outputs.iter().any(|o| o.is_virtual),
+      mpe: *self.mpe.lock().unwrap(),
+    }
+  }
+
+  // rebuild the MIDI submenu and the status for the current ports and connections
+  fn publish_devices(&self) {
+    let menu_state = self.menu_state();
+    let midi_tx = self.tx.clone();
+    let status = self.outputs_label();
+    self
+      .cb_sink
+      .send(Box::new(move |siv| {
+        menubar::rebuild_midi_menu(siv, &menu_state, midi_tx);
+        siv.call_on_name(consts::midi_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
+  }
+
+  pub fn outputs_label(&self) -> String {
+    midi_output::build_outputs_label(&self.outputs.lock().unwrap())
+  }
+
+  pub fn is_clock_out_enabled(&self, port_name: &str) -> bool {
+    self.clock_out_ports.lock().unwrap().contains(port_name)
+  }
+
+  fn toggle_clock_out(&self, port_name: String) {
+    let mut clock_out_ports = self.clock_out_ports.lock().unwrap();
+    if !clock_out_ports.remove(&port_name) {
+      clock_out_ports.insert(port_name);
+    }
+  }
+
+  fn send_clock(&self, msg: ClockMsg) {
+    let bytes = msg.to_bytes();
+    let mut outputs = self.outputs.lock().unwrap();
+    for output in outputs
+      .iter_mut()
+      .filter(|o| self.is_clock_out_enabled(&o.name))
+    {
+      let _ = output.send(&bytes);
+    }
   }
 
   fn clear_msg_config_list(&self) {
+    // nothing bends the channels back once their controls are gone
+    self.release_controls();
     let mut midi_msg_config_list = self.msg_config_list.lock().unwrap();
     midi_msg_config_list.clear();
+    self.msg_config_cursor.store(0, Ordering::Relaxed);
+    self.control_config_list.lock().unwrap().clear();
+    // a program set again is sent again
+    self.programs.lock().unwrap().clear();
   }
 
   fn set_msg_config_list(&self, midi: MidiMsg) {
@@ -252,44 +788,360 @@ impl Midi {
     midi_msg_config_list.push(midi);
   }
 
+  #[allow(clippy::too_many_arguments)]
   fn trigger_w_position(
     &self,
-    _grid_index: usize,
+    grid_index: usize,
     y_position: usize,
-    _grid_width: usize,
+    grid_width: usize,
     grid_height: usize,
     scale_mode: crate::core::scale::ScaleMode,
-    bpm: usize,
+    _bpm: usize,
+    velocity_scale: f32,
+    note_params: NoteParams,
+    step_ticks: Tick,
   ) {
     // Use the actual grid height passed as parameter
     if grid_height == 0 {
       return; // Avoid division by zero
     }
 
-    // Use scale mode to map position to note
-    let (note_index, octave) = scale_mode.y_to_scale_note(y_position, grid_height, BASE_OCTAVE);
+    let root = self.root.load(Ordering::Relaxed);
+    let note_source = *self.note_source.lock().unwrap();
+    let cursor = self.msg_config_cursor.fetch_add(1, Ordering::Relaxed);
+    let configured_msgs =
+      pick_configured_msgs(&self.msg_config_list.lock().unwrap(), note_source, cursor);
 
-    // Calculate dynamic note length based on BPM
-    // Higher BPM = shorter notes, minimum length is 1
-    // Formula: length = max(1, base_length * (base_bpm / current_bpm))
-    let base_bpm = 120;
-    let base_length = 4;
-    let calculated_length = if bpm > 0 {
-      ((base_length * base_bpm) / bpm).max(1)
+    // a loaded tuning picks the key of the row (already transposed) for the note under the playhead
+    let row_key = match configured_msgs.is_empty() && note_params.note.is_none() {
+      true => self
+        .tuning
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|tuning| tuning.row_key(y_position, grid_height, root)),
+      false => None,
+    };
+
+    // Use scale mode to map position to note, a `note` group picks the pitch class instead
+    let (note_index, octave) = match row_key {
+      Some(key) => convert_from_midi_note_num(key),
+      None => scale_mode.y_to_scale_note(y_position, grid_height, BASE_OCTAVE),
+    };
+    let note_index = note_params.note.unwrap_or(note_index);
+
+    // lengths are in steps, a `len` group holds the note for that many
+    let note_length = note_params.length.unwrap_or(1);
+    let velocity = groove::scale_velocity(
+      note_params.velocity.unwrap_or(DEFAULT_VELOCITY),
+      velocity_scale,
+    );
+    let scale_msg = MidiMsg::from(note_index, octave, note_length, velocity, 0, false);
+
+    let midi_msgs = if configured_msgs.is_empty() {
+      // nothing configured (or scale mode), play the note under the playhead
+      vec![scale_msg]
     } else {
-      base_length
+      configured_msgs
+        .into_iter()
+        .map(|mut midi_msg| {
+          if let Some(velocity) = note_params.velocity {
+            midi_msg.velocity = velocity;
+          }
+          if let Some(length) = note_params.length {
+            midi_msg.length = length;
+          }
+          midi_msg.velocity = groove::scale_velocity(midi_msg.velocity, velocity_scale);
+          midi_msg
+        })
+        .collect()
+    };
+
+    // in gate mode, a note holds for as many steps as its match spans (or its `len` group),
+    // shortened or stretched by the gate percentage, otherwise for its LEN in steps
+    let gate_mode = self.gate_mode.load(Ordering::Relaxed);
+    let gate_percent = self.gate_percent.load(Ordering::Relaxed);
+    let current_tick = self.current_tick.load(Ordering::Relaxed);
+    let is_retuned_by_bend = self.tuning.lock().unwrap().is_some()
+      && *self.retune_mode.lock().unwrap() == RetuneMode::PitchBend;
+
+    for mut midi_msg in midi_msgs {
+      if row_key.is_none() {
+        (midi_msg.note, midi_msg.octave) =
+          transpose::transpose((midi_msg.note, midi_msg.octave), root);
+      }
+      // whatever played it (row, configured message or `note` group), the key is tuned
+      let detune = self.retune(&mut midi_msg);
+      let bend = tuning::offset_bend(pitch_bend(0, 1), detune);
+      let (steps, percent) = match gate_mode {
+        true => (
+          note_params.length.map_or(note_params.span, usize::from),
+          gate_percent,
+        ),
+        false => (midi_msg.length as usize, 100),
+      };
+      let release_tick = current_tick + gate_ticks(steps, step_ticks, percent);
+      if let Some(channel) = self.assign_mpe_channel(grid_index, detune) {
+        midi_msg.channel = channel;
+        // a fresh voice starts from the center (or its detune), before its note-on
+        let cell = (
+          grid_index % grid_width.max(1),
+          grid_index / grid_width.max(1),
+        );
+        for bytes in mpe::expression(channel, cell, (grid_width, grid_height), bend) {
+          let _ = self.send(&bytes);
+        }
+      } else if is_retuned_by_bend {
+        // untuned notes too, the channel keeps the last bend otherwise
+        let _ = self.send(&pitch_bend_msg(midi_msg.channel, bend));
+      }
+      self.play(midi_msg, release_tick);
+    }
+  }
+
+  fn set_mpe(&self, zone: Option<Zone>) {
+    // held notes would be released on channels the receiver no longer reads that way
+    for note in self.stack.flush() {
+      let _ = self.trigger(&note, false);
+    }
+    let mut mpe = self.mpe.lock().unwrap();
+    let msgs = mpe
+      .iter()
+      .flat_map(Zone::release)
+      .chain(zone.iter().flat_map(Zone::configuration))
+      .collect::<Vec<_>>();
+    *mpe = zone;
+    drop(mpe);
+    for bytes in msgs {
+      let _ = self.send(&bytes);
+    }
+    self.rotation.lock().unwrap().reset();
+    self.mpe_voices.lock().unwrap().clear();
+  }
+
+  // with a tuning loaded, the detune bend for the key of `midi_msg`, moved to the nearest 12-TET
+  // key in pitch bend mode (MTS has retuned the keys of the receiver already)
+  fn retune(&self, midi_msg: &mut MidiMsg) -> i16 {
+    let tuning = self.tuning.lock().unwrap();
+    let Some(tuning) = tuning.as_ref() else {
+      return 0;
+    };
+    if *self.retune_mode.lock().unwrap() != RetuneMode::PitchBend {
+      return 0;
+    }
+    let key = convert_to_midi_note_num(midi_msg.octave, midi_msg.note);
+    // an unmapped key plays untuned
+    let Some((key, detune)) = tuning.nearest_key(key) else {
+      return 0;
     };
-    let note_length = (calculated_length as u8).min(127);
-    let midi_msg = MidiMsg::from(note_index, octave, note_length, 100, 0, false);
+    (midi_msg.note, midi_msg.octave) = convert_from_midi_note_num(key);
+    let range = self
+      .mpe
+      .lock()
+      .unwrap()
+      .map_or(consts::DEFAULT_PITCH_BEND_RANGE, |zone| {
+        zone.pitch_bend_range
+      });
+    tuning::detune_bend(detune, range)
+  }
+
+  // MTS retunes the receiver as a whole, its keys go back to 12-TET once it is not used anymore
+  fn set_tuning(&self, tuning: Option<Tuning>, retune_mode: RetuneMode) {
+    let mut current_tuning = self.tuning.lock().unwrap();
+    let mut current_mode = self.retune_mode.lock().unwrap();
+    let was_mts = current_tuning.is_some() && *current_mode == RetuneMode::Mts;
+    let msgs = match (&tuning, retune_mode) {
+      (Some(tuning), RetuneMode::Mts) => tuning.mts_messages(),
+      _ if was_mts => tuning::mts_reset(),
+      _ => Vec::new(),
+    };
+    let is_retuned_by_bend = tuning.is_some() && retune_mode == RetuneMode::PitchBend;
+    *current_tuning = tuning;
+    *current_mode = retune_mode;
+    drop(current_tuning);
+    drop(current_mode);
+
+    for bytes in msgs {
+      let _ = self.send(&bytes);
+    }
+
+    // without member channels, a note's bend detunes the notes still sounding on its channel
+    if is_retuned_by_bend && self.mpe.lock().unwrap().is_none() {
+      let _ = self.cb_sink.send(Box::new(|siv| {
+        siv.add_layer(cursive::views::Dialog::info(
+          consts::PITCH_BEND_RETUNE_WITHOUT_MPE_WARNING,
+        ));
+      }));
+    }
+  }
+
+  // in MPE mode, the member channel for a note of the match starting on `grid_index`
+  fn assign_mpe_channel(&self, grid_index: usize, detune: i16) -> Option<u8> {
+    let members = (*self.mpe.lock().unwrap())?.member_channels();
+    let channel = self
+      .rotation
+      .lock()
+      .unwrap()
+      .assign(&members, &self.stack.channels())?;
+    // every channel sounds, the oldest voice in turn is cut
+    for note in self.stack.release_channel(channel) {
+      let _ = self.trigger(&note, false);
+    }
+    let mut mpe_voices = self.mpe_voices.lock().unwrap();
+    mpe_voices.retain(|(_, voice_channel, _)| *voice_channel != channel);
+    mpe_voices.push((grid_index, channel, detune));
+    Some(channel)
+  }
+
+  // in MPE mode, move the voices of the match under the playhead along with it
+  fn trigger_expression(&self, step: &StepContext) {
+    if self.mpe.lock().unwrap().is_none() {
+      return;
+    }
+    let sounding = self.stack.channels();
+    let voices: Vec<(u8, i16)> = self
+      .mpe_voices
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|(start, channel, _)| *start == step.start && sounding.contains(channel))
+      .map(|(_, channel, detune)| (*channel, *detune))
+      .collect();
+    let bend = pitch_bend(step.offset, step.span);
+    for (channel, detune) in voices {
+      for bytes in mpe::expression(
+        channel,
+        (step.x, step.y),
+        (step.grid_width, step.grid_height),
+        tuning::offset_bend(bend, detune),
+      ) {
+        let _ = self.send(&bytes);
+      }
+    }
+  }
+
+  fn trigger_controls(&self, step: &StepContext) {
+    // ahead of the notes of the step, markers on the same channel take turns
+    if let Some((channel, program)) = step.program {
+      self.send_program(channel, program);
+    }
+    let control_config_list = self.control_config_list.lock().unwrap();
+    for msg in control_config_list.iter() {
+      let Some(bytes) = msg.to_bytes(step) else {
+        continue;
+      };
+      match msg.kind {
+        ChannelMsgKind::ProgramChange(program) => self.send_program(bytes[0], program),
+        _ => {
+          let _ = self.send(&bytes);
+        }
+      }
+    }
+  }
+
+  // a program change is only sent when the program differs
+  fn send_program(&self, channel: u8, program: u8) {
+    let channel = channel & 0x0F;
+    let mut programs = self.programs.lock().unwrap();
+    if programs.insert(channel, program) == Some(program) {
+      return;
+    }
+    drop(programs);
+    let _ = self.send(&[0xC0 | channel, program & 0x7F]);
+  }
+
+  // the bends of a match end with it, back to the center
+  fn release_controls(&self) {
+    let channels: HashSet<u8> = self
+      .control_config_list
+      .lock()
+      .unwrap()
+      .iter()
+      .filter(|msg| msg.kind == ChannelMsgKind::PitchBend)
+      .map(|msg| msg.channel)
+      .collect();
+    for channel in channels {
+      let _ = self.send(&pitch_bend_msg(channel, pitch_bend(0, 1)));
+    }
+  }
+
+  fn update_sends_controls(&self) {
+    let sends_controls =
+      !self.control_config_list.lock().unwrap().is_empty() || self.mpe.lock().unwrap().is_some();
+    self.sends_controls.store(sends_controls, Ordering::Relaxed);
+  }
+
+  /// Whether matches have controls (or MPE expression) to send, shared with the marker
+  pub fn sends_controls(&self) -> Arc<AtomicBool> {
+    Arc::clone(&self.sends_controls)
+  }
+
+  fn play(&self, midi_msg: MidiMsg, release_tick: usize) {
+    // a pitch still sounding is released first, otherwise its pending note-off would cut the new one
+    if let Some(retriggered) = self.stack.hold(midi_msg.clone(), release_tick) {
+      let _ = self.trigger(&retriggered, false);
+    }
     let _ = self.trigger(&midi_msg, true);
-    self.tx.send(Message::Push(midi_msg)).unwrap();
+  }
+
+  /// The current take as a file, recording or stopped
+  pub fn recording(&self) -> Option<Smf> {
+    self.recorder.lock().unwrap().as_ref().map(Recorder::to_smf)
+  }
+
+  fn publish_recording(&self) {
+    self.publish_recording_locked(self.recorder.lock().unwrap().as_ref());
+  }
+
+  // for callers already holding the recorder lock
+  fn publish_recording_locked(&self, recorder: Option<&Recorder>) {
+    let status = utils::build_record_status_str(
+      recorder.map(|recorder| (recorder.is_recording(), recorder.len())),
+    );
+    self
+      .cb_sink
+      .send(Box::new(move |siv| {
+        siv.call_on_name(consts::record_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
+  }
+
+  fn publish_gate(&self) {
+    let gate_mode = self.gate_mode.load(Ordering::Relaxed);
+    let gate_percent = self.gate_percent.load(Ordering::Relaxed);
+    let status = utils::build_gate_status_str(gate_mode, gate_percent);
+    self
+      .cb_sink
+      .send(Box::new(move |siv| {
+        siv.call_on_name(consts::gate_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
+  }
+
+  fn publish_note_source(&self) {
+    let note_source = *self.note_source.lock().unwrap();
+    let list_len =
+      self.msg_config_list.lock().unwrap().len() + self.control_config_list.lock().unwrap().len();
+    let status = utils::build_note_status_str(note_source.name(), list_len);
+    self
+      .cb_sink
+      .send(Box::new(move |siv| {
+        siv.call_on_name(consts::note_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
   }
 
   fn build_midi_msg(&self, midi_msg: &MidiMsg, down: bool) -> [u8; 3] {
     let note_event = if down {
-      0x90 + midi_msg.channel
+      0x90 | (midi_msg.channel & 0x0F)
     } else {
-      0x80 + midi_msg.channel
+      0x80 | (midi_msg.channel & 0x0F)
     };
 
     [
@@ -301,33 +1153,240 @@ impl Midi {
 
   pub fn trigger(&self, midi_msg: &MidiMsg, down: bool) -> Result<(), &str> {
     let built_msg = self.build_midi_msg(midi_msg, down);
-    match self.out_device.lock() {
-      Ok(mut conn_out) => {
-        let connection_out = conn_out.as_mut().unwrap();
-        connection_out.send(&built_msg).unwrap();
-        Ok(())
+    self.send(&built_msg)
+  }
+
+  /// Send a channel message to every output, and to the take being recorded
+  fn send(&self, bytes: &[u8]) -> Result<(), &str> {
+    if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+      recorder.record(bytes);
+    }
+    // a channel remap would fold every member channel onto one
+    let is_mpe = self.mpe.lock().unwrap().is_some();
+    match self.outputs.lock() {
+      // with no output port, the sequencer keeps running silently
+      Ok(mut outputs) => {
+        // an unplugged device must not silence the others, every output gets its try
+        let failed = outputs
+          .iter_mut()
+          .map(|output| match is_mpe {
+            true => output.send_unmapped(bytes),
+            false => output.send(bytes),
+          })
+          .filter(Result::is_err)
+          .count();
+        match failed {
+          0 => Ok(()),
+          _ => Err("send_midi_note_out::error"),
+        }
       }
       _ => Err("send_midi_note_out::error"),
     }
   }
 
+  fn flush_notes(&self) {
+    for note in self.stack.flush() {
+      let _ = self.trigger(&note, false);
+    }
+    // notes played by hand or still ringing on the receiver go too
+    self.send_all_notes_off();
+  }
+
   fn send_all_notes_off(&self) {
-    // Send All Notes Off (CC 123) on all 16 MIDI channels
-    if let Ok(mut conn_out) = self.out_device.lock() {
-      if let Some(connection_out) = conn_out.as_mut() {
-        for channel in 0..16 {
-          // CC 123: All Notes Off
-          let all_notes_off = [0xB0 + channel, 123, 0];
-          let _ = connection_out.send(&all_notes_off);
-          // CC 120: All Sound Off (for good measure)
-          let all_sound_off = [0xB0 + channel, 120, 0];
-          let _ = connection_out.send(&all_sound_off);
-        }
-      }
+    if let Ok(mut outputs) = self.outputs.lock() {
+      midi_output::all_notes_off(&mut outputs);
     }
   }
 }
 
+/// The configured messages a matched step plays, none when the scale note should be played
+pub fn pick_configured_msgs(
+  msg_config_list: &[MidiMsg],
+  note_source: NoteSource,
+  cursor: usize,
+) -> Vec<MidiMsg> {
+  if msg_config_list.is_empty() {
+    return vec![];
+  }
+  match note_source {
+    NoteSource::Scale => vec![],
+    NoteSource::Cycle => vec![msg_config_list[cursor % msg_config_list.len()].clone()],
+    NoteSource::Chord => msg_config_list.to_vec(),
+  }
+}
+
+/// Ticks to hold a note spanning `steps` steps of `step_ticks`, shortened/stretched by `gate_percent`
+pub fn gate_ticks(steps: usize, step_ticks: Tick, gate_percent: usize) -> usize {
+  let ticks = step_ticks * Ratio::from_integer((steps.max(1) * gate_percent) as i64) / 100;
+  ticks.ceil().to_integer().max(1) as usize
+}
+
+/// Loopback ports, eg. ALSA's "Midi Through", which play to nothing by themselves
+fn is_through_port(name: &str) -> bool {
+  let name = name.to_lowercase();
+  name.contains("through") || name.contains("loopback")
+}
+
 pub fn convert_to_midi_note_num(octave: u8, note: u8) -> u8 {
-  24 + (octave * 12) + note // 60 = C3
+  // 60 = C3, a transposed note past G8 stays on the highest data byte
+  (24 + octave as u16 * 12 + note as u16).min(127) as u8
+}
+
+/// (note, octave) of a MIDI note number, the lowest octave starts at 24
+pub fn convert_from_midi_note_num(midi_note: u8) -> (u8, u8) {
+  let midi_note = midi_note.saturating_sub(24);
+  (midi_note % 12, midi_note / 12)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::smf;
+
+  #[test]
+  fn test_clock_msg_realtime_bytes() {
+    assert_eq!(ClockMsg::Pulse.to_bytes(), vec![0xF8]);
+    assert_eq!(ClockMsg::Start.to_bytes(), vec![0xFA]);
+    assert_eq!(ClockMsg::Continue.to_bytes(), vec![0xFB]);
+    assert_eq!(ClockMsg::Stop.to_bytes(), vec![0xFC]);
+  }
+
+  #[test]
+  fn test_clock_msg_song_position_is_14_bit() {
+    assert_eq!(ClockMsg::SongPosition(0).to_bytes(), vec![0xF2, 0, 0]);
+    assert_eq!(
+      ClockMsg::SongPosition(200).to_bytes(),
+      vec![0xF2, 0x48, 0x01]
+    );
+  }
+
+  #[test]
+  fn test_clock_msg_round_trip() {
+    for msg in [
+      ClockMsg::Pulse,
+      ClockMsg::Start,
+      ClockMsg::Continue,
+      ClockMsg::Stop,
+      ClockMsg::SongPosition(0x3FFF),
+    ] {
+      assert_eq!(ClockMsg::from_bytes(&msg.to_bytes()), Some(msg));
+    }
+    assert_eq!(ClockMsg::from_bytes(&[0x90, 60, 100]), None);
+  }
+
+  fn config_list() -> Vec<MidiMsg> {
+    vec![
+      MidiMsg::from(0, 4, 1, 64, 2, false),
+      MidiMsg::from(4, 4, 2, 80, 2, false),
+      MidiMsg::from(7, 4, 3, 100, 2, false),
+    ]
+  }
+
+  #[test]
+  fn test_pick_configured_msgs_cycles_per_step() {
+    let list = config_list();
+    let notes: Vec<u8> = (0..5)
+      .flat_map(|cursor| pick_configured_msgs(&list, NoteSource::Cycle, cursor))
+      .map(|msg| msg.note)
+      .collect();
+    assert_eq!(notes, vec![0, 4, 7, 0, 4]);
+  }
+
+  #[test]
+  fn test_through_ports_are_not_a_default() {
+    assert!(is_through_port("Midi Through:Midi Through Port-0 14:0"));
+    assert!(is_through_port("Loopback MIDI"));
+    assert!(!is_through_port(
+      "USB MIDI Interface:USB MIDI Interface MIDI 1 20:0"
+    ));
+  }
+
+  #[test]
+  fn test_midi_note_num_round_trip() {
+    for midi_note in 24..=127 {
+      let (note, octave) = convert_from_midi_note_num(midi_note);
+      assert_eq!(convert_to_midi_note_num(octave, note), midi_note);
+    }
+    assert_eq!(convert_from_midi_note_num(10), (0, 0));
+    assert_eq!(convert_to_midi_note_num(9, 7), 127);
+    assert_eq!(convert_to_midi_note_num(25, 0), 127);
+  }
+
+  #[test]
+  fn test_gate_ticks_follow_steps_and_percentage() {
+    // sixteenths at 96 PPQN
+    let step_ticks = Ratio::from_integer(24);
+    assert_eq!(gate_ticks(1, step_ticks, 100), 24);
+    assert_eq!(gate_ticks(5, step_ticks, 100), 120);
+    assert_eq!(gate_ticks(4, step_ticks, 50), 48);
+    // eighth triplets do not divide evenly, round up
+    assert_eq!(gate_ticks(1, Ratio::new(32, 1), 33), 11);
+    // never shorter than a tick, a zero span still plays one step
+    assert_eq!(gate_ticks(1, step_ticks, 1), 1);
+    assert_eq!(gate_ticks(0, step_ticks, 100), 24);
+  }
+
+  #[test]
+  fn test_pick_configured_msgs_chord_and_scale() {
+    let list = config_list();
+    let chord = pick_configured_msgs(&list, NoteSource::Chord, 7);
+    assert_eq!(chord.len(), 3);
+    assert!(chord.iter().all(|msg| msg.channel == 2));
+
+    assert!(pick_configured_msgs(&list, NoteSource::Scale, 0).is_empty());
+    assert!(pick_configured_msgs(&[], NoteSource::Chord, 0).is_empty());
+  }
+
+  #[test]
+  fn test_marker_program_and_bend_release() {
+    let (cb_sink, _callbacks) = crossbeam::channel::unbounded();
+    let midi = Midi::offline(cb_sink);
+    assert!(!midi.sends_controls().load(Ordering::Relaxed));
+    midi.handle(Message::StartRecording());
+    midi.handle(Message::SetControlConfig(ChannelMsg::new(
+      ChannelMsgKind::PitchBend,
+      0,
+      None,
+    )));
+    assert!(midi.sends_controls().load(Ordering::Relaxed));
+
+    // the last cell of a 5 cell match, played by a marker with program 7
+    let step = StepContext {
+      x: 4,
+      y: 0,
+      grid_width: 5,
+      grid_height: 1,
+      offset: 4,
+      start: 0,
+      span: 5,
+      char: None,
+      groups: Vec::new(),
+      program: Some((0, 7)),
+    };
+    midi.handle(Message::TriggerControls(step.clone()));
+    midi.handle(Message::TriggerControls(step));
+    midi.handle(Message::ReleaseControls());
+
+    let sent: Vec<Vec<u8>> = midi
+      .recording()
+      .unwrap()
+      .tracks
+      .iter()
+      .flat_map(|track| track.events())
+      .filter_map(|(_, event)| match event {
+        smf::Event::Midi(bytes) => Some(bytes.clone()),
+        _ => None,
+      })
+      .collect();
+    // the program only once, the bend back to the center once the match is left
+    assert_eq!(
+      sent,
+      vec![
+        vec![0xC0, 7],
+        vec![0xE0, 0x7F, 0x7F],
+        vec![0xE0, 0x7F, 0x7F],
+        vec![0xE0, 0x00, 0x40],
+      ]
+    );
+  }
 }
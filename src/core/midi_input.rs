@@ -0,0 +1,400 @@
+use cursive::views::Dialog;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use super::command::Command;
+use super::consts;
+use super::midi::{self, ClockMsg};
+use super::midi_learn::{self, Control, MidiMap, Target};
+use super::timing::clock::SyncMode;
+use super::timing::metronome;
+use super::transpose::KeyTracker;
+use crate::app::UserData;
+use crate::view::common::playhead_controller;
+
+// a CC performs its command when it rises through the middle of its range
+const COMMAND_CC_THRESHOLD: u8 = 64;
+
+#[derive(Clone, Debug)]
+pub enum Message {
+  Connect(usize),
+  Disconnect(),
+  ConnectKeys(usize),
+  DisconnectKeys(),
+  KeyOn(u8),
+  KeyOff(u8),
+  SetLatch(bool),
+  SetScaleDetection(bool),
+  ConnectControls(usize),
+  DisconnectControls(),
+  Control(Control, u8), // (control, value)
+  Learn(Target),
+  CancelLearn(),
+  ClearMappings(),
+}
+
+pub struct MidiIn {
+  pub in_device: Mutex<Option<MidiInputConnection<()>>>,
+  pub in_device_name: Mutex<Option<String>>,
+  // a keyboard transposing the grid, apart from the clock input so it never takes over the transport
+  key_device: Mutex<Option<MidiInputConnection<()>>>,
+  keys: Mutex<KeyTracker>,
+  // a hardware controller performing the learned commands and parameters, see `midi_learn`
+  control_device: Mutex<Option<MidiInputConnection<()>>>,
+  midi_map: Mutex<MidiMap>,
+  learning: Mutex<Option<Target>>,
+  last_values: Mutex<HashMap<Control, u8>>,
+  pub tx: Sender<Message>,
+  pub rx: Receiver<Message>,
+  metronome_tx: Sender<metronome::Message>,
+  marker_tx: Sender<playhead_controller::Message>,
+  midi_tx: Sender<midi::Message>,
+  cb_sink: cursive::CbSink,
+}
+
+impl MidiIn {
+  pub fn new(
+    metronome_tx: Sender<metronome::Message>,
+    marker_tx: Sender<playhead_controller::Message>,
+    midi_tx: Sender<midi::Message>,
+    cb_sink: cursive::CbSink,
+  ) -> Self {
+    let (tx, rx) = channel();
+    // no mapping yet is fine, there is nothing learned to perform
+    let midi_map = MidiMap::load(&midi_learn::midi_map_path()).unwrap_or_default();
+    Self {
+      in_device: None.into(),
+      in_device_name: None.into(),
+      key_device: None.into(),
+      keys: KeyTracker::new().into(),
+      control_device: None.into(),
+      midi_map: midi_map.into(),
+      learning: None.into(),
+      last_values: HashMap::new().into(),
+      tx,
+      rx,
+      metronome_tx,
+      marker_tx,
+      midi_tx,
+      cb_sink,
+    }
+  }
+
+  pub fn run(self) {
+    thread::spawn(move || {
+      // perform the set with the controller it was learned on, when it is plugged in
+      if let Some(port_index) = self.controls_port_index() {
+        if let Err(e) = self.connect_controls(port_index) {
+          self.report(format!("Error connecting MIDI controller: {}", e));
+        }
+      }
+
+      for control_message in &self.rx {
+        match control_message {
+          Message::Connect(port_index) => match self.connect(port_index) {
+            Ok(()) => {
+              let _ = self
+                .metronome_tx
+                .send(metronome::Message::SetSync(SyncMode::External));
+            }
+            Err(e) => self.report(format!("Error connecting MIDI input: {}", e)),
+          },
+          Message::Disconnect() => {
+            self.disconnect();
+            let _ = self
+              .metronome_tx
+              .send(metronome::Message::SetSync(SyncMode::Internal));
+          }
+          Message::ConnectKeys(port_index) => {
+            if let Err(e) = self.connect_keys(port_index) {
+              self.report(format!("Error connecting MIDI keyboard: {}", e));
+            }
+          }
+          Message::DisconnectKeys() => {
+            if let Some(conn_in) = self.key_device.lock().unwrap().take() {
+              conn_in.close();
+            }
+            let root = self.keys.lock().unwrap().reset();
+            self.publish_root(root);
+          }
+          Message::KeyOn(note) => {
+            let mut keys = self.keys.lock().unwrap();
+            let root = keys.note_on(note);
+            let held_scale = keys.held_scale();
+            drop(keys);
+
+            self.publish_root(root);
+            if let Some((_, scale_mode)) = held_scale {
+              let _ = self
+                .marker_tx
+                .send(playhead_controller::Message::SetScaleModeLeft(scale_mode));
+              let _ = self
+                .marker_tx
+                .send(playhead_controller::Message::SetScaleModeTop(scale_mode));
+            }
+          }
+          Message::KeyOff(note) => {
+            let root = self.keys.lock().unwrap().note_off(note);
+            self.publish_root(root);
+          }
+          Message::SetLatch(latch) => {
+            let root = self.keys.lock().unwrap().set_latch(latch);
+            self.publish_root(root);
+          }
+          Message::SetScaleDetection(detect_scale) => {
+            self.keys.lock().unwrap().set_detect_scale(detect_scale);
+          }
+          Message::ConnectControls(port_index) => {
+            if let Err(e) = self.connect_controls(port_index) {
+              self.report(format!("Error connecting MIDI controller: {}", e));
+              continue;
+            }
+            let port = self.get_available_devices().into_iter().nth(port_index);
+            self.midi_map.lock().unwrap().port = port.map(|(name, _)| name);
+            self.save_midi_map();
+          }
+          Message::DisconnectControls() => {
+            if let Some(conn_in) = self.control_device.lock().unwrap().take() {
+              conn_in.close();
+            }
+            self.midi_map.lock().unwrap().port = None;
+            self.save_midi_map();
+          }
+          Message::Control(control, value) => self.handle_control(control, value),
+          Message::Learn(target) => {
+            *self.learning.lock().unwrap() = Some(target);
+          }
+          Message::CancelLearn() => {
+            *self.learning.lock().unwrap() = None;
+          }
+          Message::ClearMappings() => {
+            self.midi_map.lock().unwrap().clear();
+            self.save_midi_map();
+          }
+        }
+      }
+    });
+  }
+
+  pub fn get_available_devices(&self) -> Vec<(String, usize)> {
+    let Ok(midi_in) = MidiInput::new("MIDI Input") else {
+      return Vec::new();
+    };
+    midi_in
+      .ports()
+      .iter()
+      .enumerate()
+      .map(|(i, p)| {
+        let name = midi_in
+          .port_name(p)
+          .unwrap_or_else(|_| format!("Port {}", i));
+        (name, i)
+      })
+      .collect()
+  }
+
+  pub fn connect(&self, port_index: usize) -> Result<(), Box<dyn Error>> {
+    self.disconnect();
+
+    let mut midi_in = MidiInput::new("MIDI Input")?;
+    // timing messages are what we are listening for
+    midi_in.ignore(Ignore::SysexAndActiveSense);
+
+    let ports = midi_in.ports();
+    let port = ports.get(port_index).ok_or("Port not found")?;
+    let port_name = midi_in.port_name(port)?;
+
+    let metronome_tx = self.metronome_tx.clone();
+    let conn_in = midi_in.connect(
+      port,
+      "midir-input-connection",
+      move |_stamp, bytes, _| {
+        if let Some(msg) = ClockMsg::from_bytes(bytes) {
+          let _ = metronome_tx.send(metronome::Message::ExternalClock(msg));
+        }
+      },
+      (),
+    )?;
+
+    *self.in_device.lock().unwrap() = Some(conn_in);
+    *self.in_device_name.lock().unwrap() = Some(port_name);
+
+    Ok(())
+  }
+
+  /// Listen to the note-ons/offs of a keyboard, see `KeyTracker`
+  pub fn connect_keys(&self, port_index: usize) -> Result<(), Box<dyn Error>> {
+    if let Some(conn_in) = self.key_device.lock().unwrap().take() {
+      conn_in.close();
+    }
+
+    let midi_in = MidiInput::new("MIDI Keys Input")?;
+    let ports = midi_in.ports();
+    let port = ports.get(port_index).ok_or("Port not found")?;
+
+    let tx = self.tx.clone();
+    let conn_in = midi_in.connect(
+      port,
+      "midir-keys-connection",
+      move |_stamp, bytes, _| {
+        let msg = match bytes {
+          [status, note, velocity, ..] if status & 0xF0 == 0x90 && *velocity > 0 => {
+            Message::KeyOn(*note)
+          }
+          [status, note, ..] if status & 0xF0 == 0x80 || status & 0xF0 == 0x90 => {
+            Message::KeyOff(*note)
+          }
+          _ => return,
+        };
+        let _ = tx.send(msg);
+      },
+      (),
+    )?;
+
+    *self.key_device.lock().unwrap() = Some(conn_in);
+
+    Ok(())
+  }
+
+  /// Listen to the CCs and notes of a controller, to learn or perform the bindings
+  pub fn connect_controls(&self, port_index: usize) -> Result<(), Box<dyn Error>> {
+    if let Some(conn_in) = self.control_device.lock().unwrap().take() {
+      conn_in.close();
+    }
+
+    let midi_in = MidiInput::new("MIDI Controls Input")?;
+    let ports = midi_in.ports();
+    let port = ports.get(port_index).ok_or("Port not found")?;
+
+    let tx = self.tx.clone();
+    let conn_in = midi_in.connect(
+      port,
+      "midir-controls-connection",
+      move |_stamp, bytes, _| {
+        if let Some((control, value)) = Control::from_bytes(bytes) {
+          let _ = tx.send(Message::Control(control, value));
+        }
+      },
+      (),
+    )?;
+
+    *self.control_device.lock().unwrap() = Some(conn_in);
+
+    Ok(())
+  }
+
+  /// Index of the port the mapping was learned on, among the ports available now
+  pub fn controls_port_index(&self) -> Option<usize> {
+    let port = self.midi_map.lock().unwrap().port.clone()?;
+    self
+      .get_available_devices()
+      .into_iter()
+      .find(|(name, _)| *name == port)
+      .map(|(_, idx)| idx)
+  }
+
+  fn handle_control(&self, control: Control, value: u8) {
+    // a note-off neither learns nor performs anything, a CC at 0 still sets a parameter
+    if value == 0 && matches!(control, Control::Note(..)) {
+      return;
+    }
+
+    if let Some(target) = self.learning.lock().unwrap().take() {
+      self.midi_map.lock().unwrap().bind(control, target);
+      self.save_midi_map();
+
+      let _ = self.cb_sink.send(Box::new(move |siv| {
+        if let Some(pos) = siv
+          .screen_mut()
+          .find_layer_from_name(consts::midi_learn_section_view)
+        {
+          siv.screen_mut().remove_layer(pos);
+        }
+        siv.add_layer(Dialog::info(format!("{} → {}", control, target)).title("MIDI Learn"));
+      }));
+      return;
+    }
+
+    let last_value = self.last_values.lock().unwrap().insert(control, value);
+    // a knob turned up past the middle performs once, not on every value it sends
+    let rising = match control {
+      Control::Cc(..) => {
+        last_value.unwrap_or(0) < COMMAND_CC_THRESHOLD && value >= COMMAND_CC_THRESHOLD
+      }
+      Control::Note(..) => true,
+    };
+
+    let command = match self.midi_map.lock().unwrap().target(control) {
+      Some(Target::Command(command)) if rising => command,
+      Some(Target::Param(param)) => Command::SetParam(param, value),
+      _ => return,
+    };
+
+    let _ = self.cb_sink.send(Box::new(move |siv| {
+      if let Some(data) = siv.user_data::<UserData>().cloned() {
+        data.cmd.handle(siv, command);
+      }
+    }));
+  }
+
+  fn save_midi_map(&self) {
+    if let Err(e) = self
+      .midi_map
+      .lock()
+      .unwrap()
+      .save(&midi_learn::midi_map_path())
+    {
+      self.report(format!("Error saving MIDI mapping: {}", e));
+    }
+  }
+
+  fn report(&self, error: String) {
+    let _ = self.cb_sink.send(Box::new(move |siv| {
+      siv.add_layer(Dialog::info(error));
+    }));
+  }
+
+  fn publish_root(&self, root: Option<u8>) {
+    let Some(root) = root else {
+      return;
+    };
+    let _ = self.midi_tx.send(midi::Message::SetRoot(root));
+    let _ = self
+      .marker_tx
+      .send(playhead_controller::Message::SetRoot(root));
+  }
+
+  pub fn disconnect(&self) {
+    if let Some(conn_in) = self.in_device.lock().unwrap().take() {
+      conn_in.close();
+    }
+    *self.in_device_name.lock().unwrap() = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::command::Adjustment;
+
+  #[test]
+  fn test_learned_command_fires_once_per_rise() {
+    let (cb_sink, callbacks): (cursive::CbSink, _) = crossbeam::channel::unbounded();
+    let midi_in = MidiIn::new(channel().0, channel().0, channel().0, cb_sink);
+    let control = Control::Cc(0, 20);
+    midi_in.midi_map.lock().unwrap().bind(
+      control,
+      Target::Command(Command::AdjustBPM(Adjustment::Increase)),
+    );
+
+    // a knob swept up and back down, then up again
+    for value in [10, 40, 64, 90, 127, 100, 30, 0, 70, 127] {
+      midi_in.handle_control(control, value);
+    }
+    assert_eq!(callbacks.try_iter().count(), 2);
+  }
+}
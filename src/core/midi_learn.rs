@@ -0,0 +1,333 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::command::{Adjustment, Command, MoveDirection};
+use super::consts;
+
+/// A continuous parameter a knob or fader sweeps through, see `scale_value`
+#[derive(Clone, Serialize, Deserialize, Debug, Copy, PartialEq, Eq)]
+pub enum Param {
+  Bpm,
+  Ratio,
+  MarkerX,
+  MarkerY,
+  MarkerW,
+  MarkerH,
+  ScaleLeft,
+  ScaleTop,
+  Gate,
+}
+
+impl Param {
+  pub fn all() -> &'static [Param] {
+    &[
+      Param::Bpm,
+      Param::Ratio,
+      Param::MarkerX,
+      Param::MarkerY,
+      Param::MarkerW,
+      Param::MarkerH,
+      Param::ScaleLeft,
+      Param::ScaleTop,
+      Param::Gate,
+    ]
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Param::Bpm => "bpm",
+      Param::Ratio => "ratio",
+      Param::MarkerX => "x",
+      Param::MarkerY => "y",
+      Param::MarkerW => "w",
+      Param::MarkerH => "h",
+      Param::ScaleLeft => "scaleleft",
+      Param::ScaleTop => "scaletop",
+      Param::Gate => "gate",
+    }
+  }
+}
+
+/// The controller a binding listens to
+#[derive(Clone, Debug, Copy, PartialEq, Eq, Hash)]
+pub enum Control {
+  Cc(u8, u8),   // (channel, controller number)
+  Note(u8, u8), // (channel, note number)
+}
+
+impl Control {
+  /// The control and its value (CC value or note velocity, 0 for a note-off)
+  pub fn from_bytes(bytes: &[u8]) -> Option<(Control, u8)> {
+    let [status, number, value, ..] = *bytes else {
+      return None;
+    };
+    let channel = status & 0x0F;
+    match status & 0xF0 {
+      0xB0 => Some((Control::Cc(channel, number), value)),
+      0x90 => Some((Control::Note(channel, number), value)),
+      0x80 => Some((Control::Note(channel, number), 0)),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for Control {
+  // channels are 1-based in the mapping file, like everywhere in the UI
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Control::Cc(channel, number) => write!(f, "cc {} {}", channel + 1, number),
+      Control::Note(channel, number) => write!(f, "note {} {}", channel + 1, number),
+    }
+  }
+}
+
+/// What a control does: fire a command on press, or set a parameter from its value
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub enum Target {
+  Command(Command),
+  Param(Param),
+}
+
+impl Target {
+  /// Everything that can be learned, parameters first
+  pub fn all() -> Vec<Target> {
+    let adjustments = [Adjustment::Increase, Adjustment::Decrease];
+    let directions = [
+      MoveDirection::Up,
+      MoveDirection::Down,
+      MoveDirection::Left,
+      MoveDirection::Right,
+    ];
+
+    let mut commands = vec![
+      Command::TogglePlay,
+      Command::ResetPosition,
+      Command::TapTempo,
+      Command::PinRegion,
+      Command::RemoveRegion,
+      Command::ToggleReverse,
+      Command::ToggleArpeggiator,
+      Command::ToggleAccumulation,
+      Command::ToggleRandom,
+      Command::CycleNoteSource,
+      Command::ToggleGateMode,
+      Command::CancelTempoRamp,
+      Command::Panic,
+      Command::StartRecording,
+      Command::StopRecording,
+    ];
+    commands.extend(directions.map(Command::AdjustMarker));
+    for adjust in [
+      Command::AdjustBPM,
+      Command::AdjustRatio,
+      Command::AdjustLoopLength,
+      Command::AdjustSwing,
+      Command::AdjustGate,
+    ] {
+      commands.extend(adjustments.map(adjust));
+    }
+
+    Param::all()
+      .iter()
+      .map(|param| Target::Param(*param))
+      .chain(commands.into_iter().map(Target::Command))
+      .collect()
+  }
+
+  pub fn parse(src: &str) -> Option<Target> {
+    let src = src.split_whitespace().collect::<Vec<_>>().join(" ");
+    Target::all()
+      .into_iter()
+      .find(|target| target.to_string() == src)
+  }
+}
+
+impl fmt::Display for Target {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Target::Command(command) => write!(f, "{}", command),
+      Target::Param(param) => write!(f, "{}", param.name()),
+    }
+  }
+}
+
+/// Map a 0-127 controller value onto `min..=max`
+pub fn scale_value(value: u8, min: usize, max: usize) -> usize {
+  let value = value.min(127) as usize;
+  min + (value * max.saturating_sub(min) + 63) / 127
+}
+
+/// Controller bindings, plus the input port they were learned from.
+///
+/// file format: `port <name>` once, then one `<cc|note> <channel> <number> <target>` binding
+/// per line, `#` starts a comment. eg. `cc 1 74 bpm` or `note 10 36 adjustbpm increase`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MidiMap {
+  pub port: Option<String>,
+  bindings: Vec<(Control, Target)>,
+}
+
+impl MidiMap {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Bind `control`, replacing what it was bound to
+  pub fn bind(&mut self, control: Control, target: Target) {
+    match self.bindings.iter_mut().find(|(c, _)| *c == control) {
+      Some(binding) => binding.1 = target,
+      None => self.bindings.push((control, target)),
+    }
+  }
+
+  pub fn clear(&mut self) {
+    self.bindings.clear();
+  }
+
+  pub fn target(&self, control: Control) -> Option<Target> {
+    self
+      .bindings
+      .iter()
+      .find(|(c, _)| *c == control)
+      .map(|(_, target)| *target)
+  }
+
+  pub fn parse(src: &str) -> Result<Self, String> {
+    let mut map = Self::new();
+
+    for (line_no, line) in src.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      if let Some(port) = line.strip_prefix("port ") {
+        map.port = Some(port.trim().to_string());
+        continue;
+      }
+
+      let err = |what: &str| format!("line {}: invalid {}", line_no + 1, what);
+      let mut fields = line.splitn(4, char::is_whitespace);
+      let (Some(kind), Some(channel), Some(number), Some(target)) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+      else {
+        return Err(format!(
+          "line {}: expected `<cc|note> <channel> <number> <target>`",
+          line_no + 1
+        ));
+      };
+
+      let channel = channel
+        .parse::<u8>()
+        .ok()
+        .filter(|c| (1..=16).contains(c))
+        .ok_or(err("channel"))?
+        - 1;
+      let number = number
+        .parse::<u8>()
+        .ok()
+        .filter(|n| *n < 128)
+        .ok_or(err("number"))?;
+      let control = match kind {
+        "cc" => Control::Cc(channel, number),
+        "note" => Control::Note(channel, number),
+        _ => return Err(err("control")),
+      };
+
+      map.bind(control, Target::parse(target).ok_or(err("target"))?);
+    }
+
+    Ok(map)
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    Self::parse(&src)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, self.to_string()).map_err(|e| e.to_string())
+  }
+}
+
+impl fmt::Display for MidiMap {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if let Some(port) = &self.port {
+      writeln!(f, "port {}", port)?;
+    }
+    writeln!(f, "# control channel number target")?;
+    for (control, target) in self.bindings.iter() {
+      writeln!(f, "{} {}", control, target)?;
+    }
+    Ok(())
+  }
+}
+
+// the mapping is stored next to the text contents, eg. ~/.anupars/midi.map
+pub fn midi_map_path() -> PathBuf {
+  dirs::home_dir()
+    .unwrap_or_default()
+    .join(consts::DEFAULT_APP_DIRECTORY)
+    .join(consts::DEFAULT_MIDI_MAP_FILENAME)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_map_round_trip() {
+    let mut map = MidiMap::new();
+    map.port = Some("nanoKONTROL2 MIDI 1".to_string());
+    map.bind(Control::Cc(0, 74), Target::Param(Param::Bpm));
+    map.bind(Control::Note(9, 36), Target::Command(Command::TogglePlay));
+    map.bind(
+      Control::Cc(0, 20),
+      Target::Command(Command::AdjustBPM(Adjustment::Increase)),
+    );
+
+    let parsed = MidiMap::parse(&map.to_string()).unwrap();
+    assert_eq!(parsed, map);
+    assert_eq!(
+      parsed.target(Control::Note(9, 36)),
+      Some(Target::Command(Command::TogglePlay))
+    );
+  }
+
+  #[test]
+  fn test_learning_again_replaces_the_binding() {
+    let mut map = MidiMap::parse("cc 1 74 bpm\ncc 1 74 gate # learned again\n").unwrap();
+    assert_eq!(
+      map.target(Control::Cc(0, 74)),
+      Some(Target::Param(Param::Gate))
+    );
+    assert_eq!(map.to_string().matches("cc 1 74").count(), 1);
+
+    map.clear();
+    assert_eq!(map.target(Control::Cc(0, 74)), None);
+    assert!(MidiMap::parse("cc 17 74 bpm").is_err());
+    assert!(MidiMap::parse("cc 1 74 nothing").is_err());
+  }
+
+  #[test]
+  fn test_control_values() {
+    assert_eq!(
+      Control::from_bytes(&[0xB1, 7, 100]),
+      Some((Control::Cc(1, 7), 100))
+    );
+    assert_eq!(
+      Control::from_bytes(&[0x80, 36, 64]),
+      Some((Control::Note(0, 36), 0))
+    );
+    assert_eq!(Control::from_bytes(&[0xF8]), None);
+
+    assert_eq!(scale_value(0, 40, 240), 40);
+    assert_eq!(scale_value(127, 40, 240), 240);
+    assert_eq!(scale_value(64, 0, 2), 1);
+  }
+}
@@ -0,0 +1,119 @@
+use midir::{MidiOutputConnection, SendError};
+
+use super::consts;
+
+/// One opened output port, every enabled output receives the same notes and clock
+pub struct Output {
+  pub name: String,
+  pub enabled: bool,
+  pub channel: Option<u8>, // every channel message is moved to this channel (0-15), `None` keeps it
+  pub is_virtual: bool,
+  connection: MidiOutputConnection,
+}
+
+impl Output {
+  pub fn new(name: String, connection: MidiOutputConnection, is_virtual: bool) -> Self {
+    Self {
+      name,
+      enabled: true,
+      channel: None,
+      is_virtual,
+      connection,
+    }
+  }
+
+  pub fn send(&mut self, bytes: &[u8]) -> Result<(), SendError> {
+    if !self.enabled {
+      return Ok(());
+    }
+    self.connection.send(&remap_channel(bytes, self.channel))
+  }
+
+  /// Send without the channel remap, MPE notes keep the member channel they were given
+  pub fn send_unmapped(&mut self, bytes: &[u8]) -> Result<(), SendError> {
+    if !self.enabled {
+      return Ok(());
+    }
+    self.connection.send(bytes)
+  }
+
+  /// Silence every channel, even when disabled, so nothing hangs after a remap or mute
+  pub fn all_notes_off(&mut self) {
+    for channel in 0..16 {
+      // CC 123: All Notes Off
+      let _ = self.connection.send(&[0xB0 + channel, 123, 0]);
+      // CC 120: All Sound Off (for good measure)
+      let _ = self.connection.send(&[0xB0 + channel, 120, 0]);
+    }
+  }
+
+  pub fn label(&self) -> String {
+    let mut label = self.name.clone();
+    if self.is_virtual {
+      label.push_str(" (virtual)");
+    }
+    if let Some(channel) = self.channel {
+      label.push_str(&format!(" [ch {}]", channel + 1));
+    }
+    if !self.enabled {
+      label.push_str(" (off)");
+    }
+    label
+  }
+}
+
+/// Move a channel voice message to `channel`, system messages (eg. clock) are left untouched
+pub fn remap_channel(bytes: &[u8], channel: Option<u8>) -> Vec<u8> {
+  match (bytes, channel) {
+    ([status, rest @ ..], Some(channel)) if (0x80..0xF0).contains(status) => {
+      let mut remapped = vec![(status & 0xF0) | (channel & 0x0F)];
+      remapped.extend_from_slice(rest);
+      remapped
+    }
+    _ => bytes.to_vec(),
+  }
+}
+
+/// Silence every output, see `Output::all_notes_off`
+pub fn all_notes_off(outputs: &mut [Output]) {
+  for output in outputs.iter_mut() {
+    output.all_notes_off();
+  }
+}
+
+pub fn build_outputs_label(outputs: &[Output]) -> String {
+  if outputs.is_empty() {
+    return consts::NO_MIDI_OUTPUT_LABEL.to_string();
+  }
+  outputs
+    .iter()
+    .map(Output::label)
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_remap_channel_voice_messages() {
+    assert_eq!(
+      remap_channel(&[0x90, 60, 100], Some(3)),
+      vec![0x93, 60, 100]
+    );
+    assert_eq!(remap_channel(&[0x85, 60, 0], Some(0)), vec![0x80, 60, 0]);
+    assert_eq!(remap_channel(&[0xB2, 123, 0], Some(15)), vec![0xBF, 123, 0]);
+    assert_eq!(remap_channel(&[0x92, 60, 100], None), vec![0x92, 60, 100]);
+  }
+
+  #[test]
+  fn test_remap_channel_keeps_system_messages() {
+    assert_eq!(remap_channel(&[0xF8], Some(3)), vec![0xF8]);
+    assert_eq!(
+      remap_channel(&[0xF2, 0x48, 0x01], Some(3)),
+      vec![0xF2, 0x48, 0x01]
+    );
+    assert_eq!(remap_channel(&[], Some(3)), Vec::<u8>::new());
+  }
+}
@@ -1,15 +1,25 @@
+pub mod channel_msg;
 pub mod command;
 pub mod command_handler;
 pub mod consts;
 pub mod disspress;
 pub mod midi;
+pub mod midi_input;
+pub mod midi_learn;
+pub mod midi_output;
+pub mod mpe;
 pub mod parser;
 pub mod playback_modes;
 pub mod position;
+pub mod recorder;
 pub mod rect;
 pub mod regex;
+pub mod render;
 pub mod scale;
+pub mod smf;
 pub mod stack;
 pub mod timing;
 pub mod traits;
+pub mod transpose;
+pub mod tuning;
 pub mod utils;
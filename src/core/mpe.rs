@@ -0,0 +1,191 @@
+//! MIDI Polyphonic Expression (MPE): every note is played on a member channel of its own, so
+//! its pitch bend, timbre (CC74) and pressure can move without touching the other notes.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use super::channel_msg::{pitch_bend_msg, spread};
+use super::consts;
+
+static CC_TIMBRE: u8 = 74;
+// registered parameters
+static RPN_PITCH_BEND_RANGE: u8 = 0;
+static RPN_MPE_CONFIGURATION: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoneKind {
+  Lower, // master channel 1, members from channel 2 up
+  Upper, // master channel 16, members from channel 15 down
+}
+
+/// A zone: its master channel and how many member channels notes rotate over
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Zone {
+  pub kind: ZoneKind,
+  pub members: u8,
+  pub pitch_bend_range: u8, // semitones, on the member channels
+}
+
+impl Zone {
+  pub fn new(kind: ZoneKind, members: u8, pitch_bend_range: u8) -> Self {
+    Self {
+      kind,
+      members: members.clamp(1, consts::MAX_MPE_MEMBERS),
+      pitch_bend_range: pitch_bend_range.clamp(1, 96),
+    }
+  }
+
+  pub fn master_channel(&self) -> u8 {
+    match self.kind {
+      ZoneKind::Lower => 0,
+      ZoneKind::Upper => 15,
+    }
+  }
+
+  pub fn member_channels(&self) -> Vec<u8> {
+    match self.kind {
+      ZoneKind::Lower => (1..=self.members).collect(),
+      ZoneKind::Upper => (15 - self.members..15).rev().collect(),
+    }
+  }
+
+  /// MPE Configuration Message on the master channel, then the pitch bend range of the members
+  pub fn configuration(&self) -> Vec<[u8; 3]> {
+    let mut msgs = rpn(self.master_channel(), RPN_MPE_CONFIGURATION, self.members);
+    for channel in self.member_channels() {
+      msgs.extend(rpn(channel, RPN_PITCH_BEND_RANGE, self.pitch_bend_range));
+    }
+    msgs
+  }
+
+  /// Turn the zone off on the receiver, a configuration without members
+  pub fn release(&self) -> Vec<[u8; 3]> {
+    rpn(self.master_channel(), RPN_MPE_CONFIGURATION, 0)
+  }
+}
+
+impl fmt::Display for Zone {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let kind = match self.kind {
+      ZoneKind::Lower => "lower",
+      ZoneKind::Upper => "upper",
+    };
+    write!(
+      f,
+      "{}, {} ch, {} st",
+      kind, self.members, self.pitch_bend_range
+    )
+  }
+}
+
+// set a registered parameter, then deselect it so later data entries go nowhere
+fn rpn(channel: u8, parameter: u8, value: u8) -> Vec<[u8; 3]> {
+  let cc = 0xB0 | (channel & 0x0F);
+  vec![
+    [cc, 101, 0],
+    [cc, 100, parameter],
+    [cc, 6, value],
+    [cc, 38, 0],
+    [cc, 101, 127],
+    [cc, 100, 127],
+  ]
+}
+
+/// Hands out member channels in turn, skipping the ones still holding a note
+#[derive(Debug, Default)]
+pub struct ChannelRotation {
+  next: usize,
+}
+
+impl ChannelRotation {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The channel for a new note, the next busy one in turn when they are all sounding
+  pub fn assign(&mut self, members: &[u8], busy: &HashSet<u8>) -> Option<u8> {
+    let len = members.len();
+    if len == 0 {
+      return None;
+    }
+    let idx = (0..len)
+      .map(|i| (self.next + i) % len)
+      .find(|idx| !busy.contains(&members[*idx]))
+      .unwrap_or(self.next % len);
+    self.next = (idx + 1) % len;
+    Some(members[idx])
+  }
+
+  pub fn reset(&mut self) {
+    self.next = 0;
+  }
+}
+
+/// Per-note expression of a note on `channel` from the cell under the playhead: the bend from
+/// where it is inside the match, the timbre from the row (brighter at the top) and the pressure
+/// from the column
+pub fn expression(
+  channel: u8,
+  (x, y): (usize, usize),
+  (grid_width, grid_height): (usize, usize),
+  pitch_bend: u16,
+) -> Vec<Vec<u8>> {
+  let channel = channel & 0x0F;
+  vec![
+    pitch_bend_msg(channel, pitch_bend),
+    vec![0xB0 | channel, CC_TIMBRE, 127 - spread(y, grid_height)],
+    vec![0xD0 | channel, spread(x, grid_width)],
+  ]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_zone_channels_and_configuration() {
+    let lower = Zone::new(ZoneKind::Lower, 3, 48);
+    assert_eq!(lower.master_channel(), 0);
+    assert_eq!(lower.member_channels(), vec![1, 2, 3]);
+
+    let upper = Zone::new(ZoneKind::Upper, 20, 2);
+    assert_eq!(upper.members, 15);
+    assert_eq!(upper.member_channels().first(), Some(&14));
+    assert_eq!(upper.member_channels().last(), Some(&0));
+
+    let configuration = lower.configuration();
+    // RPN 6 = 3 members on channel 1, then RPN 0 on each member
+    assert_eq!(
+      configuration[..3],
+      [[0xB0, 101, 0], [0xB0, 100, 6], [0xB0, 6, 3]]
+    );
+    assert_eq!(configuration.len(), 6 * 4);
+    assert_eq!(configuration[8], [0xB1, 6, 48]);
+    assert_eq!(lower.release()[2], [0xB0, 6, 0]);
+  }
+
+  #[test]
+  fn test_channels_rotate_over_free_members() {
+    let members = [1, 2, 3];
+    let mut rotation = ChannelRotation::new();
+    let mut busy = HashSet::new();
+
+    assert_eq!(rotation.assign(&members, &busy), Some(1));
+    assert_eq!(rotation.assign(&members, &busy), Some(2));
+    // channel 3 still sounds, skip it
+    busy.insert(3);
+    assert_eq!(rotation.assign(&members, &busy), Some(1));
+    // everything sounds, take the next in turn
+    busy.extend([1, 2]);
+    assert_eq!(rotation.assign(&members, &busy), Some(2));
+    assert_eq!(rotation.assign(&[], &busy), None);
+  }
+
+  #[test]
+  fn test_expression_from_the_cell() {
+    let msgs = expression(2, (9, 0), (10, 5), 0x2000);
+    assert_eq!(msgs[0], vec![0xE2, 0x00, 0x40]);
+    assert_eq!(msgs[1], vec![0xB2, 74, 127]);
+    assert_eq!(msgs[2], vec![0xD2, 127]);
+  }
+}
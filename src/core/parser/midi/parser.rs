@@ -1,19 +1,36 @@
+use nom::branch::alt;
 use nom::bytes::complete::tag;
+use nom::bytes::complete::take_while1;
 use nom::character::complete::digit1;
 use nom::character::complete::one_of;
 use nom::character::streaming::space1;
+use nom::combinator::map;
 use nom::combinator::map_res;
 use nom::combinator::opt;
+use nom::combinator::value;
 use nom::multi::separated_list1;
+use nom::sequence::preceded;
 use nom::sequence::tuple;
 use nom::IResult;
 
+use crate::core::channel_msg::{CcValue, ChannelMsgKind};
+
 type MidiParser = (Vec<(String, u8)>, Vec<u8>, Vec<u8>, u8);
+type ControlParser = (Vec<(ChannelMsgKind, Option<String>)>, u8);
+
+// the highest octave with a MIDI note in it, G8 is 127 with C3 at 60
+const MAX_OCTAVE: u8 = 8;
 
 fn parse_note_octave(input: &str) -> IResult<&str, (String, u8)> {
   let (input, note) = one_of("CDEFGAB")(input)?; // Parse note (C, D, E, F, G, A, B)
   let (input, sharp) = opt(tag("#"))(input)?; // Parse optional sharp symbol (#)
   let (input, octave) = map_res(digit1, |s: &str| s.parse::<u8>())(input)?; // Parse octave
+  if octave > MAX_OCTAVE {
+    return Err(nom::Err::Error(nom::error::Error {
+      input,
+      code: nom::error::ErrorKind::Digit,
+    }));
+  }
 
   let note_with_sharp = format!("{}{}", note, sharp.unwrap_or(""));
 
@@ -24,10 +41,11 @@ fn parse_note_octave_array(input: &str) -> IResult<&str, Vec<(String, u8)>> {
   separated_list1(tag(","), parse_note_octave)(input)
 }
 
+// 1-16, as the channels are counted on the Outputs menu
 fn parse_midi_channel(input: &str) -> IResult<&str, u8> {
   let (input, channel) = map_res(digit1, |s: &str| s.parse::<u8>())(input)?;
 
-  if channel <= 16 {
+  if (1..=16).contains(&channel) {
     Ok((input, channel))
   } else {
     Err(nom::Err::Error(nom::error::Error {
@@ -92,6 +110,75 @@ pub fn parse_midi_msg(input: &str) -> IResult<&str, MidiParser> {
   Ok((input, (note_octave, len, vel, channel)))
 }
 
+fn parse_data_byte(input: &str) -> IResult<&str, u8> {
+  let (input, byte) = map_res(digit1, |s: &str| s.parse::<u8>())(input)?;
+
+  if byte <= 127 {
+    Ok((input, byte))
+  } else {
+    Err(nom::Err::Error(nom::error::Error {
+      input,
+      code: nom::error::ErrorKind::Eof,
+    }))
+  }
+}
+
+// `x`, `y`, `chr` or a value
+fn parse_cc_value(input: &str) -> IResult<&str, CcValue> {
+  alt((
+    value(CcValue::X, tag("x")),
+    value(CcValue::Y, tag("y")),
+    value(CcValue::Char, tag("chr")),
+    map(parse_data_byte, CcValue::Fixed),
+  ))(input)
+}
+
+// `cc74:x`, `pb` or `pc12`
+fn parse_control_kind(input: &str) -> IResult<&str, ChannelMsgKind> {
+  alt((
+    map(
+      tuple((
+        preceded(tag("cc"), parse_data_byte),
+        preceded(tag(":"), parse_cc_value),
+      )),
+      |(controller, value)| ChannelMsgKind::Cc(controller, value),
+    ),
+    value(ChannelMsgKind::PitchBend, tag("pb")),
+    map(
+      preceded(tag("pc"), parse_data_byte),
+      ChannelMsgKind::ProgramChange,
+    ),
+  ))(input)
+}
+
+// a control, optionally bound to a named group: `cc74:chr@vowel`
+fn parse_control(input: &str) -> IResult<&str, (ChannelMsgKind, Option<String>)> {
+  let (input, kind) = parse_control_kind(input)?;
+  let group_name = take_while1(|c: char| c.is_alphanumeric() || c == '_');
+  let (input, group) = opt(preceded(tag("@"), group_name))(input)?;
+
+  Ok((input, (kind, group.map(str::to_string))))
+}
+
+fn parse_control_array(input: &str) -> IResult<&str, Vec<(ChannelMsgKind, Option<String>)>> {
+  separated_list1(tag(","), parse_control)(input)
+}
+
+/// Controls and their channel, eg. `cc74:x,pb@bend,pc5 1`
+pub fn parse_control_msg(input: &str) -> IResult<&str, ControlParser> {
+  let (input, (controls, _, channel)) =
+    tuple((parse_control_array, space1, parse_midi_channel))(input)?;
+
+  if !input.is_empty() {
+    return Err(nom::Err::Error(nom::error::Error {
+      input,
+      code: nom::error::ErrorKind::Eof,
+    }));
+  }
+
+  Ok((input, (controls, channel)))
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -157,17 +244,25 @@ mod tests {
 
   #[test]
   fn test_parse_midi_msg_max_values() {
-    let input = "G9 127 127 16";
+    let input = "G8 127 127 16";
     let result = parse_midi_msg(input);
     assert!(result.is_ok());
     let (remaining, (notes, len, vel, channel)) = result.unwrap();
     assert_eq!(remaining, "");
-    assert_eq!(notes, vec![("G".to_string(), 9)]);
+    assert_eq!(notes, vec![("G".to_string(), 8)]);
     assert_eq!(len, vec![127]);
     assert_eq!(vel, vec![127]);
     assert_eq!(channel, 16);
   }
 
+  #[test]
+  fn test_parse_midi_msg_invalid_octave_and_channel_zero() {
+    assert!(parse_midi_msg("G9 64 100 1").is_err());
+    assert!(parse_midi_msg("C25 64 100 1").is_err());
+    assert!(parse_midi_msg("C4 64 100 0").is_err());
+    assert!(parse_control_msg("pb 0").is_err());
+  }
+
   #[test]
   fn test_parse_midi_msg_invalid_channel_too_high() {
     let input = "C4 64 100 17";
@@ -406,4 +501,37 @@ mod tests {
     assert_eq!(vel, vec![100, 80]);
     assert_eq!(channel, 10);
   }
+
+  #[test]
+  fn test_parse_control_msg() {
+    let input = "cc74:x,cc1:64,cc11:chr@first_vel,pb@bend,pc12 3";
+    let result = parse_control_msg(input);
+    assert!(result.is_ok());
+    let (remaining, (controls, channel)) = result.unwrap();
+    assert_eq!(remaining, "");
+    assert_eq!(
+      controls,
+      vec![
+        (ChannelMsgKind::Cc(74, CcValue::X), None),
+        (ChannelMsgKind::Cc(1, CcValue::Fixed(64)), None),
+        (
+          ChannelMsgKind::Cc(11, CcValue::Char),
+          Some("first_vel".to_string())
+        ),
+        (ChannelMsgKind::PitchBend, Some("bend".to_string())),
+        (ChannelMsgKind::ProgramChange(12), None),
+      ]
+    );
+    assert_eq!(channel, 3);
+  }
+
+  #[test]
+  fn test_parse_control_msg_invalid() {
+    assert!(parse_control_msg("cc128:x 1").is_err());
+    assert!(parse_control_msg("cc74 1").is_err());
+    assert!(parse_control_msg("cc74:z 1").is_err());
+    assert!(parse_control_msg("pc200 1").is_err());
+    assert!(parse_control_msg("pb 17").is_err());
+    assert!(parse_control_msg("pb").is_err());
+  }
 }
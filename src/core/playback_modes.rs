@@ -7,10 +7,58 @@
 //! - Arpeggiator: Pattern-based movement through regex matches
 
 use cursive::Vec2;
+use num::rational::Ratio;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::Hash;
 use std::hash::Hasher;
 
+// clock beats are quarter notes
+static BEATS_PER_WHOLE_NOTE: i64 = 4;
+
+/// Length of one marker step, in clock ticks
+pub fn ticks_per_step(
+  ticks_per_beat: Ratio<i64>,
+  (numerator, denominator): (i64, usize),
+) -> Ratio<i64> {
+  Ratio::new(numerator * BEATS_PER_WHOLE_NOTE, denominator.max(1) as i64) * ticks_per_beat
+}
+
+/// Index of the marker step sounding at `tick`.
+///
+/// `ratio` is the step length as a fraction of a whole note, eg. 1/16, 1/12 for eighth
+/// triplets or 3/32 for dotted sixteenths. A non-zero `loop_length` restarts the steps every
+/// `loop_length` steps, independently of the bar, so markers can run in polymeter.
+pub fn step_at(
+  tick: usize,
+  ticks_per_beat: Ratio<i64>,
+  (numerator, denominator): (i64, usize),
+  loop_length: usize,
+) -> usize {
+  let ticks_per_step = ticks_per_step(ticks_per_beat, (numerator, denominator));
+  if ticks_per_step <= Ratio::from_integer(0) {
+    return 0;
+  }
+
+  let step = (Ratio::from_integer(tick as i64) / ticks_per_step)
+    .floor()
+    .to_integer() as usize;
+
+  match loop_length {
+    0 => step,
+    len => step % len,
+  }
+}
+
+/// Active position to restart a loop from, so that its first step lands on the first cell
+pub fn loop_start_position(marker_h: usize, reverse: bool) -> Vec2 {
+  if reverse {
+    // stepping back from the top row wraps to the bottom row
+    Vec2::new(0, 0)
+  } else {
+    Vec2::new(0, marker_h.saturating_sub(1))
+  }
+}
+
 /// Calculate position for normal (forward) sequential mode
 pub fn calculate_normal_position(
   adjusted_pos: usize,
@@ -129,6 +177,38 @@ mod tests {
     assert_eq!(pos.x, 3); // 4 - 1 - 0 = 3
   }
 
+  #[test]
+  fn test_step_at_exact_triplets_and_dotted_notes() {
+    let ppqn = Ratio::from_integer(96);
+    // a sixteenth is 24 ticks, an eighth triplet 32, a dotted sixteenth 36
+    assert_eq!(step_at(23, ppqn, (1, 16), 0), 0);
+    assert_eq!(step_at(24, ppqn, (1, 16), 0), 1);
+    assert_eq!(step_at(95, ppqn, (1, 12), 0), 2);
+    assert_eq!(step_at(96, ppqn, (1, 12), 0), 3);
+    assert_eq!(step_at(72, ppqn, (3, 32), 0), 2);
+    assert_eq!(step_at(71, ppqn, (3, 32), 0), 1);
+  }
+
+  #[test]
+  fn test_step_at_loops_independently_of_the_bar() {
+    let ppqn = Ratio::from_integer(96);
+    // 5 sixteenths against a bar of 16: the first step comes back every 120 ticks
+    assert_eq!(step_at(119, ppqn, (1, 16), 5), 4);
+    assert_eq!(step_at(120, ppqn, (1, 16), 5), 0);
+    assert_eq!(step_at(384, ppqn, (1, 16), 5), 1);
+  }
+
+  #[test]
+  fn test_loop_start_lands_on_first_cell() {
+    let mut pos = loop_start_position(3, false);
+    calculate_normal_position(0, 4, 3, &mut pos);
+    assert_eq!(pos, Vec2::new(0, 0));
+
+    let mut pos = loop_start_position(3, true);
+    calculate_reverse_position(0, 4, 3, &mut pos);
+    assert_eq!(pos, Vec2::new(3, 2));
+  }
+
   #[test]
   fn test_random_is_deterministic() {
     let mut pos1 = Vec2::new(0, 0);
@@ -0,0 +1,190 @@
+use num::rational::Ratio;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::consts;
+use super::smf::{Event, Smf, Track};
+use super::timing::clock::{Signature, Tick};
+
+/// Taps the messages sent to the outputs into a type 1 SMF, one track per MIDI channel.
+///
+/// Time is counted in clock ticks, at the ticks per beat the recording started with, and keeps
+/// running forward when the clock goes back (loop, stop/start) so a take is one continuous jam.
+pub struct Recorder {
+  recording: bool,
+  division: i64, // ticks per beat of the file
+  signature: Signature,
+  tempo: usize,
+  last_tick: Option<usize>, // last clock tick seen
+  elapsed: Tick,            // in file ticks
+  events: usize,
+  conductor: Track,
+  channels: BTreeMap<u8, Track>,
+}
+
+impl Recorder {
+  pub fn new(signature: Signature, tempo: usize) -> Self {
+    let mut recorder = Self {
+      recording: true,
+      division: signature.ticks_per_beat.to_integer().max(1),
+      signature,
+      tempo,
+      last_tick: None,
+      elapsed: Ratio::from_integer(0),
+      events: 0,
+      conductor: Track::new(),
+      channels: BTreeMap::new(),
+    };
+    recorder.conductor.push(0, time_signature(signature));
+    recorder.conductor.push(0, Event::Tempo(tempo as u32));
+    recorder
+  }
+
+  pub fn is_recording(&self) -> bool {
+    self.recording
+  }
+
+  /// Channel messages recorded so far
+  pub fn len(&self) -> usize {
+    self.events
+  }
+
+  pub fn stop(&mut self) {
+    self.recording = false;
+  }
+
+  /// Follow the clock, called on every tick before the messages sent on it
+  pub fn advance(&mut self, tick: usize) {
+    if let Some(last_tick) = self.last_tick.filter(|last_tick| tick > *last_tick) {
+      let ticks = Ratio::from_integer((tick - last_tick) as i64);
+      self.elapsed += ticks * self.division / self.signature.ticks_per_beat;
+    }
+    self.last_tick = Some(tick);
+  }
+
+  /// Record a channel message (note-on/off, CC, ...) at the current time
+  pub fn record(&mut self, bytes: &[u8]) {
+    let Some(status) = bytes
+      .first()
+      .filter(|status| (0x80..0xF0).contains(*status))
+    else {
+      return;
+    };
+    if !self.recording {
+      return;
+    }
+    let channel = status & 0x0F;
+    let now = self.now();
+    self
+      .channels
+      .entry(channel)
+      .or_insert_with(|| {
+        let mut track = Track::new();
+        track.push(0, Event::TrackName(format!("ch {}", channel + 1)));
+        track
+      })
+      .push(now, Event::Midi(bytes.to_vec()));
+    self.events += 1;
+  }
+
+  pub fn set_tempo(&mut self, tempo: usize) {
+    if self.recording && tempo != self.tempo {
+      self.tempo = tempo;
+      let now = self.now();
+      self.conductor.push(now, Event::Tempo(tempo as u32));
+    }
+  }
+
+  pub fn set_signature(&mut self, signature: Signature) {
+    if self.recording {
+      self.signature = signature;
+      let now = self.now();
+      self.conductor.push(now, time_signature(signature));
+    }
+  }
+
+  pub fn to_smf(&self) -> Smf {
+    let mut smf = Smf::new(self.division.clamp(1, u16::MAX as i64) as u16);
+    smf.tracks.push(self.conductor.clone());
+    smf.tracks.extend(self.channels.values().cloned());
+    smf
+  }
+
+  fn now(&self) -> u64 {
+    self.elapsed.floor().to_integer() as u64
+  }
+}
+
+// a beat is a quarter note, like for the MIDI clock
+fn time_signature(signature: Signature) -> Event {
+  let beats_per_bar = signature
+    .beats_per_bar
+    .to_integer()
+    .clamp(1, u8::MAX as i64);
+  Event::TimeSignature(beats_per_bar as u8, 2)
+}
+
+/// Where a take named `name` is written, relative names go to ~/.anupars/recordings
+pub fn recording_path(name: &str) -> PathBuf {
+  let path = Path::new(name);
+  let mut path = match path.is_absolute() {
+    true => path.to_path_buf(),
+    false => dirs::home_dir()
+      .unwrap_or_default()
+      .join(consts::DEFAULT_APP_DIRECTORY)
+      .join(consts::DEFAULT_RECORDING_DIRNAME)
+      .join(path),
+  };
+  if path.extension().is_none() {
+    path.set_extension("mid");
+  }
+  path
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_time_keeps_running_when_the_clock_rewinds() {
+    let mut recorder = Recorder::new(Signature::default(), 120);
+    recorder.advance(0);
+    recorder.record(&[0x90, 60, 100]);
+    recorder.advance(48);
+    recorder.record(&[0x80, 60, 0]);
+    // stop/start, the clock is back at 0
+    recorder.advance(0);
+    recorder.advance(24);
+    recorder.record(&[0x91, 64, 100]);
+    // clock messages are not recorded
+    recorder.record(&[0xF8]);
+
+    assert_eq!(recorder.len(), 3);
+    assert_eq!(recorder.now(), 72);
+
+    let smf = recorder.to_smf();
+    // conductor, channel 1 and channel 2
+    assert_eq!(smf.tracks.len(), 3);
+  }
+
+  #[test]
+  fn test_nothing_is_recorded_once_stopped() {
+    let mut recorder = Recorder::new(Signature::default(), 120);
+    recorder.record(&[0x90, 60, 100]);
+    recorder.stop();
+    recorder.record(&[0x80, 60, 0]);
+    recorder.set_tempo(140);
+
+    assert!(!recorder.is_recording());
+    assert_eq!(recorder.len(), 1);
+  }
+
+  #[test]
+  fn test_recording_path() {
+    assert!(recording_path("jam").ends_with("recordings/jam.mid"));
+    assert_eq!(
+      recording_path("/tmp/jam.midi"),
+      PathBuf::from("/tmp/jam.midi")
+    );
+  }
+}
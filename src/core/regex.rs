@@ -22,6 +22,7 @@ struct RegexError {
 
 #[derive(Debug, Clone)]
 struct MatchGroup {
+  name: Option<String>,
   s: String,
 }
 
@@ -29,9 +30,112 @@ struct MatchGroup {
 pub struct Match {
   i: usize,
   l: usize,
+  s: String,
   groups: Vec<MatchGroup>,
 }
 
+/// Note parameters carried by the named groups of a match.
+///
+/// - `(?P<vel>..)`: the captured digits as velocity (0-127), or 16 per captured char without digits
+/// - `(?P<note>..)`: a pitch class spelled `a`-`g`, optionally followed by `#` or `b`
+/// - `(?P<len>..)`: the captured digits as length in steps, or the captured char count without digits
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct NoteParams {
+  pub velocity: Option<u8>,
+  pub note: Option<u8>, // pitch class, 0-11
+  pub length: Option<u8>,
+  pub span: usize, // grid cells covered by the whole match
+}
+
+impl Match {
+  /// Cells from the start of the match to `grid_index`, when the match covers it
+  /// (a match running over a line break is counted without the padding of the row)
+  pub fn offset_of(&self, grid_index: usize) -> Option<usize> {
+    (self.i..self.i + self.l)
+      .contains(&grid_index)
+      .then(|| grid_index - self.i)
+  }
+
+  /// Grid index of the first cell of the match
+  pub fn start(&self) -> usize {
+    self.i
+  }
+
+  /// Cells covered by the match
+  pub fn span(&self) -> usize {
+    self.l
+  }
+
+  /// The char `offset` cells into the match
+  pub fn char_at(&self, offset: usize) -> Option<char> {
+    self.s.chars().filter(|&c| c != '\n').nth(offset)
+  }
+
+  /// The named groups that took part in the match, with the first char they captured
+  pub fn group_chars(&self) -> Vec<(String, char)> {
+    self
+      .groups
+      .iter()
+      .filter_map(|group| Some((group.name.clone()?, group.s.chars().next()?)))
+      .collect()
+  }
+
+  pub fn note_params(&self) -> NoteParams {
+    let mut params = NoteParams {
+      span: self.l,
+      ..NoteParams::default()
+    };
+    for group in &self.groups {
+      match group.name.as_deref() {
+        Some("vel") => params.velocity = parse_velocity(&group.s),
+        Some("note") => params.note = parse_pitch_class(&group.s),
+        Some("len") => params.length = parse_length(&group.s),
+        _ => (),
+      }
+    }
+    params
+  }
+}
+
+fn parse_digits(s: &str) -> Option<usize> {
+  let digits: String = s.chars().filter(char::is_ascii_digit).collect();
+  digits.parse::<usize>().ok()
+}
+
+fn parse_velocity(s: &str) -> Option<u8> {
+  let velocity = match parse_digits(s) {
+    Some(value) => value,
+    None if s.is_empty() => return None,
+    None => s.chars().count() * 16,
+  };
+  Some(velocity.min(127) as u8)
+}
+
+fn parse_pitch_class(s: &str) -> Option<u8> {
+  let mut chars = s.trim().chars();
+  let natural = match chars.next()?.to_ascii_lowercase() {
+    'c' => 0,
+    'd' => 2,
+    'e' => 4,
+    'f' => 5,
+    'g' => 7,
+    'a' => 9,
+    'b' => 11,
+    _ => return None,
+  };
+  let pitch_class = match chars.next() {
+    Some('#') => natural + 1,
+    Some('b') => natural + 11,
+    _ => natural,
+  };
+  Some(pitch_class % 12)
+}
+
+fn parse_length(s: &str) -> Option<u8> {
+  let length = parse_digits(s).unwrap_or_else(|| s.chars().count());
+  (length > 0).then(|| length.min(127) as u8)
+}
+
 #[derive(Debug, Clone)]
 pub enum Message {
   Solve(EventData),
@@ -122,12 +226,14 @@ impl RegExpHandler {
         for cap in regex.captures_iter(text) {
           let groups: Vec<MatchGroup> = cap
             .iter()
+            .zip(regex.capture_names())
             .enumerate()
-            .filter_map(|(i, s)| {
+            .filter_map(|(i, (s, name))| {
               if i == 0 {
                 None
               } else {
                 Some(MatchGroup {
+                  name: name.map(str::to_string),
                   s: s?.as_str().to_string(),
                 })
               }
@@ -150,6 +256,7 @@ impl RegExpHandler {
             Match {
               i: grid_index,
               l: grid_length,
+              s: match_str.to_string(),
               groups,
             },
           );
@@ -166,6 +273,11 @@ impl RegExpHandler {
     }
   }
 
+  /// Matches by grid index, or the syntax error of the pattern
+  pub fn find_matches(data: &EventData) -> Result<HashMap<usize, Match>, String> {
+    Self::process_event(data).map_err(|err| err.message)
+  }
+
   pub fn run(self) {
     for control_message in &self.rx {
       match control_message {
@@ -233,3 +345,66 @@ impl RegExpHandler {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn solve(pattern: &str, text: &str) -> HashMap<usize, Match> {
+    RegExpHandler::process_event(&EventData {
+      text: text.to_string(),
+      pattern: pattern.to_string(),
+      flags: String::new(),
+      grid_width: 80,
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn test_named_groups_set_note_params() {
+    let matches = solve(r"(?P<note>[a-g]#?)(?P<vel>\d+)/(?P<len>x+)", "c#90/xxx");
+    assert_eq!(
+      matches[&0].note_params(),
+      NoteParams {
+        velocity: Some(90),
+        note: Some(1),
+        length: Some(3),
+        span: 8,
+      }
+    );
+  }
+
+  #[test]
+  fn test_unnamed_or_missing_groups_keep_defaults() {
+    let matches = solve(r"(\w+)(?P<vel>\d)?", "abc");
+    assert_eq!(
+      matches[&0].note_params(),
+      NoteParams {
+        span: 3,
+        ..NoteParams::default()
+      }
+    );
+  }
+
+  #[test]
+  fn test_cells_and_groups_of_a_match() {
+    let matches = solve(r"(?P<word>b\w+)", "a brown fox");
+    let matched = &matches[&2];
+    assert_eq!(matched.offset_of(2), Some(0));
+    assert_eq!(matched.offset_of(6), Some(4));
+    assert_eq!(matched.offset_of(7), None);
+    assert_eq!(matched.char_at(1), Some('r'));
+    assert_eq!(matched.group_chars(), vec![("word".to_string(), 'b')]);
+  }
+
+  #[test]
+  fn test_note_param_rules() {
+    assert_eq!(parse_velocity("300"), Some(127));
+    assert_eq!(parse_velocity("!!!"), Some(48));
+    assert_eq!(parse_pitch_class("Bb"), Some(10));
+    assert_eq!(parse_pitch_class("cb"), Some(11));
+    assert_eq!(parse_pitch_class("h"), None);
+    assert_eq!(parse_length("2"), Some(2));
+    assert_eq!(parse_length("0"), None);
+  }
+}
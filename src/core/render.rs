@@ -0,0 +1,543 @@
+//! Offline render: run the marker over a text for a number of bars, faster than real time,
+//! and write what it plays to a Standard MIDI File, without the TUI.
+//!
+//! The marker and the MIDI engine are the ones the app runs, driven tick by tick from here
+//! instead of by the metronome, so a render plays exactly what the sequencer would.
+
+use cursive::Vec2;
+use num::rational::Ratio;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+
+use super::midi::{self, Midi};
+use super::rect::Rect;
+use super::regex::{EventData, RegExpHandler};
+use super::scale::ScaleMode;
+use super::smf::Smf;
+use super::timing::clock::{Signature, Timer, TimerEvent};
+use super::timing::source::VirtualTime;
+use crate::view::common::playhead::{self, MarkerArea};
+
+pub static USAGE: &str = "\
+usage: anupars render <text file> --regex <pattern> [options]
+
+  --regex <pattern>      regular expression to match the text with
+  --flags <flags>        regex flags, eg. `im` (i, m, s, x, U)
+  --grid <w>x<h>         grid size, the text wraps at its width (default 80x24)
+  --marker <x>,<y>,<w>,<h>  marker rect on the grid (default: the whole grid)
+  --scale-left <name>    scale of vertical steps, eg. `major`, `minor-pentatonic` (default chromatic)
+  --scale-top <name>     scale of horizontal steps (default chromatic)
+  --bpm <n>              tempo written to the file (default 120)
+  --ratio <n>/<d>        step length as a fraction of a whole note (default 1/16)
+  --loop <n>             restart the marker every n steps, 0 for off (default 0)
+  --region <x>,<y>,<w>,<h>[:<n>/<d>[:<loop>]]
+                         pin a region playing along with the marker, with its own ratio
+                         and loop length (default: the marker's), can be repeated
+  --beats <n>            beats per bar (default 8)
+  --bars <n>             number of bars to render (default 4)
+  --gate <percent>       gate mode, notes last this percent of the step (default off,
+                         notes keep their own length)
+  --reverse, --arpeggiator, --random, --accumulation
+                         playback modes
+  -o, --output <path>    .mid file to write (default render.mid)";
+
+static DEFAULT_GRID_SIZE: (usize, usize) = (80, 24);
+static DEFAULT_BPM: usize = 120;
+static DEFAULT_RATIO: (i64, usize) = (1, 16);
+static DEFAULT_BEATS_PER_BAR: i64 = 8;
+static DEFAULT_BARS: usize = 4;
+static DEFAULT_OUTPUT: &str = "render.mid";
+
+// (rect, ratio, loop length), the marker's ratio and loop length when not given
+pub type RegionArgs = (Rect, Option<(i64, usize)>, Option<usize>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderArgs {
+  pub text_path: PathBuf,
+  pub pattern: String,
+  pub flags: String,
+  pub grid_size: (usize, usize),
+  pub marker: Option<Rect>,
+  pub scale_left: ScaleMode,
+  pub scale_top: ScaleMode,
+  pub bpm: usize,
+  pub ratio: (i64, usize),
+  pub loop_length: usize,
+  pub regions: Vec<RegionArgs>,
+  pub beats_per_bar: i64,
+  pub bars: usize,
+  pub gate_percent: Option<usize>, // gate mode when given
+  pub reverse: bool,
+  pub arpeggiator: bool,
+  pub random: bool,
+  pub accumulation: bool,
+  pub output: PathBuf,
+}
+
+impl RenderArgs {
+  pub fn parse(args: &[String]) -> Result<Self, String> {
+    let mut text_path = None;
+    let mut pattern = None;
+    let mut render = Self {
+      text_path: PathBuf::new(),
+      pattern: String::new(),
+      flags: String::new(),
+      grid_size: DEFAULT_GRID_SIZE,
+      marker: None,
+      scale_left: ScaleMode::default(),
+      scale_top: ScaleMode::default(),
+      bpm: DEFAULT_BPM,
+      ratio: DEFAULT_RATIO,
+      loop_length: 0,
+      regions: Vec::new(),
+      beats_per_bar: DEFAULT_BEATS_PER_BAR,
+      bars: DEFAULT_BARS,
+      gate_percent: None,
+      reverse: false,
+      arpeggiator: false,
+      random: false,
+      accumulation: false,
+      output: PathBuf::from(DEFAULT_OUTPUT),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+      let mut value = || {
+        args
+          .next()
+          .cloned()
+          .ok_or_else(|| format!("missing value for {}", arg))
+      };
+      match arg.as_str() {
+        "--regex" => pattern = Some(value()?),
+        "--flags" => render.flags = value()?,
+        "--grid" => render.grid_size = parse_size(&value()?)?,
+        "--marker" => render.marker = Some(parse_rect(&value()?)?),
+        "--scale-left" => render.scale_left = parse_scale(&value()?)?,
+        "--scale-top" => render.scale_top = parse_scale(&value()?)?,
+        "--bpm" => render.bpm = parse_number(arg, &value()?)?,
+        "--ratio" => render.ratio = parse_ratio(&value()?)?,
+        "--loop" => render.loop_length = parse_number(arg, &value()?)?,
+        "--region" => render.regions.push(parse_region(&value()?)?),
+        "--beats" => render.beats_per_bar = parse_number(arg, &value()?)? as i64,
+        "--bars" => render.bars = parse_number(arg, &value()?)?,
+        "--gate" => render.gate_percent = Some(parse_number(arg, &value()?)?),
+        "--reverse" => render.reverse = true,
+        "--arpeggiator" => render.arpeggiator = true,
+        "--random" => render.random = true,
+        "--accumulation" => render.accumulation = true,
+        "-o" | "--output" => render.output = PathBuf::from(value()?),
+        _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+        _ if text_path.is_none() => text_path = Some(PathBuf::from(arg)),
+        _ => return Err(format!("unexpected argument {}", arg)),
+      }
+    }
+
+    render.text_path = text_path.ok_or("missing text file")?;
+    render.pattern = pattern.ok_or("missing --regex")?;
+
+    let (grid_width, grid_height) = render.grid_size;
+    if grid_width == 0 || grid_height == 0 {
+      return Err("the grid cannot be empty".to_string());
+    }
+    if let Some(marker) = render.marker {
+      if marker.right() >= grid_width || marker.bottom() >= grid_height {
+        return Err("the marker does not fit in the grid".to_string());
+      }
+    }
+    if render
+      .regions
+      .iter()
+      .any(|(rect, _, _)| rect.right() >= grid_width || rect.bottom() >= grid_height)
+    {
+      return Err("a region does not fit in the grid".to_string());
+    }
+    for (name, value) in [
+      ("--bpm", render.bpm),
+      ("--beats", render.beats_per_bar as usize),
+      ("--bars", render.bars),
+    ] {
+      if value == 0 {
+        return Err(format!("{} must be greater than 0", name));
+      }
+    }
+
+    Ok(render)
+  }
+
+  fn signature(&self) -> Signature {
+    Signature {
+      beats_per_bar: Ratio::from_integer(self.beats_per_bar),
+      ..Signature::default()
+    }
+  }
+}
+
+fn parse_number(name: &str, src: &str) -> Result<usize, String> {
+  src
+    .trim()
+    .parse::<usize>()
+    .map_err(|_| format!("invalid {} `{}`", name, src))
+}
+
+// `80x24`
+fn parse_size(src: &str) -> Result<(usize, usize), String> {
+  let err = || format!("invalid grid size `{}`, expected <w>x<h>", src);
+  let (w, h) = src.split_once('x').ok_or_else(err)?;
+  Ok((
+    w.trim().parse().map_err(|_| err())?,
+    h.trim().parse().map_err(|_| err())?,
+  ))
+}
+
+// `x,y,w,h`
+fn parse_rect(src: &str) -> Result<Rect, String> {
+  let err = || format!("invalid marker `{}`, expected <x>,<y>,<w>,<h>", src);
+  let fields = src
+    .split(',')
+    .map(|field| field.trim().parse::<usize>())
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|_| err())?;
+  match fields[..] {
+    [x, y, w, h] if w > 0 && h > 0 => Ok(Rect::from_size((x, y), (w, h))),
+    _ => Err(err()),
+  }
+}
+
+// `x,y,w,h`, `x,y,w,h:1/12` or `x,y,w,h:1/12:5`
+fn parse_region(src: &str) -> Result<RegionArgs, String> {
+  let mut fields = src.split(':');
+  let rect = parse_rect(fields.next().unwrap_or(""))?;
+  let ratio = fields.next().map(parse_ratio).transpose()?;
+  let loop_length = fields
+    .next()
+    .map(|loop_length| parse_number("--region loop", loop_length))
+    .transpose()?;
+  match fields.next() {
+    Some(_) => Err(format!(
+      "invalid region `{}`, expected <x>,<y>,<w>,<h>[:<n>/<d>[:<loop>]]",
+      src
+    )),
+    None => Ok((rect, ratio, loop_length)),
+  }
+}
+
+// `1/16`
+fn parse_ratio(src: &str) -> Result<(i64, usize), String> {
+  let err = || format!("invalid ratio `{}`, expected <n>/<d>", src);
+  let (numerator, denominator) = src.split_once('/').ok_or_else(err)?;
+  let numerator = numerator.trim().parse::<i64>().map_err(|_| err())?;
+  let denominator = denominator.trim().parse::<usize>().map_err(|_| err())?;
+  match numerator > 0 && denominator > 0 {
+    true => Ok((numerator, denominator)),
+    false => Err(err()),
+  }
+}
+
+// the scale name from the menu or its variant, ignoring case, spaces and dashes
+fn parse_scale(src: &str) -> Result<ScaleMode, String> {
+  let normalize = |name: &str| {
+    name
+      .chars()
+      .filter(char::is_ascii_alphanumeric)
+      .collect::<String>()
+      .to_lowercase()
+  };
+  let name = normalize(src);
+  ScaleMode::all()
+    .iter()
+    .find(|scale| normalize(scale.name()) == name || normalize(&format!("{:?}", scale)) == name)
+    .copied()
+    .ok_or_else(|| format!("unknown scale `{}`", src))
+}
+
+/// Play `text` for the given number of bars and return the take
+pub fn render(args: &RenderArgs, text: &str) -> Result<Smf, String> {
+  let (grid_width, grid_height) = args.grid_size;
+  let matches = RegExpHandler::find_matches(&EventData {
+    text: text.to_string(),
+    pattern: args.pattern.clone(),
+    flags: args.flags.clone(),
+    grid_width,
+  })?;
+
+  // nothing is drawn, the status updates the engine sends are dropped as they come
+  let (cb_sink, callbacks): (cursive::CbSink, _) = crossbeam::channel::unbounded();
+
+  // never connected to an output, every message only goes to the recorder
+  let midi = Midi::offline(cb_sink.clone());
+  let (marker_midi_tx, marker_midi_rx) = channel();
+  let marker = MarkerArea::new(marker_midi_tx, midi.sends_controls());
+  let signature = args.signature();
+
+  for message in [
+    playhead::Message::SetGridSize(grid_width, grid_height),
+    playhead::Message::SetScaleModeLeft(args.scale_left),
+    playhead::Message::SetScaleModeTop(args.scale_top),
+    playhead::Message::SetTempo(args.bpm),
+    playhead::Message::SetSignature(signature),
+    playhead::Message::SetRatio(args.ratio, cb_sink.clone()),
+    playhead::Message::SetLoopLength(args.loop_length, cb_sink.clone()),
+    playhead::Message::SetMatcher((!matches.is_empty()).then_some(matches), cb_sink.clone()),
+  ] {
+    marker.handle(message);
+  }
+  for (enabled, message) in [
+    (
+      args.reverse,
+      playhead::Message::ToggleReverseMode(cb_sink.clone()),
+    ),
+    (
+      args.arpeggiator,
+      playhead::Message::ToggleArpeggiatorMode(cb_sink.clone()),
+    ),
+    (
+      args.random,
+      playhead::Message::ToggleRandomMode(cb_sink.clone()),
+    ),
+    (
+      args.accumulation,
+      playhead::Message::ToggleAccumulationMode(cb_sink.clone()),
+    ),
+  ] {
+    if enabled {
+      marker.handle(message);
+    }
+  }
+  // regions are pinned from the marker, placed and set up like it in turn
+  for (rect, ratio, loop_length) in &args.regions {
+    marker.place(*rect);
+    marker.handle(playhead::Message::SetRatio(
+      ratio.unwrap_or(args.ratio),
+      cb_sink.clone(),
+    ));
+    marker.handle(playhead::Message::SetLoopLength(
+      loop_length.unwrap_or(args.loop_length),
+      cb_sink.clone(),
+    ));
+    marker.handle(playhead::Message::PinRegion());
+  }
+  marker.handle(playhead::Message::SetRatio(args.ratio, cb_sink.clone()));
+  marker.handle(playhead::Message::SetLoopLength(
+    args.loop_length,
+    cb_sink.clone(),
+  ));
+  marker.place(
+    args
+      .marker
+      .unwrap_or_else(|| Rect::from_size(Vec2::zero(), (grid_width, grid_height))),
+  );
+  marker.collect_regex_indexes();
+
+  for message in [
+    midi::Message::SetTempo(args.bpm),
+    midi::Message::SetSignature(signature),
+    midi::Message::StartRecording(),
+  ] {
+    midi.handle(message);
+  }
+  if let Some(gate_percent) = args.gate_percent {
+    midi.handle(midi::Message::ToggleGateMode());
+    midi.handle(midi::Message::SetGate(gate_percent));
+  }
+
+  // same order as the metronome: the clock tick releases due notes, then the marker steps
+  let ticks = (signature.ticks_per_bar() * Ratio::from_integer(args.bars as i64))
+    .ceil()
+    .to_integer() as usize;
+  // ticks come from the clock's own scheduler, on time that jumps straight to every deadline
+  let source = Arc::new(VirtualTime::free_running());
+  let mut timer = Timer::new(signature, Ratio::from_integer(args.bpm as i64), source);
+  let mut tick = 0;
+  while tick < ticks {
+    // pulses only matter to clock followers, none listen to a render
+    let TimerEvent::Tick(_) = timer.next() else {
+      continue;
+    };
+    midi.handle(midi::Message::Tick(tick));
+    marker.handle(playhead::Message::SetActivePos(tick, 1.0, cb_sink.clone()));
+    for message in marker_midi_rx.try_iter() {
+      midi.handle(message);
+    }
+    marker.ui_update_queue.lock().unwrap().clear();
+    callbacks.try_iter().for_each(drop);
+    tick += 1;
+  }
+  // notes still held are cut at the end of the last bar
+  midi.handle(midi::Message::Tick(ticks));
+  midi.handle(midi::Message::FlushNotes());
+
+  midi
+    .recording()
+    .ok_or_else(|| "nothing was recorded".to_string())
+}
+
+/// `anupars render ...`, returns the process exit code
+pub fn main(args: &[String]) -> i32 {
+  if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+    println!("{}", USAGE);
+    return 0;
+  }
+
+  let result = RenderArgs::parse(args).and_then(|args| {
+    let text = fs::read_to_string(&args.text_path)
+      .map_err(|e| format!("could not read {}: {}", args.text_path.display(), e))?;
+    let smf = render(&args, &text)?;
+    smf
+      .save(&args.output)
+      .map_err(|e| format!("could not save {}: {}", args.output.display(), e))?;
+    Ok(args)
+  });
+
+  match result {
+    Ok(args) => {
+      println!(
+        "rendered {} bars of {} to {}",
+        args.bars,
+        args.text_path.display(),
+        args.output.display()
+      );
+      0
+    }
+    Err(e) => {
+      eprintln!("{}\n\n{}", e, USAGE);
+      1
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::smf;
+
+  fn args(src: &str) -> Vec<String> {
+    src.split_whitespace().map(str::to_string).collect()
+  }
+
+  #[test]
+  fn test_parse_args() {
+    let render = RenderArgs::parse(&args(
+      "text.txt --regex a+ --flags i --grid 40x10 --marker 2,3,8,4 --scale-left minor-pentatonic \
+       --scale-top Dorian --bpm 90 --ratio 1/12 --bars 2 --reverse --arpeggiator -o out.mid",
+    ))
+    .unwrap();
+
+    assert_eq!(render.text_path, PathBuf::from("text.txt"));
+    assert_eq!(render.pattern, "a+");
+    assert_eq!(render.grid_size, (40, 10));
+    assert_eq!(render.marker, Some(Rect::from_size((2, 3), (8, 4))));
+    assert_eq!(render.scale_left, ScaleMode::MinorPentatonic);
+    assert_eq!(render.scale_top, ScaleMode::Dorian);
+    assert_eq!(render.ratio, (1, 12));
+    assert!(render.reverse && render.arpeggiator && !render.random);
+    assert_eq!(render.output, PathBuf::from("out.mid"));
+  }
+
+  #[test]
+  fn test_parse_args_errors() {
+    assert!(RenderArgs::parse(&args("text.txt")).is_err());
+    assert!(RenderArgs::parse(&args("--regex a")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --ratio 1/0")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --scale-top nope")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --grid 8x2 --marker 4,0,8,2")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --bars")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --grid 8x2 --region 0,2,4,1")).is_err());
+    assert!(RenderArgs::parse(&args("text.txt --regex a --region 0,0,4,1:1/12:5:3")).is_err());
+  }
+
+  #[test]
+  fn test_parse_region() {
+    let render = RenderArgs::parse(&args(
+      "text.txt --regex a --region 0,0,4,1 --region 0,1,4,1:3/32 --region 1,1,2,1:1/12:5",
+    ))
+    .unwrap();
+    assert_eq!(
+      render.regions,
+      vec![
+        (Rect::from_size((0, 0), (4, 1)), None, None),
+        (Rect::from_size((0, 1), (4, 1)), Some((3, 32)), None),
+        (Rect::from_size((1, 1), (2, 1)), Some((1, 12)), Some(5)),
+      ]
+    );
+  }
+
+  // ticks of every note-on of the take
+  fn note_on_ticks(smf: &Smf) -> Vec<u64> {
+    let mut ticks: Vec<u64> = smf
+      .tracks
+      .iter()
+      .flat_map(|track| track.events())
+      .filter_map(|(tick, event)| match event {
+        smf::Event::Midi(bytes) if bytes[0] & 0xF0 == 0x90 && bytes[2] > 0 => Some(*tick),
+        _ => None,
+      })
+      .collect();
+    ticks.sort();
+    ticks
+  }
+
+  #[test]
+  fn test_render_regions_drift_against_the_marker() {
+    // sixteenths over the top row, eighth triplets over the bottom one, for a bar of 4 beats
+    let render_args = RenderArgs::parse(&args(
+      "text.txt --regex a --grid 4x2 --marker 0,0,4,1 --region 0,1,4,1:1/12 --bars 1 --beats 4",
+    ))
+    .unwrap();
+    let ticks = note_on_ticks(&render(&render_args, "aaaaaaaa").unwrap());
+
+    // 24 ticks a sixteenth, 32 a triplet at 96 PPQN, both only meet on the beats
+    let mut expected: Vec<u64> = (0..16)
+      .map(|step| step * 24)
+      .chain((0..12).map(|step| step * 32))
+      .collect();
+    expected.sort();
+    assert_eq!(ticks, expected);
+  }
+
+  #[test]
+  fn test_render_gate_shortens_the_notes() {
+    let render_args = RenderArgs::parse(&args(
+      "text.txt --regex a --grid 4x1 --bars 1 --beats 4 --gate 25",
+    ))
+    .unwrap();
+    assert_eq!(render_args.gate_percent, Some(25));
+
+    // the first note-off of a sixteenth (24 ticks) lands a quarter of the way in
+    let smf = render(&render_args, "a...").unwrap();
+    let note_off = smf
+      .tracks
+      .iter()
+      .flat_map(|track| track.events())
+      .find_map(|(tick, event)| match event {
+        smf::Event::Midi(bytes) if bytes[0] & 0xF0 == 0x80 || bytes[2] == 0 => Some(*tick),
+        _ => None,
+      });
+    assert_eq!(note_off, Some(6));
+  }
+
+  #[test]
+  fn test_render_plays_the_matches() {
+    let render_args =
+      RenderArgs::parse(&args("text.txt --regex a --grid 4x1 --bars 1 --beats 4")).unwrap();
+
+    // conductor and channel 1
+    let smf = render(&render_args, "a.a.").unwrap();
+    assert_eq!(smf.tracks.len(), 2);
+
+    // no match, only the conductor track
+    let smf = render(&render_args, "....").unwrap();
+    assert_eq!(smf.tracks.len(), 1);
+
+    assert!(render(
+      &RenderArgs {
+        pattern: "(".to_string(),
+        ..render_args
+      },
+      "a.a."
+    )
+    .is_err());
+  }
+}
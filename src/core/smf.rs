@@ -0,0 +1,196 @@
+//! Standard MIDI File (SMF) writer, type 1: a tempo/meter track followed by the note tracks
+
+use std::fs;
+use std::path::Path;
+
+// microseconds per minute, tempo meta events store microseconds per quarter note
+static MICROS_PER_MINUTE: u32 = 60_000_000;
+// divisions above this would be read as SMPTE timing
+static MAX_DIVISION: u16 = 0x7FFF;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  Midi(Vec<u8>),         // channel message, running status is never used
+  Tempo(u32),            // beats per minute
+  TimeSignature(u8, u8), // (numerator, denominator as a power of 2)
+  TrackName(String),
+}
+
+impl Event {
+  fn write(&self, out: &mut Vec<u8>) {
+    match self {
+      Event::Midi(bytes) => out.extend_from_slice(bytes),
+      Event::Tempo(bpm) => {
+        let micros_per_beat = MICROS_PER_MINUTE / (*bpm).max(1);
+        out.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        out.extend_from_slice(&micros_per_beat.to_be_bytes()[1..]);
+      }
+      Event::TimeSignature(numerator, denominator_pow) => {
+        // 24 MIDI clocks per metronome click, 8 thirty-seconds per quarter note
+        out.extend_from_slice(&[0xFF, 0x58, 0x04, *numerator, *denominator_pow, 24, 8]);
+      }
+      Event::TrackName(name) => {
+        out.extend_from_slice(&[0xFF, 0x03]);
+        write_var_len(name.len() as u32, out);
+        out.extend_from_slice(name.as_bytes());
+      }
+    }
+  }
+}
+
+/// Events at absolute ticks, in any order (sorted, stable, when written)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Track {
+  events: Vec<(u64, Event)>,
+}
+
+impl Track {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn push(&mut self, tick: u64, event: Event) {
+    self.events.push((tick, event));
+  }
+
+  /// Events in the order they were pushed
+  #[cfg(test)]
+  pub fn events(&self) -> &[(u64, Event)] {
+    &self.events
+  }
+
+  fn write(&self, out: &mut Vec<u8>) {
+    let mut events: Vec<&(u64, Event)> = self.events.iter().collect();
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut data = Vec::new();
+    let mut last_tick = 0;
+    for (tick, event) in events {
+      write_var_len((tick - last_tick) as u32, &mut data);
+      event.write(&mut data);
+      last_tick = *tick;
+    }
+    // end of track
+    data.extend_from_slice(&[0x00, 0xFF, 0x2F, 0x00]);
+
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(&data);
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Smf {
+  division: u16, // ticks per quarter note
+  pub tracks: Vec<Track>,
+}
+
+impl Smf {
+  pub fn new(division: u16) -> Self {
+    Self {
+      division: division.clamp(1, MAX_DIVISION),
+      tracks: Vec::new(),
+    }
+  }
+
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&1u16.to_be_bytes()); // type 1
+    out.extend_from_slice(&(self.tracks.len() as u16).to_be_bytes());
+    out.extend_from_slice(&self.division.to_be_bytes());
+
+    for track in self.tracks.iter() {
+      track.write(&mut out);
+    }
+    out
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, self.to_bytes()).map_err(|e| e.to_string())
+  }
+}
+
+/// Variable-length quantity, 7 bits per byte, most significant first
+fn write_var_len(value: u32, out: &mut Vec<u8>) {
+  let mut bytes = vec![(value & 0x7F) as u8];
+  let mut value = value >> 7;
+  while value > 0 {
+    bytes.push((value & 0x7F) as u8 | 0x80);
+    value >>= 7;
+  }
+  out.extend(bytes.iter().rev());
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn var_len(value: u32) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_var_len(value, &mut out);
+    out
+  }
+
+  #[test]
+  fn test_var_len() {
+    assert_eq!(var_len(0), [0x00]);
+    assert_eq!(var_len(0x40), [0x40]);
+    assert_eq!(var_len(0x7F), [0x7F]);
+    assert_eq!(var_len(0x80), [0x81, 0x00]);
+    assert_eq!(var_len(0x2000), [0xC0, 0x00]);
+    assert_eq!(var_len(0x0FFF_FFFF), [0xFF, 0xFF, 0xFF, 0x7F]);
+  }
+
+  #[test]
+  fn test_header_and_meta_events() {
+    let mut conductor = Track::new();
+    conductor.push(0, Event::TimeSignature(7, 3));
+    conductor.push(0, Event::Tempo(120));
+
+    let mut smf = Smf::new(96);
+    smf.tracks.push(conductor);
+    let bytes = smf.to_bytes();
+
+    assert_eq!(
+      bytes[..14],
+      [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 1, 0, 96]
+    );
+    assert_eq!(bytes[14..18], *b"MTrk");
+    assert_eq!(bytes[18..22], [0, 0, 0, 19]);
+    assert_eq!(
+      bytes[22..],
+      [
+        0x00, 0xFF, 0x58, 0x04, 7, 3, 24, 8, // 7/8
+        0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 500000us per beat
+        0x00, 0xFF, 0x2F, 0x00,
+      ]
+    );
+  }
+
+  #[test]
+  fn test_events_are_written_in_time_order() {
+    let mut track = Track::new();
+    track.push(200, Event::Midi(vec![0x80, 60, 0]));
+    track.push(0, Event::Midi(vec![0x90, 60, 100]));
+    track.push(200, Event::Midi(vec![0x90, 62, 100]));
+
+    let mut smf = Smf::new(96);
+    smf.tracks.push(track);
+    let bytes = smf.to_bytes();
+
+    assert_eq!(
+      bytes[22..],
+      [
+        0x00, 0x90, 60, 100, // note on
+        0x81, 0x48, 0x80, 60, 0, // 200 ticks later, off
+        0x00, 0x90, 62, 100, // same tick, pushed later
+        0x00, 0xFF, 0x2F, 0x00,
+      ]
+    );
+  }
+}
@@ -1,87 +1,138 @@
-use std::sync::mpsc::{channel, Sender};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::{Duration, Instant};
+use std::collections::HashSet;
+use std::sync::Mutex;
 
-use super::midi::{self, MidiMsg};
-
-#[derive(Clone, Debug)]
-pub enum Message {
-  Push(MidiMsg),
-}
+use super::midi::MidiMsg;
 
+/// Pending note-offs, scheduled on clock ticks
+#[derive(Default)]
 pub struct Stack {
-  pub stack: Arc<Mutex<Vec<midi::MidiMsg>>>,
-  // pub stack_msg_config: Arc<Mutex<Vec<midi::MidiMsg>>>,
+  held: Mutex<Vec<(usize, MidiMsg)>>, // (release_tick, msg)
+  last_tick: Mutex<Option<usize>>,
 }
 
 impl Stack {
   pub fn new() -> Stack {
-    Stack {
-      stack: Arc::new(Mutex::new(vec![])),
-      // stack_msg_config: Arc::new(Mutex::new(vec![])),
-    }
+    Stack::default()
   }
 
-  pub fn run(self: Arc<Self>, _midi_tx: Sender<midi::Message>) -> Sender<Message> {
-    let (tx, rx) = channel();
+  /// Hold `midi_msg` until `release_tick`, returns the note it takes over when that pitch is
+  /// still held, so the caller can release it before the new note-on
+  pub fn hold(&self, midi_msg: MidiMsg, release_tick: usize) -> Option<MidiMsg> {
+    let mut held = self.held.lock().unwrap();
+    let retriggered = held
+      .iter()
+      .position(|(_, item)| item.key() == midi_msg.key())
+      .map(|idx| held.remove(idx).1);
+    held.push((release_tick, midi_msg));
+    retriggered
+  }
 
-    thread::spawn(move || {
-      for control_message in &rx {
-        match control_message {
-          Message::Push(midi_msg) => {
-            self.push(midi_msg);
-          }
-        }
+  /// Release the notes due at `tick`, or all of them when the clock went back
+  pub fn release_until(&self, tick: usize) -> Vec<MidiMsg> {
+    let mut last_tick = self.last_tick.lock().unwrap();
+    let rewound = last_tick.is_some_and(|last_tick| tick < last_tick);
+    *last_tick = Some(tick);
+    drop(last_tick);
+
+    let mut held = self.held.lock().unwrap();
+    let mut notes_to_release = Vec::new();
+    held.retain(|(release_tick, item)| {
+      let is_due = rewound || tick >= *release_tick;
+      if is_due {
+        notes_to_release.push(item.clone());
       }
+      !is_due
     });
 
-    tx
+    notes_to_release
+  }
+
+  /// Channels with a note still held, for MPE channel rotation
+  pub fn channels(&self) -> HashSet<u8> {
+    let held = self.held.lock().unwrap();
+    held.iter().map(|(_, item)| item.key().0).collect()
   }
 
-  pub fn refresh(self: Arc<Self>, midi_tx: Sender<midi::Message>) {
-    thread::spawn(move || {
-      // Reduced from 34ms to 8ms for 8x better timing resolution
-      // This provides ~125 FPS update rate for precise MIDI note releases
-      let frame_duration = Duration::from_millis(8); // 8 ms per frame (125 FPS)
-      loop {
-        let frame_start = Instant::now();
-
-        {
-          let mut st = self.stack.lock().unwrap();
-
-          // Batch collect all note-offs to send at once (reduces lock time)
-          let mut notes_to_release = Vec::new();
-
-          st.retain_mut(|item| {
-            if item.length < 2 {
-              notes_to_release.push(item.clone());
-            }
-            item.length -= 1;
-            // Keep the message if its length is still >= 1
-            item.length >= 1
-          });
-
-          // Release lock before sending MIDI (reduces contention)
-          drop(st);
-
-          // Send all note-offs in batch
-          for note in notes_to_release {
-            let _ = midi_tx.send(midi::Message::Trigger(note, false));
-          }
-        }
-
-        let elapsed = frame_start.elapsed();
-
-        if elapsed < frame_duration {
-          thread::sleep(frame_duration - elapsed);
-        }
+  /// Release the notes held on `channel`, eg. before an MPE voice takes it over
+  pub fn release_channel(&self, channel: u8) -> Vec<MidiMsg> {
+    let mut held = self.held.lock().unwrap();
+    let mut notes_to_release = Vec::new();
+    held.retain(|(_, item)| {
+      let is_on_channel = item.key().0 == channel;
+      if is_on_channel {
+        notes_to_release.push(item.clone());
       }
+      !is_on_channel
     });
+
+    notes_to_release
+  }
+
+  /// Release every held note, eg. when playback stops
+  pub fn flush(&self) -> Vec<MidiMsg> {
+    *self.last_tick.lock().unwrap() = None;
+    let mut held = self.held.lock().unwrap();
+    held.drain(..).map(|(_, item)| item).collect()
   }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_notes_release_on_their_tick() {
+    let stack = Stack::new();
+    stack.hold(MidiMsg::from(0, 4, 1, 100, 1, true), 24);
+    stack.hold(MidiMsg::from(4, 4, 1, 100, 1, true), 96);
+
+    assert_eq!(stack.release_until(23).len(), 0);
+    assert_eq!(stack.release_until(24).len(), 1);
+    assert_eq!(stack.release_until(95).len(), 0);
+    // stop/reset moves the clock back, nothing is left hanging
+    assert_eq!(stack.release_until(0).len(), 1);
+    assert!(stack.flush().is_empty());
+  }
+
+  #[test]
+  fn test_held_pitch_is_retriggered_once() {
+    let stack = Stack::new();
+    assert!(stack
+      .hold(MidiMsg::from(0, 4, 1, 100, 1, true), 48)
+      .is_none());
+    // same pitch on another channel is another note
+    assert!(stack
+      .hold(MidiMsg::from(0, 4, 1, 100, 2, true), 48)
+      .is_none());
+
+    let retriggered = stack.hold(MidiMsg::from(0, 4, 1, 90, 1, true), 72);
+    assert!(retriggered.is_some());
+
+    // only the latest note-off is left for that pitch
+    assert_eq!(stack.release_until(48).len(), 1);
+    assert_eq!(stack.release_until(72).len(), 1);
+  }
+
+  #[test]
+  fn test_notes_release_per_channel() {
+    let stack = Stack::new();
+    stack.hold(MidiMsg::from(0, 4, 1, 100, 1, true), 24);
+    stack.hold(MidiMsg::from(0, 4, 1, 100, 2, true), 24);
+    stack.hold(MidiMsg::from(4, 4, 1, 100, 2, true), 48);
+
+    assert_eq!(stack.channels(), HashSet::from([1, 2]));
+    assert_eq!(stack.release_channel(2).len(), 2);
+    assert_eq!(stack.channels(), HashSet::from([1]));
+    assert!(stack.release_channel(2).is_empty());
+  }
+
+  #[test]
+  fn test_flush_releases_everything() {
+    let stack = Stack::new();
+    stack.hold(MidiMsg::from(0, 4, 1, 100, 1, true), 24);
+    stack.hold(MidiMsg::from(7, 4, 1, 100, 1, true), 960);
 
-  pub fn push(&self, midi_msg: MidiMsg) {
-    let mut stack = self.stack.lock().unwrap();
-    stack.push(midi_msg);
+    assert_eq!(stack.flush().len(), 2);
+    assert!(stack.release_until(10_000).is_empty());
   }
 }
@@ -0,0 +1,160 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use num::rational::Ratio;
+
+use super::clock::{RampCurve, Tempo};
+
+/// Ramp to `target` over `bars` bars, starting at the downbeat of bar `bar` (counted from 0)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoPoint {
+  pub bar: i64,
+  pub target: Tempo,
+  pub bars: i64,
+  pub curve: RampCurve,
+}
+
+/// Tempo automation lane, one ramp at most per bar.
+///
+/// file format: one `<bar> <bpm> <bars> <lin|exp>` ramp per line, `#` starts a comment.
+/// BPM is rational, eg. `120` or `241/2`, so a stored lane plays back exactly.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TempoLane {
+  points: Vec<TempoPoint>,
+}
+
+impl TempoLane {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.points.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.points.is_empty()
+  }
+
+  /// Add a ramp, replacing the one already starting at the same bar
+  pub fn insert(&mut self, point: TempoPoint) {
+    match self.points.binary_search_by_key(&point.bar, |p| p.bar) {
+      Ok(idx) => self.points[idx] = point,
+      Err(idx) => self.points.insert(idx, point),
+    }
+  }
+
+  pub fn point_at(&self, bar: i64) -> Option<&TempoPoint> {
+    self
+      .points
+      .binary_search_by_key(&bar, |p| p.bar)
+      .ok()
+      .map(|idx| &self.points[idx])
+  }
+
+  pub fn parse(src: &str) -> Result<Self, String> {
+    let mut lane = Self::new();
+
+    for (line_no, line) in src.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      let err = |what: &str| format!("line {}: invalid {}", line_no + 1, what);
+      let fields: Vec<&str> = line.split_whitespace().collect();
+      let [bar, target, bars, curve] = fields[..] else {
+        return Err(format!(
+          "line {}: expected `<bar> <bpm> <bars> <lin|exp>`",
+          line_no + 1
+        ));
+      };
+
+      let point = TempoPoint {
+        bar: bar.parse().ok().filter(|b| *b >= 0).ok_or(err("bar"))?,
+        target: target
+          .parse::<Tempo>()
+          .ok()
+          .filter(|t| *t > Ratio::from_integer(0))
+          .ok_or(err("bpm"))?,
+        bars: bars.parse().ok().filter(|b| *b > 0).ok_or(err("length"))?,
+        curve: RampCurve::from_name(curve).ok_or(err("curve"))?,
+      };
+      lane.insert(point);
+    }
+
+    Ok(lane)
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    Self::parse(&src)
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, self.to_string()).map_err(|e| e.to_string())
+  }
+}
+
+impl fmt::Display for TempoLane {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "# bar bpm bars curve")?;
+    for point in self.points.iter() {
+      writeln!(
+        f,
+        "{} {} {} {}",
+        point.bar,
+        point.target,
+        point.bars,
+        point.curve.name()
+      )?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lane_round_trip_is_exact() {
+    let mut lane = TempoLane::new();
+    lane.insert(TempoPoint {
+      bar: 8,
+      target: Ratio::new(281, 2),
+      bars: 4,
+      curve: RampCurve::Exponential,
+    });
+    lane.insert(TempoPoint {
+      bar: 0,
+      target: Ratio::from_integer(90),
+      bars: 1,
+      curve: RampCurve::Linear,
+    });
+
+    let parsed = TempoLane::parse(&lane.to_string()).unwrap();
+    assert_eq!(parsed, lane);
+    assert_eq!(parsed.point_at(8).unwrap().target, Ratio::new(281, 2));
+    assert!(parsed.point_at(4).is_none());
+  }
+
+  #[test]
+  fn test_lane_keeps_one_ramp_per_bar() {
+    let lane = TempoLane::parse("4 120 2 lin\n4 140 2 exp # replaces the first\n").unwrap();
+    assert_eq!(lane.len(), 1);
+    assert_eq!(lane.point_at(4).unwrap().curve, RampCurve::Exponential);
+  }
+
+  #[test]
+  fn test_lane_rejects_malformed_lines() {
+    assert!(TempoLane::parse("4 120 2\n").is_err());
+    assert!(TempoLane::parse("4 0 2 lin\n").is_err());
+    assert!(TempoLane::parse("4 120 2 log\n").is_err());
+    assert!(TempoLane::parse("-1 120 2 lin\n").is_err());
+  }
+}
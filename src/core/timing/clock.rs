@@ -1,11 +1,16 @@
 use super::metronome;
+use super::source::TimeSource;
+use crate::core::midi::{self, ClockMsg};
+#[cfg(debug_assertions)]
+use crate::view::common::timing_diagnostic::TimingStats;
 use num::integer::Integer;
 use num::rational::Ratio;
+use num::ToPrimitive;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Sender};
 use std::sync::{Arc, Mutex, MutexGuard};
-use std::thread::{self, sleep};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
 pub type Tick = Ratio<i64>;
@@ -15,11 +20,18 @@ pub type NudgeTempo = Ratio<i64>;
 static SECONDS_PER_MINUTE: i64 = 60;
 static NANOS_PER_SECOND: i64 = 1_000_000_000;
 
-static DEFAULT_TICKS_PER_BEAT: i64 = 4;
+// 96 PPQN, so triplet and dotted note rates land on whole ticks
+static DEFAULT_TICKS_PER_BEAT: i64 = 96;
 static DEFAULT_BEATS_PER_BAR: i64 = 8;
 static DEFAULT_BARS_PER_LOOP: i64 = 8;
 static DEFAULT_BEATS_PER_MINUTE: i64 = 120;
 
+// MIDI timing clock resolution (pulses per quarter note)
+static MIDI_CLOCK_PPQN: i64 = 24;
+// Song Position Pointer counts in MIDI beats (sixteenth notes)
+static MIDI_BEATS_PER_BEAT: i64 = 4;
+static MIDI_CLOCK_PULSES_PER_MIDI_BEAT: i64 = MIDI_CLOCK_PPQN / MIDI_BEATS_PER_BEAT;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Signature {
   pub ticks_per_beat: Tick,
@@ -67,6 +79,10 @@ impl Signature {
     nanos_per_beat / self.ticks_per_beat
   }
 
+  pub fn nanos_per_pulse(&self, beats_per_minute: Tick) -> Tick {
+    self.nanos_per_beat(beats_per_minute) / MIDI_CLOCK_PPQN
+  }
+
   pub fn nanos_per_beat(&self, beats_per_minute: Tick) -> Tick {
     self.nanos_per_tick(beats_per_minute) * self.ticks_per_beat
   }
@@ -105,10 +121,23 @@ impl Time {
     }
   }
 
+  /// Time at a position counted in MIDI clock pulses (24 PPQN)
+  pub fn from_pulses(signature: Signature, pulses: i64) -> Self {
+    let beats = Ratio::new(pulses, MIDI_CLOCK_PPQN);
+    Self {
+      ticks: (beats * signature.ticks_per_beat).floor(),
+      signature,
+    }
+  }
+
   pub fn ticks(&self) -> Tick {
     self.ticks
   }
 
+  pub fn signature(&self) -> Signature {
+    self.signature
+  }
+
   pub fn beats(&self) -> Tick {
     self.signature.ticks_to_beats(self.ticks)
   }
@@ -129,10 +158,25 @@ impl Time {
     self.bars() % self.signature.bars_per_loop
   }
 
+  /// (bar, beat, tick) within the loop, counted from 1
+  pub fn position(&self) -> (i64, i64, i64) {
+    (
+      self.bars_since_loop().floor().to_integer() + 1,
+      self.beats_since_bar().floor().to_integer() + 1,
+      self.ticks_since_beat().floor().to_integer() + 1,
+    )
+  }
+
   pub fn ticks_before_beat(&self) -> Tick {
     self.ticks() - self.ticks_since_beat()
   }
 
+  /// Position in MIDI beats (sixteenth notes) as used by Song Position Pointer
+  pub fn song_position(&self) -> u16 {
+    let midi_beats = self.beats() * MIDI_BEATS_PER_BEAT;
+    midi_beats.floor().to_integer().clamp(0, 0x3FFF) as u16
+  }
+
   pub fn is_first_tick(&self) -> bool {
     self.ticks_since_beat().floor() == Ratio::from_integer(0)
   }
@@ -145,6 +189,19 @@ impl Time {
     self.bars_since_loop().floor() == Ratio::from_integer(0)
   }
 
+  /// Coarsest boundary starting at this tick, `Off` when it is not on a beat
+  pub fn boundary(&self) -> Quantization {
+    if !self.is_first_tick() {
+      Quantization::Off
+    } else if !self.is_first_beat() {
+      Quantization::Beat
+    } else if !self.is_first_bar() {
+      Quantization::Bar
+    } else {
+      Quantization::Loop
+    }
+  }
+
   pub fn next(&self) -> Self {
     Self {
       ticks: self.ticks + 1,
@@ -171,68 +228,278 @@ impl Time {
   }
 }
 
+// one grid of absolutely scheduled steps (ticks or clock pulses)
 #[derive(Clone, Copy, Debug)]
+struct Deadline {
+  anchor: Instant,
+  steps: i64,
+  nanos_per_step: Tick,
+}
+
+impl Deadline {
+  fn new(anchor: Instant, steps: i64, nanos_per_step: Tick) -> Self {
+    Self {
+      anchor,
+      steps,
+      nanos_per_step,
+    }
+  }
+
+  fn instant(&self) -> Instant {
+    self.anchor + nanos_to_duration(self.nanos_per_step * self.steps)
+  }
+
+  fn advance(&mut self, now: Instant) {
+    self.steps += 1;
+
+    // fell behind by more than a step (eg. system suspend):
+    // skip the missed steps on the same grid instead of bursting them out
+    let behind = now.saturating_duration_since(self.instant());
+    if !behind.is_zero() {
+      let missed = Ratio::from_integer(duration_to_nanos(behind)) / self.nanos_per_step;
+      self.steps += missed.ceil().to_integer();
+    }
+  }
+
+  // keep the fraction of the current step that is left, at the new step length
+  fn retime(&mut self, now: Instant, nanos_per_step: Tick) {
+    let nanos_left = duration_to_nanos(self.instant().saturating_duration_since(now));
+    let phase_left =
+      (Ratio::from_integer(nanos_left) / self.nanos_per_step).min(Ratio::from_integer(1));
+
+    self.anchor = now + nanos_to_duration(phase_left * nanos_per_step);
+    self.steps = 0;
+    self.nanos_per_step = nanos_per_step;
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimerEvent {
+  Tick(Duration), // carries how late the tick fired
+  Pulse,
+}
+
+/// Drift-free scheduler for ticks and 24-PPQN clock pulses.
+/// Deadlines are absolute from a shared anchor, so sleep overshoot never accumulates.
+#[derive(Clone, Debug)]
 pub struct Timer {
-  instant: Instant,
   signature: Signature,
+  tick: Deadline,
+  pulse: Deadline,
+  source: Arc<dyn TimeSource>,
 }
 
 impl Timer {
-  pub fn new(signature: Signature) -> Self {
+  pub fn new(signature: Signature, beats_per_minute: Tempo, source: Arc<dyn TimeSource>) -> Self {
+    let now = source.now();
     Self {
-      instant: Instant::now(),
       signature,
+      source,
+      // tick 0 is "now", the pulse on it marks the downbeat for clock followers
+      tick: Deadline::new(now, 1, signature.nanos_per_tick(beats_per_minute)),
+      pulse: Deadline::new(now, 0, signature.nanos_per_pulse(beats_per_minute)),
     }
   }
 
-  pub fn nanos(&self) -> Tick {
-    Ratio::from_integer(duration_to_nanos(self.instant.elapsed()))
+  pub fn set_tempo(&mut self, beats_per_minute: Tempo) {
+    let now = self.source.now();
+    self
+      .tick
+      .retime(now, self.signature.nanos_per_tick(beats_per_minute));
+    self
+      .pulse
+      .retime(now, self.signature.nanos_per_pulse(beats_per_minute));
   }
 
-  pub fn nanos_since_tick(&self, beats_per_minute: Tick) -> Tick {
-    self.nanos() % self.signature.nanos_per_tick(beats_per_minute)
+  pub fn next(&mut self) -> TimerEvent {
+    let is_tick = self.tick.instant() <= self.pulse.instant();
+    let deadline = if is_tick {
+      &mut self.tick
+    } else {
+      &mut self.pulse
+    };
+
+    let due = deadline.instant();
+    self.source.wait_until(due);
+    let now = self.source.now();
+    deadline.advance(now);
+
+    if is_tick {
+      TimerEvent::Tick(now.saturating_duration_since(due))
+    } else {
+      TimerEvent::Pulse
+    }
   }
+}
 
-  #[allow(dead_code)]
-  pub fn nanos_since_beat(&self, beats_per_minute: Tick) -> Tick {
-    self.nanos() % self.signature.nanos_per_beat(beats_per_minute)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RampCurve {
+  Linear,
+  Exponential,
+}
+
+impl RampCurve {
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Linear => "lin",
+      Self::Exponential => "exp",
+    }
   }
 
-  #[allow(dead_code)]
-  pub fn nanos_since_bar(&self, beats_per_minute: Tick) -> Tick {
-    self.nanos() % self.signature.nanos_per_bar(beats_per_minute)
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "lin" => Some(Self::Linear),
+      "exp" => Some(Self::Exponential),
+      _ => None,
+    }
   }
+}
 
-  #[allow(dead_code)]
-  pub fn nanos_since_loop(&self, beats_per_minute: Tick) -> Tick {
-    self.nanos() % self.signature.nanos_per_loop(beats_per_minute)
+/// Tempo change from `from` to `to` over `length` ticks, starting at tick `start`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoRamp {
+  pub from: Tempo,
+  pub to: Tempo,
+  pub start: Tick,
+  pub length: Tick,
+  pub curve: RampCurve,
+}
+
+impl TempoRamp {
+  pub fn tempo_at(&self, ticks: Tick) -> Tempo {
+    if self.is_done(ticks) {
+      return self.to;
+    }
+    let progress = ((ticks - self.start) / self.length).max(Ratio::from_integer(0));
+
+    match self.curve {
+      // exact in the rational domain
+      RampCurve::Linear => self.from + (self.to - self.from) * progress,
+      // equal tempo ratios per tick, which sounds even to the ear.
+      // irrational in general, so it is rounded to a hundredth of a BPM
+      RampCurve::Exponential => {
+        let ratio = (self.to / self.from).to_f64().unwrap_or(1.0);
+        let factor = ratio.powf(progress.to_f64().unwrap_or(1.0));
+        let tempo = self.from.to_f64().unwrap_or(0.0) * factor;
+        Ratio::approximate_float(tempo)
+          .map(|tempo| round_to_nearest(tempo, 100))
+          .unwrap_or(self.to)
+      }
+    }
   }
 
-  pub fn nanos_until_tick(&self, beats_per_minute: Tick) -> Tick {
-    let nanos_since_tick = self.nanos_since_tick(beats_per_minute);
-    let nanos_per_tick = self.signature.nanos_per_tick(beats_per_minute);
-    nanos_per_tick - nanos_since_tick
+  pub fn is_done(&self, ticks: Tick) -> bool {
+    ticks - self.start >= self.length
   }
+}
 
-  pub fn next(&self, beats_per_minute: Tick) -> Tick {
-    let nanos_until_tick = self.nanos_until_tick(beats_per_minute);
+/// Grid a change can be quantized to, ordered from finest to coarsest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quantization {
+  Off,
+  Beat,
+  Bar,
+  Loop,
+}
 
-    let nanos = nanos_until_tick.numer() / nanos_until_tick.denom();
+impl Quantization {
+  pub fn all() -> &'static [Self] {
+    &[Self::Off, Self::Beat, Self::Bar, Self::Loop]
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Off => "off",
+      Self::Beat => "beat",
+      Self::Bar => "bar",
+      Self::Loop => "loop",
+    }
+  }
+}
 
-    sleep(Duration::new(0, nanos as u32));
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SyncMode {
+  Internal,
+  External,
+}
 
-    nanos_until_tick
+// state of following an external MIDI clock master
+#[derive(Debug)]
+struct ExternalSync {
+  pulses: i64,
+  awaiting_first_pulse: bool,
+  beat_start: Option<Instant>,
+  beat_pulses: i64,
+}
+
+impl ExternalSync {
+  fn new() -> Self {
+    Self {
+      pulses: 0,
+      awaiting_first_pulse: true,
+      beat_start: None,
+      beat_pulses: 0,
+    }
+  }
+
+  // the first pulse after Start/Continue/SPP sits on the current position,
+  // every following pulse advances it by one
+  fn advance(&mut self) -> i64 {
+    if self.awaiting_first_pulse {
+      self.awaiting_first_pulse = false;
+    } else {
+      self.pulses += 1;
+    }
+    self.pulses
+  }
+
+  // estimate tempo once per beat worth of pulses
+  fn measure_pulse(&mut self, now: Instant) -> Option<Tempo> {
+    let Some(beat_start) = self.beat_start else {
+      self.beat_start = Some(now);
+      self.beat_pulses = 0;
+      return None;
+    };
+
+    self.beat_pulses += 1;
+    if self.beat_pulses < MIDI_CLOCK_PPQN {
+      return None;
+    }
+
+    let nanos_per_beat = duration_to_nanos(now.duration_since(beat_start));
+    self.beat_start = Some(now);
+    self.beat_pulses = 0;
+
+    if nanos_per_beat == 0 {
+      return None;
+    }
+
+    let beats_per_minute =
+      Ratio::from_integer(SECONDS_PER_MINUTE * NANOS_PER_SECOND) / nanos_per_beat;
+    Some(beats_per_minute.round())
   }
 }
 
 #[derive(Debug)]
 pub struct Clock {
   time: Arc<Mutex<Time>>,
-  timer: Arc<Mutex<Timer>>,
   signature: Arc<Mutex<Signature>>,
   tempo: Arc<Mutex<Tempo>>,
   tap: Arc<Mutex<Option<Instant>>>,
   playing: AtomicBool,
+  external: AtomicBool,
+  external_sync: Mutex<ExternalSync>,
+  // picked up by the tick thread, so steady-state ticks never lock tempo/signature
+  restart_timer: AtomicBool,
+  tempo_changed: AtomicBool,
+  ramping: AtomicBool,
+  ramp: Mutex<Option<TempoRamp>>,
+  // set once every control sender is gone, the tick thread stops with it
+  shut_down: AtomicBool,
+  midi_tx: Sender<midi::Message>,
+  source: Arc<dyn TimeSource>,
+  #[cfg(debug_assertions)]
+  timing_stats: Arc<TimingStats>,
 }
 
 #[derive(Clone, Debug)]
@@ -243,22 +510,35 @@ pub enum Message {
   StartStop,
   Signature(Signature),
   Tap,
+  SetSync(SyncMode),
+  ExternalClock(ClockMsg),
+  StartRamp(Tempo, Tick, RampCurve), // (target, bars, curve)
+  CancelRamp,
 }
 
 impl Clock {
-  pub fn new() -> Self {
+  pub fn new(midi_tx: Sender<midi::Message>, source: Arc<dyn TimeSource>) -> Self {
     let signature = Arc::new(Mutex::new(Signature::default()));
     let time = Arc::new(Mutex::new(Time::new(Signature::default())));
-    let timer = Arc::new(Mutex::new(Timer::new(Signature::default())));
     let tempo = Arc::new(Mutex::new(Ratio::from_integer(DEFAULT_BEATS_PER_MINUTE)));
 
     Self {
       time,
-      timer,
       signature,
       tempo,
       tap: Arc::new(Mutex::new(None)),
       playing: AtomicBool::new(false),
+      external: AtomicBool::new(false),
+      external_sync: Mutex::new(ExternalSync::new()),
+      restart_timer: AtomicBool::new(true),
+      tempo_changed: AtomicBool::new(false),
+      ramping: AtomicBool::new(false),
+      ramp: Mutex::new(None),
+      shut_down: AtomicBool::new(false),
+      midi_tx,
+      source,
+      #[cfg(debug_assertions)]
+      timing_stats: Arc::new(TimingStats::new("Tick Lateness")),
     }
   }
 
@@ -266,7 +546,11 @@ impl Clock {
     self.playing.load(Ordering::SeqCst)
   }
 
-  pub fn run_tick(self: Arc<Self>, metronome_tx: Sender<metronome::Message>) {
+  fn is_external(&self) -> bool {
+    self.external.load(Ordering::SeqCst)
+  }
+
+  pub fn run_tick(self: Arc<Self>, metronome_tx: Sender<metronome::Message>) -> JoinHandle<()> {
     metronome_tx
       .send(metronome::Message::Signature(Signature::default()))
       .unwrap();
@@ -274,16 +558,59 @@ impl Clock {
       .send(metronome::Message::Tempo(*self.get_tempo().deref()))
       .unwrap();
 
-    thread::spawn(move || loop {
-      if self.is_playing() {
-        self.tick();
-        metronome_tx
-          .send(metronome::Message::Time(self.time()))
-          .unwrap();
-      } else {
-        thread::sleep(Duration::from_millis(100));
+    thread::spawn(move || {
+      let mut timer = Timer::new(
+        *self.signature.lock().unwrap(),
+        *self.get_tempo(),
+        Arc::clone(&self.source),
+      );
+
+      while !self.shut_down.load(Ordering::SeqCst) {
+        // in external sync, ticks are driven by incoming pulses instead
+        if !self.is_playing() || self.is_external() {
+          thread::sleep(Duration::from_millis(10));
+          continue;
+        }
+
+        if self.restart_timer.swap(false, Ordering::SeqCst) {
+          self.tempo_changed.store(false, Ordering::SeqCst);
+          timer = Timer::new(
+            *self.signature.lock().unwrap(),
+            *self.get_tempo(),
+            Arc::clone(&self.source),
+          );
+        } else if self.tempo_changed.swap(false, Ordering::SeqCst) {
+          timer.set_tempo(*self.get_tempo());
+        }
+
+        match timer.next() {
+          TimerEvent::Tick(lateness) => {
+            if !self.is_playing() {
+              continue;
+            }
+            let time = self.tick();
+            if self.ramping.load(Ordering::SeqCst) {
+              self.step_ramp(time, &metronome_tx);
+            }
+            let _ = metronome_tx.send(metronome::Message::Time(time));
+
+            #[cfg(debug_assertions)]
+            self.timing_stats.record(lateness.as_micros() as u64);
+            #[cfg(not(debug_assertions))]
+            let _ = lateness;
+          }
+          TimerEvent::Pulse => {
+            if self.is_playing() {
+              self.send_midi_clock(ClockMsg::Pulse);
+            }
+          }
+        }
       }
-    });
+    })
+  }
+
+  fn send_midi_clock(&self, msg: ClockMsg) {
+    let _ = self.midi_tx.send(midi::Message::Clock(msg));
   }
 
   fn get_tempo(&self) -> MutexGuard<'_, Ratio<i64>> {
@@ -304,34 +631,91 @@ impl Clock {
     thread::spawn(move || {
       for control_message in &rx {
         match control_message {
-          Message::Reset => self.reset(),
+          Message::Reset => {
+            self.reset();
+            self.send_midi_clock(ClockMsg::SongPosition(self.time().song_position()));
+          }
           Message::StartStop => {
-            self.playing.fetch_xor(true, Ordering::SeqCst);
+            // transport follows the master while slaved
+            if self.is_external() {
+              continue;
+            }
+            let was_playing = self.playing.fetch_xor(true, Ordering::SeqCst);
+            let _ = metronome_tx.send(metronome::Message::Playing(!was_playing));
+            // resume on a fresh grid starting now
+            self.restart_timer.store(true, Ordering::SeqCst);
+
+            #[cfg(debug_assertions)]
+            if was_playing {
+              self.timing_stats.print_stats();
+              self.timing_stats.reset();
+            }
+
+            let msg = if was_playing {
+              ClockMsg::Stop
+            } else if self.time().ticks() == Ratio::from_integer(0) {
+              ClockMsg::Start
+            } else {
+              ClockMsg::Continue
+            };
+            self.send_midi_clock(msg);
           }
           Message::Signature(signature) => {
             self.set_signature(signature);
           }
           Message::Tap => {
+            // tempo and phase follow the master while slaved
+            if self.is_external() {
+              continue;
+            }
+            // the tap lands on the (quantized) beat, so the tick grid restarts from it
+            self.restart_timer.store(true, Ordering::SeqCst);
             if let Some(new_tempo) = self.tap() {
-              metronome_tx
-                .send(metronome::Message::Tempo(new_tempo))
-                .unwrap();
+              let _ = metronome_tx.send(metronome::Message::Tempo(new_tempo));
             }
           }
           Message::NudgeTempo(nudge) => {
             let mut _tempo = self.get_tempo();
             let old_tempo = _tempo;
             let new_tempo = *old_tempo + nudge;
-            metronome_tx
-              .send(metronome::Message::Tempo(new_tempo))
-              .unwrap();
+            let _ = metronome_tx.send(metronome::Message::Tempo(new_tempo));
           }
           Message::Tempo(tempo) => {
+            // a tempo set by hand (or by the sync master) takes over from a running ramp
+            if self.ramping.load(Ordering::SeqCst) {
+              self.set_ramp(None, &metronome_tx);
+            }
             let mut _tempo = self.get_tempo();
             *_tempo = tempo;
+            self.tempo_changed.store(true, Ordering::SeqCst);
+          }
+          Message::StartRamp(target, bars, curve) => {
+            if self.is_external() || target <= Ratio::from_integer(0) {
+              continue;
+            }
+            let signature = *self.signature.lock().unwrap();
+            let ramp = TempoRamp {
+              from: *self.get_tempo(),
+              to: target,
+              start: self.time().ticks(),
+              length: (bars * signature.ticks_per_bar()).max(Ratio::from_integer(1)),
+              curve,
+            };
+            self.set_ramp(Some(ramp), &metronome_tx);
+          }
+          Message::CancelRamp => {
+            self.set_ramp(None, &metronome_tx);
+          }
+          Message::SetSync(sync_mode) => {
+            self.set_sync(sync_mode);
+            let _ = metronome_tx.send(metronome::Message::Playing(false));
+          }
+          Message::ExternalClock(msg) => {
+            self.handle_external_clock(msg, &metronome_tx);
           }
         }
       }
+      self.shut_down.store(true, Ordering::SeqCst);
     });
 
     tx
@@ -363,21 +747,114 @@ impl Clock {
   //   });
   // }
 
+  pub fn set_sync(&self, sync_mode: SyncMode) {
+    self
+      .external
+      .store(sync_mode == SyncMode::External, Ordering::SeqCst);
+    self.playing.store(false, Ordering::SeqCst);
+    *self.external_sync.lock().unwrap() = ExternalSync::new();
+  }
+
+  fn handle_external_clock(&self, msg: ClockMsg, metronome_tx: &Sender<metronome::Message>) {
+    if !self.is_external() {
+      return;
+    }
+
+    // pass the master clock through to our own outputs
+    self.send_midi_clock(msg);
+
+    let mut sync = self.external_sync.lock().unwrap();
+    match msg {
+      ClockMsg::Start => {
+        sync.pulses = 0;
+        sync.awaiting_first_pulse = true;
+        self.reset();
+        self.playing.store(true, Ordering::SeqCst);
+        let _ = metronome_tx.send(metronome::Message::Playing(true));
+      }
+      ClockMsg::Continue => {
+        sync.awaiting_first_pulse = true;
+        self.playing.store(true, Ordering::SeqCst);
+        let _ = metronome_tx.send(metronome::Message::Playing(true));
+      }
+      ClockMsg::Stop => {
+        self.playing.store(false, Ordering::SeqCst);
+        let _ = metronome_tx.send(metronome::Message::Playing(false));
+      }
+      ClockMsg::SongPosition(pos) => {
+        sync.pulses = pos as i64 * MIDI_CLOCK_PULSES_PER_MIDI_BEAT;
+        sync.awaiting_first_pulse = true;
+        let signature = *self.signature.lock().unwrap();
+        *self.time.lock().unwrap() = Time::from_pulses(signature, sync.pulses);
+      }
+      ClockMsg::Pulse => {
+        if let Some(tempo) = sync.measure_pulse(self.source.now()) {
+          if tempo != *self.get_tempo() {
+            let _ = metronome_tx.send(metronome::Message::Tempo(tempo));
+          }
+        }
+
+        if !self.is_playing() {
+          return;
+        }
+
+        let signature = *self.signature.lock().unwrap();
+        let target = Time::from_pulses(signature, sync.advance());
+        drop(sync);
+
+        loop {
+          let mut time = self.time.lock().unwrap();
+          if time.ticks() >= target.ticks() {
+            break;
+          }
+          *time = time.next();
+          let next_time = *time;
+          drop(time);
+          let _ = metronome_tx.send(metronome::Message::Time(next_time));
+        }
+      }
+    }
+  }
+
+  fn set_ramp(&self, ramp: Option<TempoRamp>, metronome_tx: &Sender<metronome::Message>) {
+    *self.ramp.lock().unwrap() = ramp;
+    self.ramping.store(ramp.is_some(), Ordering::SeqCst);
+    let _ = metronome_tx.send(metronome::Message::Ramp(ramp));
+  }
+
+  // runs on the tick thread, so the new tempo applies from the very next tick
+  fn step_ramp(&self, time: Time, metronome_tx: &Sender<metronome::Message>) {
+    let Some(ramp) = *self.ramp.lock().unwrap() else {
+      return;
+    };
+
+    let tempo = ramp.tempo_at(time.ticks());
+    let mut current = self.get_tempo();
+    if *current != tempo {
+      *current = tempo;
+      self.tempo_changed.store(true, Ordering::SeqCst);
+      let _ = metronome_tx.send(metronome::Message::RampTempo(tempo));
+    }
+    drop(current);
+
+    if ramp.is_done(time.ticks()) {
+      self.set_ramp(None, metronome_tx);
+    }
+  }
+
   pub fn reset(&self) {
     let mut time = self.time.lock().unwrap();
-    let mut timer = self.timer.lock().unwrap();
     let signature = self.signature.lock().unwrap();
     *time = Time::new(*signature);
-    *timer = Timer::new(*signature);
+    self.restart_timer.store(true, Ordering::SeqCst);
   }
 
   pub fn set_signature(&self, signature: Signature) {
     let mut sig = self.signature.lock().unwrap();
     *sig = signature;
     let mut time = self.time.lock().unwrap();
-    let mut timer = self.timer.lock().unwrap();
     *time = Time::new(*sig);
-    *timer = Timer::new(*sig);
+    self.restart_timer.store(true, Ordering::SeqCst);
   }
 
   pub fn time(&self) -> Time {
@@ -385,11 +862,10 @@ impl Clock {
     *t
   }
 
-  pub fn tick(&self) -> Tick {
-    let nanos_until_tick = self.timer.lock().unwrap().next(*self.get_tempo().deref());
+  pub fn tick(&self) -> Time {
     let mut time = self.time.lock().unwrap();
     *time = time.next();
-    nanos_until_tick
+    *time
   }
 
   pub fn tap(&self) -> Option<Tempo> {
@@ -404,7 +880,9 @@ impl Clock {
     // if second tap on beat, adjust tempo
     if let Some(t) = *tap {
       let sig = self.signature.lock().unwrap();
-      let tap_nanos = Ratio::from_integer(duration_to_nanos(t.elapsed()));
+      let tap_nanos = Ratio::from_integer(duration_to_nanos(
+        self.source.now().saturating_duration_since(t),
+      ));
       if tap_nanos < sig.nanos_per_beat(*self.get_tempo().deref()) * 2 {
         let tap_beats_per_nanos = Ratio::from_integer(1) / tap_nanos;
         let tap_beats_per_seconds = tap_beats_per_nanos * Ratio::from_integer(NANOS_PER_SECOND);
@@ -413,12 +891,16 @@ impl Clock {
       }
     }
 
-    *tap = Some(Instant::now());
+    *tap = Some(self.source.now());
 
     next_tempo
   }
 }
 
+pub(super) fn nanos_to_duration(nanos: Tick) -> Duration {
+  Duration::from_nanos(nanos.floor().to_integer().max(0) as u64)
+}
+
 fn duration_to_nanos(duration: Duration) -> i64 {
   duration.as_secs() as i64 * 1_000_000_000 + duration.subsec_nanos() as i64
 }
@@ -427,3 +909,249 @@ fn round_to_nearest<T: Clone + Copy + Integer>(value: Ratio<T>, quantum: T) -> R
   let quantum_rat = Ratio::from_integer(quantum);
   (value * quantum_rat).round() / quantum_rat
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::timing::source::VirtualTime;
+
+  #[test]
+  fn test_time_from_pulses_follows_ticks_per_beat() {
+    let signature = Signature::default();
+    // 24 pulses per beat, 96 ticks per beat => four ticks every pulse
+    assert_eq!(
+      Time::from_pulses(signature, 5).ticks(),
+      Ratio::from_integer(20)
+    );
+    assert_eq!(
+      Time::from_pulses(signature, 24).ticks(),
+      Ratio::from_integer(96)
+    );
+
+    let coarse = Signature {
+      ticks_per_beat: Ratio::from_integer(4),
+      ..signature
+    };
+    // 4 ticks per beat => one tick every 6 pulses
+    assert_eq!(Time::from_pulses(coarse, 5).ticks(), Ratio::from_integer(0));
+    assert_eq!(Time::from_pulses(coarse, 6).ticks(), Ratio::from_integer(1));
+  }
+
+  #[test]
+  fn test_external_sync_first_pulse_keeps_position() {
+    let mut sync = ExternalSync::new();
+    assert_eq!(sync.advance(), 0);
+    assert_eq!(sync.advance(), 1);
+    sync.awaiting_first_pulse = true;
+    assert_eq!(sync.advance(), 1);
+  }
+
+  #[test]
+  fn test_deadline_retime_keeps_phase() {
+    let now = Instant::now();
+    let millis = |ms: i64| Ratio::from_integer(ms * 1_000_000);
+    let mut deadline = Deadline::new(now, 1, millis(200));
+
+    // half of the current step is left when the step length halves
+    deadline.retime(now + Duration::from_millis(100), millis(100));
+    assert_eq!(deadline.instant(), now + Duration::from_millis(150));
+  }
+
+  #[test]
+  fn test_timer_on_virtual_time_is_exact() {
+    let time = Arc::new(VirtualTime::free_running());
+    let start = time.now();
+    let mut timer = Timer::new(
+      Signature::default(),
+      Ratio::from_integer(120),
+      Arc::clone(&time) as Arc<dyn TimeSource>,
+    );
+
+    // 24 PPQN under 96 ticks per beat: a pulse every 4 ticks, the first one on the downbeat
+    let (mut pulses, mut ticks) = (0, 0);
+    while ticks < 4 {
+      match timer.next() {
+        TimerEvent::Pulse => pulses += 1,
+        TimerEvent::Tick(lateness) => {
+          assert_eq!(lateness, Duration::ZERO);
+          ticks += 1;
+        }
+      }
+    }
+
+    assert_eq!(pulses, 1);
+    // a 24th of a beat at 120 BPM
+    assert_eq!(time.now() - start, Duration::from_nanos(20_833_333));
+  }
+
+  #[test]
+  fn test_clock_plays_on_virtual_time() {
+    let time = Arc::new(VirtualTime::free_running());
+    let (midi_tx, midi_rx) = channel();
+    let (metronome_tx, metronome_rx) = channel();
+    let clock = Arc::new(Clock::new(
+      midi_tx,
+      Arc::clone(&time) as Arc<dyn TimeSource>,
+    ));
+    let tick_thread = Arc::clone(&clock).run_tick(metronome_tx.clone());
+    let clock_tx = clock.run(metronome_tx);
+    clock_tx.send(Message::StartStop).unwrap();
+
+    // every tick reaches the metronome in order, none skipped however fast time runs
+    let ticks: Vec<i64> = metronome_rx
+      .iter()
+      .filter_map(|msg| match msg {
+        metronome::Message::Time(time) => Some(time.ticks().to_integer()),
+        _ => None,
+      })
+      .take(96)
+      .collect();
+    assert_eq!(ticks, (1..=96).collect::<Vec<_>>());
+    // a beat at 120 BPM
+    assert!(time.elapsed() >= Duration::from_millis(500));
+
+    clock_tx.send(Message::StartStop).unwrap();
+    let clock_msgs: Vec<ClockMsg> = midi_rx
+      .iter()
+      .filter_map(|msg| match msg {
+        midi::Message::Clock(msg) => Some(msg),
+        _ => None,
+      })
+      .take_while(|msg| *msg != ClockMsg::Stop)
+      .collect();
+    assert!(clock_msgs.contains(&ClockMsg::Start));
+    // 24 PPQN, the pulse on the downbeat included
+    let pulses = clock_msgs
+      .iter()
+      .filter(|msg| **msg == ClockMsg::Pulse)
+      .count();
+    assert!(pulses >= 24);
+
+    // dropping the last control sender stops both threads
+    drop(clock_tx);
+    tick_thread.join().unwrap();
+  }
+
+  #[test]
+  fn test_clock_reset_sends_song_position_zero() {
+    let time = Arc::new(VirtualTime::free_running());
+    let (midi_tx, midi_rx) = channel();
+    let (metronome_tx, metronome_rx) = channel();
+    let clock = Arc::new(Clock::new(
+      midi_tx,
+      Arc::clone(&time) as Arc<dyn TimeSource>,
+    ));
+    let tick_thread = Arc::clone(&clock).run_tick(metronome_tx.clone());
+    let clock_tx = clock.run(metronome_tx);
+
+    // play a beat, so there is a position to go back from
+    clock_tx.send(Message::StartStop).unwrap();
+    metronome_rx
+      .iter()
+      .filter(|msg| matches!(msg, metronome::Message::Time(_)))
+      .take(96)
+      .for_each(drop);
+    clock_tx.send(Message::StartStop).unwrap();
+    clock_tx.send(Message::Reset).unwrap();
+
+    let song_position = midi_rx.iter().find_map(|msg| match msg {
+      midi::Message::Clock(ClockMsg::SongPosition(position)) => Some(position),
+      _ => None,
+    });
+    assert_eq!(song_position, Some(0));
+
+    drop(clock_tx);
+    tick_thread.join().unwrap();
+  }
+
+  #[test]
+  fn test_deadline_advance_skips_missed_steps() {
+    let now = Instant::now();
+    let mut deadline = Deadline::new(now, 1, Ratio::from_integer(10_000_000));
+
+    deadline.advance(now + Duration::from_millis(35));
+    assert_eq!(deadline.instant(), now + Duration::from_millis(40));
+  }
+
+  #[test]
+  fn test_time_position_wraps_in_odd_meter() {
+    let signature = Signature {
+      ticks_per_beat: Ratio::from_integer(4),
+      beats_per_bar: Ratio::from_integer(7),
+      bars_per_loop: Ratio::from_integer(2),
+    };
+    let at = |ticks: i64| Time::from_pulses(signature, ticks * 6).position();
+
+    assert_eq!(at(0), (1, 1, 1));
+    assert_eq!(at(5), (1, 2, 2));
+    assert_eq!(at(28), (2, 1, 1));
+    // back to the downbeat after 2 bars of 7/4
+    assert_eq!(at(56), (1, 1, 1));
+  }
+
+  #[test]
+  fn test_time_boundary_is_the_coarsest_grid_starting_at_tick() {
+    let signature = Signature {
+      ticks_per_beat: Ratio::from_integer(4),
+      beats_per_bar: Ratio::from_integer(3),
+      bars_per_loop: Ratio::from_integer(2),
+    };
+    let at = |ticks: i64| Time::from_pulses(signature, ticks * 6).boundary();
+
+    assert_eq!(at(0), Quantization::Loop);
+    assert_eq!(at(1), Quantization::Off);
+    assert_eq!(at(4), Quantization::Beat);
+    assert_eq!(at(12), Quantization::Bar);
+    assert_eq!(at(24), Quantization::Loop);
+    assert!(at(12) >= Quantization::Beat);
+  }
+
+  #[test]
+  fn test_linear_ramp_is_exact() {
+    let ramp = TempoRamp {
+      from: Ratio::from_integer(120),
+      to: Ratio::from_integer(121),
+      start: Ratio::from_integer(10),
+      length: Ratio::from_integer(3),
+      curve: RampCurve::Linear,
+    };
+
+    assert_eq!(
+      ramp.tempo_at(Ratio::from_integer(10)),
+      Ratio::from_integer(120)
+    );
+    assert_eq!(ramp.tempo_at(Ratio::from_integer(11)), Ratio::new(361, 3));
+    assert!(!ramp.is_done(Ratio::from_integer(12)));
+    assert_eq!(
+      ramp.tempo_at(Ratio::from_integer(13)),
+      Ratio::from_integer(121)
+    );
+    assert!(ramp.is_done(Ratio::from_integer(13)));
+    // holds the target once finished
+    assert_eq!(
+      ramp.tempo_at(Ratio::from_integer(20)),
+      Ratio::from_integer(121)
+    );
+  }
+
+  #[test]
+  fn test_exponential_ramp_keeps_equal_ratios() {
+    let ramp = TempoRamp {
+      from: Ratio::from_integer(100),
+      to: Ratio::from_integer(400),
+      start: Ratio::from_integer(0),
+      length: Ratio::from_integer(4),
+      curve: RampCurve::Exponential,
+    };
+
+    // halfway through a 4x change is a 2x change
+    assert_eq!(
+      ramp.tempo_at(Ratio::from_integer(2)),
+      Ratio::from_integer(200)
+    );
+    assert_eq!(
+      ramp.tempo_at(Ratio::from_integer(4)),
+      Ratio::from_integer(400)
+    );
+  }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::path::Path;
+
+use num::rational::Ratio;
+use num::ToPrimitive;
+
+use super::clock::{Tick, Time};
+
+pub static MIN_SWING: i64 = 50;
+pub static MAX_SWING: i64 = 75;
+pub static GROOVE_FILE_EXTENSION: &str = "groove";
+
+// grooves and swing are laid out on a sixteenth-note grid regardless of clock resolution
+static SIXTEENTHS_PER_BEAT: i64 = 4;
+
+/// Timing offset (in sixteenths, late only) and velocity scale of one sixteenth step
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GrooveStep {
+  pub offset: Tick,
+  pub velocity: f32,
+}
+
+impl Default for GrooveStep {
+  fn default() -> Self {
+    Self {
+      offset: Ratio::from_integer(0),
+      velocity: 1.0,
+    }
+  }
+}
+
+/// User-defined groove template, repeating every `steps.len()` sixteenths.
+///
+/// file format: one `<offset> <velocity>` pair per line, `#` starts a comment, eg.
+/// ```text
+/// # offset velocity
+/// 0.0  1.0
+/// 0.12 0.7
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Groove {
+  pub name: String,
+  steps: Vec<GrooveStep>,
+}
+
+impl Groove {
+  pub fn parse(name: &str, src: &str) -> Result<Self, String> {
+    let mut steps = Vec::new();
+
+    for (line_no, line) in src.lines().enumerate() {
+      let line = line.split('#').next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+
+      let mut fields = line.split_whitespace();
+      let offset = parse_field(fields.next(), line_no, "offset")?;
+      let velocity = match fields.next() {
+        Some(field) => parse_field(Some(field), line_no, "velocity")?,
+        None => 1.0,
+      };
+
+      if !(0.0..1.0).contains(&offset) {
+        return Err(format!(
+          "line {}: offset must be within [0, 1) of a sixteenth",
+          line_no + 1
+        ));
+      }
+      if velocity < 0.0 {
+        return Err(format!("line {}: velocity must be positive", line_no + 1));
+      }
+
+      steps.push(GrooveStep {
+        offset: Ratio::approximate_float(offset).unwrap_or(Ratio::from_integer(0)),
+        velocity: velocity as f32,
+      });
+    }
+
+    if steps.is_empty() {
+      return Err("groove has no steps".to_string());
+    }
+
+    Ok(Self {
+      name: name.to_string(),
+      steps,
+    })
+  }
+
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let name = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .ok_or("invalid groove filename")?;
+    let src = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    Self::parse(name, &src)
+  }
+
+  /// Every `*.groove` file within `dir`, sorted by name. Unreadable files are skipped.
+  pub fn load_dir(dir: &Path) -> Vec<Self> {
+    let Ok(entries) = fs::read_dir(dir) else {
+      return Vec::new();
+    };
+
+    let mut grooves: Vec<Self> = entries
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(GROOVE_FILE_EXTENSION))
+      .filter_map(|path| Self::load(&path).ok())
+      .collect();
+    grooves.sort_by(|a, b| a.name.cmp(&b.name));
+    grooves
+  }
+
+  pub fn step(&self, sixteenth: i64) -> GrooveStep {
+    self.steps[sixteenth.rem_euclid(self.steps.len() as i64) as usize]
+  }
+}
+
+fn parse_field(field: Option<&str>, line_no: usize, what: &str) -> Result<f64, String> {
+  field
+    .ok_or(format!("line {}: missing {}", line_no + 1, what))?
+    .parse::<f64>()
+    .map_err(|_| format!("line {}: invalid {}", line_no + 1, what))
+}
+
+/// Delay (in ticks) and velocity scale for `time`, combining swing and an optional groove.
+/// Swing is the share (in percent) of a sixteenth pair taken by its first note,
+/// 50 is straight and every off-beat sixteenth is pushed back as it grows.
+/// Ticks between two sixteenths are shifted proportionally, so the clock stretches
+/// smoothly and markers running at other rates are swung as well.
+pub fn shift(time: &Time, swing: i64, groove: Option<&Groove>) -> (Tick, f32) {
+  let ticks_per_sixteenth = time.signature().ticks_per_beat / SIXTEENTHS_PER_BEAT;
+  if ticks_per_sixteenth < Ratio::from_integer(1) {
+    // clock runs coarser than sixteenths, nothing to shift
+    return (Ratio::from_integer(0), 1.0);
+  }
+
+  let sixteenths = time.ticks() / ticks_per_sixteenth;
+  let sixteenth = sixteenths.floor().to_integer();
+  let phase = sixteenths.fract();
+
+  let (offset, velocity) = sixteenth_shift(sixteenth, swing, groove);
+  if phase == Ratio::from_integer(0) {
+    return (offset * ticks_per_sixteenth, velocity);
+  }
+
+  let (next_offset, _) = sixteenth_shift(sixteenth + 1, swing, groove);
+  let offset = offset * (Ratio::from_integer(1) - phase) + next_offset * phase;
+  (offset * ticks_per_sixteenth, velocity)
+}
+
+// offset (in sixteenths) and velocity scale at the start of a sixteenth
+fn sixteenth_shift(sixteenth: i64, swing: i64, groove: Option<&Groove>) -> (Tick, f32) {
+  let mut step = groove.map(|g| g.step(sixteenth)).unwrap_or_default();
+  if sixteenth % 2 == 1 {
+    let swing = swing.clamp(MIN_SWING, MAX_SWING);
+    step.offset += Ratio::new(2 * swing - 100, 100);
+  }
+
+  // keep every step ahead of the next one, so the tick order never changes
+  let max_offset = Ratio::new(99, 100);
+  let offset = if step.offset > max_offset {
+    max_offset
+  } else {
+    step.offset
+  };
+
+  (offset, step.velocity)
+}
+
+/// Scale a MIDI velocity, staying within the valid (non note-off) range
+pub fn scale_velocity(velocity: u8, scale: f32) -> u8 {
+  (velocity as f32 * scale)
+    .round()
+    .clamp(1.0, 127.0)
+    .to_u8()
+    .unwrap_or(velocity)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::core::timing::clock::Signature;
+
+  fn time_at(sixteenths: i64) -> Time {
+    // 6 pulses per sixteenth
+    Time::from_pulses(coarse_signature(), sixteenths * 6)
+  }
+
+  // one tick per sixteenth
+  fn coarse_signature() -> Signature {
+    Signature {
+      ticks_per_beat: Ratio::from_integer(4),
+      ..Signature::default()
+    }
+  }
+
+  #[test]
+  fn test_parse_groove() {
+    let groove = Groove::parse("mpc", "# offset velocity\n0 1\n0.25 0.5 # late\n\n0.1\n").unwrap();
+    assert_eq!(groove.steps.len(), 3);
+    assert_eq!(groove.step(1).offset, Ratio::new(1, 4));
+    assert_eq!(groove.step(1).velocity, 0.5);
+    assert_eq!(groove.step(2).velocity, 1.0);
+    // wraps around
+    assert_eq!(groove.step(4), groove.step(1));
+
+    assert!(Groove::parse("empty", "# nothing\n").is_err());
+    assert!(Groove::parse("early", "-0.1 1.0\n").is_err());
+    assert!(Groove::parse("bogus", "a b\n").is_err());
+  }
+
+  #[test]
+  fn test_swing_only_delays_off_beats() {
+    assert_eq!(shift(&time_at(0), 66, None).0, Ratio::from_integer(0));
+    assert_eq!(shift(&time_at(1), 50, None).0, Ratio::from_integer(0));
+    assert_eq!(shift(&time_at(1), 66, None).0, Ratio::new(32, 100));
+    assert_eq!(shift(&time_at(3), 75, None).0, Ratio::new(1, 2));
+  }
+
+  #[test]
+  fn test_groove_offset_is_capped_below_next_tick() {
+    let groove = Groove::parse("lazy", "0 1\n0.9 0.8\n").unwrap();
+    let (offset, velocity) = shift(&time_at(1), 75, Some(&groove));
+    assert_eq!(offset, Ratio::new(99, 100));
+    assert_eq!(velocity, 0.8);
+  }
+
+  #[test]
+  fn test_swing_stretches_ticks_between_sixteenths() {
+    // 24 ticks per sixteenth with the default signature
+    // 4 ticks per pulse
+    let at = |ticks: i64| {
+      shift(
+        &Time::from_pulses(Signature::default(), ticks / 4),
+        75,
+        None,
+      )
+      .0
+    };
+
+    assert_eq!(at(0), Ratio::from_integer(0));
+    // halfway into the on-beat sixteenth, half of the off-beat delay
+    assert_eq!(at(12), Ratio::from_integer(6));
+    assert_eq!(at(24), Ratio::from_integer(12));
+    // and back to straight at the next on-beat
+    assert_eq!(at(36), Ratio::from_integer(6));
+    assert_eq!(at(48), Ratio::from_integer(0));
+  }
+
+  #[test]
+  fn test_scale_velocity_is_clamped() {
+    assert_eq!(scale_velocity(100, 0.5), 50);
+    assert_eq!(scale_velocity(100, 2.0), 127);
+    assert_eq!(scale_velocity(100, 0.0), 1);
+  }
+}
@@ -1,13 +1,23 @@
+use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use cursive::views::{Dialog, TextView};
+use num::rational::Ratio;
 use num::ToPrimitive;
 
+use crate::core::midi::{self, ClockMsg};
+use crate::core::{consts, utils};
 use crate::view::common::playhead_controller;
 
+use super::automation::{TempoLane, TempoPoint};
 use super::clock;
+use super::groove::{self, Groove};
+use super::source::TimeSource;
 
 #[derive(Clone, Debug)]
 pub enum Message {
@@ -19,6 +29,19 @@ pub enum Message {
   StartStop,
   NudgeTempo(clock::NudgeTempo),
   Tap,
+  SetSync(clock::SyncMode),
+  ExternalClock(ClockMsg),
+  SetSwing(i64),
+  NudgeSwing(i64),
+  SetGroove(Option<Groove>),
+  Playing(bool),
+  RampTempo(clock::Tempo),
+  Ramp(Option<clock::TempoRamp>),
+  StartRamp(clock::Tempo, i64, clock::RampCurve), // (target, bars, curve)
+  CancelRamp,
+  SaveTempoLane,
+  LoadTempoLane,
+  ClearTempoLane,
 }
 
 #[derive(Debug)]
@@ -26,11 +49,20 @@ pub struct Metronome {
   pub tx: Sender<Message>,
   pub rx: Receiver<Message>,
   pub marker_tx: Sender<playhead_controller::Message>,
+  pub midi_tx: Sender<midi::Message>,
   cb_sink: cursive::CbSink,
+  current_tempo: Arc<Mutex<i64>>,
+  source: Arc<dyn TimeSource>,
 }
 
 impl Metronome {
-  pub fn new(cb_sink: cursive::CbSink, marker_tx: Sender<playhead_controller::Message>) -> Self {
+  pub fn new(
+    cb_sink: cursive::CbSink,
+    marker_tx: Sender<playhead_controller::Message>,
+    midi_tx: Sender<midi::Message>,
+    current_tempo: Arc<Mutex<i64>>,
+    source: Arc<dyn TimeSource>,
+  ) -> Self {
     let (tx, rx) = channel();
 
     Self {
@@ -38,21 +70,43 @@ impl Metronome {
       rx,
       cb_sink,
       marker_tx,
+      midi_tx,
+      current_tempo,
+      source,
     }
   }
 
   pub fn run(self) {
-    let clock = Arc::new(clock::Clock::new());
+    let clock = Arc::new(clock::Clock::new(
+      self.midi_tx.clone(),
+      Arc::clone(&self.source),
+    ));
     let metronome_tx_cloned = self.tx.clone();
     let metronome_tx_cloned_2 = self.tx.clone();
     let clock_cloned = Arc::clone(&clock);
     let clock_tx = clock.run(metronome_tx_cloned);
     clock_cloned.run_tick(metronome_tx_cloned_2);
+    let groove_tx = Self::run_groove(
+      self.marker_tx.clone(),
+      self.midi_tx.clone(),
+      Arc::clone(&self.source),
+    );
 
-    for control_message in self.rx {
+    let mut signature = clock::Signature::default();
+    let mut tempo = clock::Tempo::from_integer(consts::DEFAULT_TEMPO);
+    let mut swing = consts::DEFAULT_SWING;
+    let mut groove: Option<Groove> = None;
+    let mut ramp: Option<clock::TempoRamp> = None;
+    let mut tempo_lane = TempoLane::new();
+    let mut last_time: Option<clock::Time> = None;
+    // a redraw per tick is plenty at 4 ticks per beat, but not at higher clock resolutions
+    let mut position_throttler = utils::Throttler::new(Duration::from_millis(16));
+
+    for control_message in &self.rx {
       match control_message {
         Message::Reset => {
           clock_tx.send(clock::Message::Reset).unwrap();
+          self.update_position_status(clock::Time::new(signature));
         }
         Message::StartStop => {
           clock_tx.send(clock::Message::StartStop).unwrap();
@@ -63,29 +117,242 @@ impl Metronome {
         Message::Tap => {
           clock_tx.send(clock::Message::Tap).unwrap();
         }
-        // sent by clock
-        Message::Signature(signature) => {
+        Message::SetSync(sync_mode) => {
+          clock_tx.send(clock::Message::SetSync(sync_mode)).unwrap();
+        }
+        // sent by midi input
+        Message::ExternalClock(msg) => {
+          clock_tx.send(clock::Message::ExternalClock(msg)).unwrap();
+        }
+        // sent by clock and UI, the clock restarts from the downbeat
+        Message::Signature(new_signature) => {
+          signature = new_signature;
           clock_tx.send(clock::Message::Signature(signature)).unwrap();
+          let _ = self.midi_tx.send(midi::Message::SetSignature(signature));
+          self
+            .marker_tx
+            .send(playhead_controller::Message::SetSignature(signature))
+            .unwrap();
+          self.update_position_status(clock::Time::new(signature));
         }
         // sent by clock
-        Message::Tempo(tempo) => {
+        Message::Tempo(new_tempo) => {
+          tempo = new_tempo;
           clock_tx.send(clock::Message::Tempo(tempo)).unwrap();
-
-          // Forward tempo to marker as BPM (convert from Ratio to usize)
-          let bpm = tempo.to_integer() as usize;
+          self.publish_tempo(tempo);
+        }
+        // sent by clock, which already runs at the ramped tempo
+        Message::RampTempo(new_tempo) => {
+          // a ramp moves every tick, the marker, midi out and status only need whole BPM changes
+          let bpm_changed = new_tempo.to_integer() != tempo.to_integer();
+          tempo = new_tempo;
+          if bpm_changed {
+            self.publish_tempo(tempo);
+          }
+        }
+        // sent by clock whenever a ramp starts or ends
+        Message::Ramp(new_ramp) => {
+          ramp = new_ramp;
+          self.update_ramp_status(ramp, &tempo_lane);
+        }
+        Message::StartRamp(target, bars, curve) => {
+          // performed ramps are recorded, so a set can be replayed from the lane
+          let bar = last_time
+            .map(|t| t.bars().floor().to_integer())
+            .unwrap_or(0);
+          tempo_lane.insert(TempoPoint {
+            bar,
+            target,
+            bars,
+            curve,
+          });
+          clock_tx
+            .send(clock::Message::StartRamp(
+              target,
+              Ratio::from_integer(bars),
+              curve,
+            ))
+            .unwrap();
+          self.update_ramp_status(ramp, &tempo_lane);
+        }
+        Message::CancelRamp => {
+          clock_tx.send(clock::Message::CancelRamp).unwrap();
+        }
+        Message::SaveTempoLane => {
+          if let Err(e) = tempo_lane.save(&tempo_lane_path()) {
+            self.report(format!("Error saving tempo lane: {}", e));
+          }
+        }
+        Message::LoadTempoLane => match TempoLane::load(&tempo_lane_path()) {
+          Ok(lane) => {
+            tempo_lane = lane;
+            self.update_ramp_status(ramp, &tempo_lane);
+          }
+          Err(e) => self.report(format!("Error loading tempo lane: {}", e)),
+        },
+        Message::ClearTempoLane => {
+          tempo_lane = TempoLane::new();
+          self.update_ramp_status(ramp, &tempo_lane);
+        }
+        Message::SetSwing(new_swing) => {
+          swing = new_swing.clamp(groove::MIN_SWING, groove::MAX_SWING);
+          self.update_swing_status(swing, groove.as_ref());
+        }
+        Message::NudgeSwing(nudge) => {
+          swing = (swing + nudge).clamp(groove::MIN_SWING, groove::MAX_SWING);
+          self.update_swing_status(swing, groove.as_ref());
+        }
+        Message::SetGroove(new_groove) => {
+          groove = new_groove;
+          self.update_swing_status(swing, groove.as_ref());
+        }
+        // sent by clock
+        Message::Playing(playing) => {
+          // nothing is left hanging once the transport stops
+          if !playing {
+            let _ = self.midi_tx.send(midi::Message::FlushNotes());
+          }
           self
             .marker_tx
-            .send(playhead_controller::Message::SetTempo(bpm))
+            .send(playhead_controller::Message::SetPlaying(playing))
             .unwrap();
         }
         Message::Time(time) => {
+          last_time = Some(time);
+
+          // held changes land ahead of the tick that starts the boundary
+          let boundary = time.boundary();
+          if boundary >= clock::Quantization::Bar && !tempo_lane.is_empty() {
+            let bar = time.bars().floor().to_integer();
+            if let Some(point) = tempo_lane.point_at(bar) {
+              clock_tx
+                .send(clock::Message::StartRamp(
+                  point.target,
+                  Ratio::from_integer(point.bars),
+                  point.curve,
+                ))
+                .unwrap();
+            }
+          }
+          if boundary != clock::Quantization::Off {
+            self
+              .marker_tx
+              .send(playhead_controller::Message::Boundary(boundary))
+              .unwrap();
+          }
+
+          // swing/groove can only delay a tick, it is held back until its shifted deadline
+          let (offset, velocity_scale) = groove::shift(&time, swing, groove.as_ref());
+          let delay = clock::nanos_to_duration(offset * signature.nanos_per_tick(tempo));
           let tick = time.ticks().to_usize().unwrap();
-          self
-            .marker_tx
-            .send(playhead_controller::Message::SetActivePos(tick))
+          groove_tx
+            .send((self.source.now() + delay, tick, velocity_scale))
             .unwrap();
+
+          position_throttler.call(|| self.update_position_status(time));
         }
       }
     }
   }
+
+  // ticks come in order and are never delayed past the next one, so a FIFO keeps them sorted
+  fn run_groove(
+    marker_tx: Sender<playhead_controller::Message>,
+    midi_tx: Sender<midi::Message>,
+    source: Arc<dyn TimeSource>,
+  ) -> Sender<(Instant, usize, f32)> {
+    let (tx, rx) = channel::<(Instant, usize, f32)>();
+
+    thread::Builder::new()
+      .name("groove".to_string())
+      .spawn(move || {
+        for (deadline, tick, velocity_scale) in rx {
+          source.wait_until(deadline);
+          // gated notes are released on the same (shifted) ticks their note-ons land on
+          let _ = midi_tx.send(midi::Message::Tick(tick));
+          marker_tx
+            .send(playhead_controller::Message::SetActivePos(
+              tick,
+              velocity_scale,
+            ))
+            .unwrap();
+        }
+      })
+      .expect("Failed to spawn groove thread");
+
+    tx
+  }
+
+  fn publish_tempo(&self, tempo: clock::Tempo) {
+    // Forward tempo to marker as BPM (convert from Ratio to usize)
+    let bpm = tempo.to_integer() as usize;
+    // keep BPM nudges going from the tempo set by tap/sync/ramps
+    *self.current_tempo.lock().unwrap() = tempo.round().to_integer();
+
+    self
+      .marker_tx
+      .send(playhead_controller::Message::SetTempo(bpm))
+      .unwrap();
+
+    self
+      .cb_sink
+      .send(Box::new(move |s| {
+        s.call_on_name(consts::bpm_status_unit_view, |view: &mut TextView| {
+          view.set_content(utils::build_bpm_status_str(bpm));
+        });
+      }))
+      .unwrap();
+  }
+
+  fn update_ramp_status(&self, ramp: Option<clock::TempoRamp>, tempo_lane: &TempoLane) {
+    let status = utils::build_ramp_status_str(
+      ramp.map(|r| (r.to.round().to_integer(), r.curve.name())),
+      tempo_lane.len(),
+    );
+    self
+      .cb_sink
+      .send(Box::new(move |s| {
+        s.call_on_name(consts::ramp_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
+  }
+
+  fn report(&self, error: String) {
+    let _ = self.cb_sink.send(Box::new(move |s| {
+      s.add_layer(Dialog::info(error));
+    }));
+  }
+
+  fn update_position_status(&self, time: clock::Time) {
+    self
+      .cb_sink
+      .send(Box::new(move |s| {
+        s.call_on_name(consts::bbt_status_unit_view, |view: &mut TextView| {
+          view.set_content(utils::build_bbt_status_str(time.position()));
+        });
+      }))
+      .unwrap();
+  }
+
+  fn update_swing_status(&self, swing: i64, groove: Option<&Groove>) {
+    let status = utils::build_swing_status_str(swing, groove.map(|g| g.name.as_str()));
+    self
+      .cb_sink
+      .send(Box::new(move |s| {
+        s.call_on_name(consts::swing_status_unit_view, |view: &mut TextView| {
+          view.set_content(status);
+        });
+      }))
+      .unwrap();
+  }
+}
+
+// the tempo lane is stored next to the text contents, eg. ~/.anupars/tempo.lane
+fn tempo_lane_path() -> PathBuf {
+  dirs::home_dir()
+    .unwrap_or_default()
+    .join(consts::DEFAULT_APP_DIRECTORY)
+    .join(consts::DEFAULT_TEMPO_LANE_FILENAME)
 }
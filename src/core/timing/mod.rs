@@ -1,2 +1,5 @@
+pub mod automation;
 pub mod clock;
+pub mod groove;
 pub mod metronome;
+pub mod source;
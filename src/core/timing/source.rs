@@ -0,0 +1,143 @@
+use std::fmt::Debug;
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+static SPIN_THRESHOLD_NANOS: u64 = 500_000;
+
+/// Where the engine reads the time from and how it waits for a deadline
+pub trait TimeSource: Debug + Send + Sync {
+  fn now(&self) -> Instant;
+
+  fn wait_until(&self, deadline: Instant);
+}
+
+/// Wall clock time
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealTime;
+
+impl TimeSource for RealTime {
+  fn now(&self) -> Instant {
+    Instant::now()
+  }
+
+  // hybrid wait: coarse sleep until close to the deadline, then spin for sub-millisecond precision
+  fn wait_until(&self, deadline: Instant) {
+    let spin_threshold = Duration::from_nanos(SPIN_THRESHOLD_NANOS);
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining > spin_threshold {
+      sleep(remaining - spin_threshold);
+    }
+    while Instant::now() < deadline {
+      thread::yield_now();
+    }
+  }
+}
+
+/// Deterministic time that only moves when told to.
+///
+/// A stepped source blocks every waiter until `advance`/`advance_to` passes its deadline,
+/// so tests can walk the engine tick by tick. A free running source jumps straight to
+/// any deadline it is asked to wait for, which renders faster than real time.
+#[derive(Debug)]
+pub struct VirtualTime {
+  origin: Instant,
+  elapsed: Mutex<Duration>,
+  moved: Condvar,
+  free_running: bool,
+}
+
+impl VirtualTime {
+  #[cfg(test)]
+  pub fn stepped() -> Self {
+    Self::new(false)
+  }
+
+  pub fn free_running() -> Self {
+    Self::new(true)
+  }
+
+  fn new(free_running: bool) -> Self {
+    Self {
+      origin: Instant::now(),
+      elapsed: Mutex::new(Duration::ZERO),
+      moved: Condvar::new(),
+      free_running,
+    }
+  }
+
+  pub fn elapsed(&self) -> Duration {
+    *self.elapsed.lock().unwrap()
+  }
+
+  #[cfg(test)]
+  pub fn advance(&self, duration: Duration) {
+    let mut elapsed = self.elapsed.lock().unwrap();
+    *elapsed += duration;
+    self.moved.notify_all();
+  }
+
+  /// Move forward to `instant`, never backwards
+  pub fn advance_to(&self, instant: Instant) {
+    let mut elapsed = self.elapsed.lock().unwrap();
+    *elapsed = (*elapsed).max(instant.saturating_duration_since(self.origin));
+    self.moved.notify_all();
+  }
+}
+
+impl TimeSource for VirtualTime {
+  fn now(&self) -> Instant {
+    self.origin + self.elapsed()
+  }
+
+  fn wait_until(&self, deadline: Instant) {
+    if self.free_running {
+      self.advance_to(deadline);
+      return;
+    }
+
+    let target = deadline.saturating_duration_since(self.origin);
+    let elapsed = self.elapsed.lock().unwrap();
+    let _elapsed = self
+      .moved
+      .wait_while(elapsed, |elapsed| *elapsed < target)
+      .unwrap();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::sync::Arc;
+
+  #[test]
+  fn test_free_running_time_jumps_to_deadlines() {
+    let time = VirtualTime::free_running();
+    let start = time.now();
+
+    time.wait_until(start + Duration::from_millis(250));
+    assert_eq!(time.now() - start, Duration::from_millis(250));
+
+    // never moves backwards
+    time.wait_until(start);
+    assert_eq!(time.elapsed(), Duration::from_millis(250));
+  }
+
+  #[test]
+  fn test_stepped_time_blocks_until_advanced() {
+    let time = Arc::new(VirtualTime::stepped());
+    let deadline = time.now() + Duration::from_millis(10);
+
+    let waiter = {
+      let time = Arc::clone(&time);
+      thread::spawn(move || {
+        time.wait_until(deadline);
+        time.now()
+      })
+    };
+
+    time.advance(Duration::from_millis(4));
+    time.advance(Duration::from_millis(6));
+    assert_eq!(waiter.join().unwrap(), deadline);
+  }
+}
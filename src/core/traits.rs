@@ -151,6 +151,23 @@ impl<T: Printable + Copy> Matrix<T> {
     }
   }
 
+  /// Render a cell inside a pinned region, dimmer than the marker
+  fn render_region_cell(
+    &self,
+    printer: &Printer,
+    x: usize,
+    y: usize,
+    cell_index: usize,
+    text_matcher: &Option<HashMap<usize, Match>>,
+  ) {
+    let style = Style::from_color_style(ColorStyle::secondary());
+    let display_char = match text_matcher {
+      Some(matcher) if matcher.contains_key(&cell_index) => "*".to_string(),
+      _ => self.get_display_char(x, y),
+    };
+    printer.print_styled((x, y), &SpannedString::styled(display_char, style));
+  }
+
   /// Print the matrix to the given printer with marker UI highlighting
   pub fn print(&self, printer: &Printer, marker_ui: &MarkerUI) {
     let MarkerUI {
@@ -158,6 +175,7 @@ impl<T: Printable + Copy> Matrix<T> {
       marker_pos,
       marker_area,
       actived_pos,
+      regions,
       ..
     } = marker_ui;
 
@@ -195,6 +213,15 @@ impl<T: Printable + Copy> Matrix<T> {
           } else {
             self.render_marker_area_cell(printer, x, y, cell_index, marker_ui);
           }
+        } else if let Some((_, region_active_pos)) = regions
+          .iter()
+          .find(|(region_area, _)| region_area.contains(pos.into()))
+        {
+          if region_active_pos.eq(&pos) {
+            self.render_active_marker(printer, pos, cell_index, text_matcher);
+          } else {
+            self.render_region_cell(printer, x, y, cell_index, text_matcher);
+          }
         }
       }
     }
@@ -0,0 +1,151 @@
+//! Transpose track: the keys held on a MIDI keyboard set the root the grid is played from
+
+use std::collections::BTreeSet;
+
+use super::scale::ScaleMode;
+
+/// Follows the keys held on a MIDI keyboard, the lowest one is the root
+#[derive(Debug, Default)]
+pub struct KeyTracker {
+  held: BTreeSet<u8>, // MIDI note numbers
+  root: u8,           // pitch class, 0-11
+  latch: bool,        // keep the root once every key is released
+  detect_scale: bool,
+}
+
+impl KeyTracker {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the new root when unlatching released it
+  pub fn set_latch(&mut self, latch: bool) -> Option<u8> {
+    self.latch = latch;
+    match !latch && self.held.is_empty() {
+      true => self.set_root(0),
+      false => None,
+    }
+  }
+
+  pub fn set_detect_scale(&mut self, detect_scale: bool) {
+    self.detect_scale = detect_scale;
+  }
+
+  /// Returns the new root when it changed
+  pub fn note_on(&mut self, note: u8) -> Option<u8> {
+    self.held.insert(note);
+    self.follow_held()
+  }
+
+  /// Returns the new root when it changed
+  pub fn note_off(&mut self, note: u8) -> Option<u8> {
+    self.held.remove(&note);
+    if self.held.is_empty() {
+      return match self.latch {
+        true => None,
+        false => self.set_root(0),
+      };
+    }
+    self.follow_held()
+  }
+
+  /// Back to an untransposed grid
+  pub fn reset(&mut self) -> Option<u8> {
+    self.held.clear();
+    self.set_root(0)
+  }
+
+  /// The scale (and root) fitting every held key, once enough keys are held to tell
+  pub fn held_scale(&self) -> Option<(u8, ScaleMode)> {
+    if !self.detect_scale {
+      return None;
+    }
+    let pitch_classes: BTreeSet<u8> = self.held.iter().map(|note| note % 12).collect();
+    detect_scale(&pitch_classes, self.root)
+  }
+
+  fn follow_held(&mut self) -> Option<u8> {
+    let lowest = *self.held.first()?;
+    self.set_root(lowest % 12)
+  }
+
+  fn set_root(&mut self, root: u8) -> Option<u8> {
+    (self.root != root).then(|| {
+      self.root = root;
+      root
+    })
+  }
+}
+
+// fewer keys fit too many scales to be worth switching
+static MIN_KEYS_FOR_DETECTION: usize = 3;
+
+/// First scale (in `ScaleMode::all` order) on `root` containing every pitch class
+pub fn detect_scale(pitch_classes: &BTreeSet<u8>, root: u8) -> Option<(u8, ScaleMode)> {
+  if pitch_classes.len() < MIN_KEYS_FOR_DETECTION {
+    return None;
+  }
+  ScaleMode::all()
+    .iter()
+    .filter(|scale| **scale != ScaleMode::Chromatic)
+    .find(|scale| {
+      pitch_classes
+        .iter()
+        .all(|pc| scale.contains_note((pc + 12 - root) % 12))
+    })
+    .map(|scale| (root, *scale))
+}
+
+/// Move a (note_index, octave) pair up by `root` semitones
+pub fn transpose((note_index, octave): (u8, u8), root: u8) -> (u8, u8) {
+  let note = note_index + root % 12;
+  (note % 12, octave + note / 12)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_lowest_held_key_is_the_root() {
+    let mut keys = KeyTracker::new();
+    assert_eq!(keys.note_on(62), Some(2)); // D
+    assert_eq!(keys.note_on(67), None); // G above, D stays the root
+    assert_eq!(keys.note_on(57), Some(9)); // A below
+    assert_eq!(keys.note_off(57), Some(2));
+  }
+
+  #[test]
+  fn test_latch_keeps_the_root_after_release() {
+    let mut keys = KeyTracker::new();
+    keys.note_on(64);
+    assert_eq!(keys.note_off(64), Some(0));
+
+    keys.set_latch(true);
+    keys.note_on(64);
+    assert_eq!(keys.note_off(64), None);
+    // unlatching lets go of E
+    assert_eq!(keys.set_latch(false), Some(0));
+  }
+
+  #[test]
+  fn test_detect_scale_from_held_keys() {
+    let mut keys = KeyTracker::new();
+    keys.set_detect_scale(true);
+    keys.note_on(57); // A
+    keys.note_on(60); // C
+    assert_eq!(keys.held_scale(), None);
+    keys.note_on(64); // E
+    assert_eq!(keys.held_scale(), Some((9, ScaleMode::Minor)));
+
+    let c_major: BTreeSet<u8> = [0, 4, 7].into_iter().collect();
+    assert_eq!(detect_scale(&c_major, 0), Some((0, ScaleMode::Major)));
+  }
+
+  #[test]
+  fn test_transpose_carries_the_octave() {
+    assert_eq!(transpose((0, 3), 0), (0, 3));
+    assert_eq!(transpose((9, 3), 2), (11, 3));
+    assert_eq!(transpose((11, 3), 2), (1, 4));
+  }
+}
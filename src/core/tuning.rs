@@ -0,0 +1,444 @@
+//! Microtonal tunings from Scala files: a `.scl` scale with any number of notes per period,
+//! mapped onto MIDI keys by an optional `.kbm` keyboard mapping of the same name.
+//!
+//! See <https://www.huygens-fokker.org/scala/scl_format.html> and
+//! <https://www.huygens-fokker.org/scala/help.htm#mappings>
+
+use std::fs;
+use std::path::Path;
+
+pub static SCALA_FILE_EXTENSION: &str = "scl";
+pub static KEYBOARD_MAPPING_FILE_EXTENSION: &str = "kbm";
+
+// middle C (MIDI 60), so the keys of an unmapped scale start where the 12-TET keyboards do
+static DEFAULT_MIDDLE_NOTE: u8 = 60;
+static DEFAULT_REFERENCE_FREQUENCY: f64 = 261.625_565_300_598_6;
+static PITCH_BEND_CENTER: i32 = 0x2000;
+static PITCH_BEND_MAX: i32 = 0x3FFF;
+static MTS_KEYS_PER_MESSAGE: usize = 64;
+
+/// How the tuned pitches reach the receiver
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RetuneMode {
+  #[default]
+  Mts, // MIDI Tuning Standard, the receiver retunes its keys once per tuning
+  PitchBend, // the nearest 12-TET key, bent per note (with MPE, or one note per channel)
+}
+
+impl RetuneMode {
+  pub fn name(&self) -> &'static str {
+    match self {
+      RetuneMode::Mts => "MTS",
+      RetuneMode::PitchBend => "Pitch Bend",
+    }
+  }
+}
+
+/// A `.kbm` keyboard mapping: which scale degree every key plays, and the key tuned to a frequency
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+  map_size: usize, // keys per repetition of the mapping, 0 maps every key to the next degree
+  first_note: u8,
+  last_note: u8,
+  middle_note: u8, // plays degree 0
+  reference_note: u8,
+  reference_frequency: f64,
+  octave_degree: usize, // degree the mapping repeats at, 0 for the period of the scale
+  mapping: Vec<Option<usize>>, // degree per key of the mapping, `None` for an unmapped key (`x`)
+}
+
+impl Default for Keymap {
+  fn default() -> Self {
+    Self {
+      map_size: 0,
+      first_note: 0,
+      last_note: 127,
+      middle_note: DEFAULT_MIDDLE_NOTE,
+      reference_note: DEFAULT_MIDDLE_NOTE,
+      reference_frequency: DEFAULT_REFERENCE_FREQUENCY,
+      octave_degree: 0,
+      mapping: Vec::new(),
+    }
+  }
+}
+
+impl Keymap {
+  pub fn parse(src: &str) -> Result<Self, String> {
+    let mut lines = src
+      .lines()
+      .enumerate()
+      .filter(|(_, line)| !line.starts_with('!'))
+      .map(|(line_no, line)| (line_no, line.split_whitespace().next().unwrap_or("")));
+
+    let map_size = parse_field(lines.next(), "map size")?;
+    let first_note = parse_field::<u8>(lines.next(), "first note")?.min(127);
+    let last_note = parse_field::<u8>(lines.next(), "last note")?.min(127);
+    let middle_note = parse_field::<u8>(lines.next(), "middle note")?.min(127);
+    let reference_note = parse_field::<u8>(lines.next(), "reference note")?.min(127);
+    let reference_frequency = parse_field::<f64>(lines.next(), "reference frequency")?;
+    if reference_frequency <= 0.0 {
+      return Err("reference frequency must be positive".to_string());
+    }
+    let octave_degree = parse_field(lines.next(), "formal octave degree")?;
+
+    // keys left out at the end are unmapped
+    let mut mapping = Vec::with_capacity(map_size);
+    for (line_no, entry) in lines.take(map_size) {
+      mapping.push(match entry {
+        "x" | "" => None,
+        degree => Some(
+          degree
+            .parse::<usize>()
+            .map_err(|_| format!("line {}: invalid degree", line_no + 1))?,
+        ),
+      });
+    }
+    mapping.resize(map_size, None);
+
+    Ok(Self {
+      map_size,
+      first_note,
+      last_note,
+      middle_note,
+      reference_note,
+      reference_frequency,
+      octave_degree,
+      mapping,
+    })
+  }
+}
+
+/// A scale loaded from a `.scl` file, with its keyboard mapping
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tuning {
+  pub name: String,
+  pub description: String,
+  pitches: Vec<f64>, // cents of degrees 1..=n above degree 0, the last one is the period
+  keymap: Keymap,
+}
+
+impl Tuning {
+  pub fn parse(name: &str, scl: &str, kbm: Option<&str>) -> Result<Self, String> {
+    let mut lines = scl
+      .lines()
+      .enumerate()
+      .filter(|(_, line)| !line.starts_with('!'));
+
+    let description = lines.next().map_or("", |(_, line)| line.trim()).to_string();
+    let (line_no, count) = lines.next().ok_or("missing number of notes")?;
+    let count = count
+      .trim()
+      .parse::<usize>()
+      .map_err(|_| format!("line {}: invalid number of notes", line_no + 1))?;
+    if count == 0 {
+      return Err(format!(
+        "line {}: a scale needs at least one note",
+        line_no + 1
+      ));
+    }
+
+    let pitches = lines
+      .take(count)
+      .map(|(line_no, line)| parse_pitch(line).map_err(|e| format!("line {}: {}", line_no + 1, e)))
+      .collect::<Result<Vec<_>, _>>()?;
+    if pitches.len() < count {
+      return Err(format!("expected {} notes, found {}", count, pitches.len()));
+    }
+
+    let keymap = match kbm {
+      Some(kbm) => Keymap::parse(kbm).map_err(|e| format!("{}.kbm: {}", name, e))?,
+      None => Keymap::default(),
+    };
+
+    let tuning = Self {
+      name: name.to_string(),
+      description,
+      pitches,
+      keymap,
+    };
+    if tuning.key_cents(tuning.keymap.reference_note).is_none() {
+      return Err(format!("{}.kbm: the reference note is not mapped", name));
+    }
+    Ok(tuning)
+  }
+
+  /// A `.scl` file, with the `.kbm` file next to it when there is one
+  pub fn load(path: &Path) -> Result<Self, String> {
+    let name = path
+      .file_stem()
+      .and_then(|stem| stem.to_str())
+      .ok_or("invalid scale filename")?;
+    let scl = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let kbm = fs::read_to_string(path.with_extension(KEYBOARD_MAPPING_FILE_EXTENSION)).ok();
+    Self::parse(name, &scl, kbm.as_deref())
+  }
+
+  /// Every `*.scl` file within `dir`, sorted by name. Unreadable files are skipped.
+  pub fn load_dir(dir: &Path) -> Vec<Self> {
+    let Ok(entries) = fs::read_dir(dir) else {
+      return Vec::new();
+    };
+
+    let mut tunings: Vec<Self> = entries
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(SCALA_FILE_EXTENSION))
+      .filter_map(|path| Self::load(&path).ok())
+      .collect();
+    tunings.sort_by(|a, b| a.name.cmp(&b.name));
+    tunings
+  }
+
+  // notes per period
+  fn len(&self) -> usize {
+    self.pitches.len()
+  }
+
+  // cents of any degree above degree 0, past the period as well
+  fn degree_cents(&self, degree: usize) -> f64 {
+    let period = self.pitches[self.len() - 1];
+    let periods = (degree / self.len()) as f64;
+    match degree % self.len() {
+      0 => periods * period,
+      idx => periods * period + self.pitches[idx - 1],
+    }
+  }
+
+  // keys from the middle note to `key`, split into (repetitions of the mapping, key within it)
+  fn key_position(&self, key: u8) -> Option<(i32, usize)> {
+    let keymap = &self.keymap;
+    if !(keymap.first_note..=keymap.last_note).contains(&key) {
+      return None;
+    }
+    let keys = key as i32 - keymap.middle_note as i32;
+    let size = match keymap.map_size {
+      0 => self.len(),
+      size => size,
+    } as i32;
+    Some((keys.div_euclid(size), keys.rem_euclid(size) as usize))
+  }
+
+  /// The scale degree `key` plays, `None` when the key is not mapped
+  pub fn degree_of(&self, key: u8) -> Option<usize> {
+    let (_, idx) = self.key_position(key)?;
+    match self.keymap.map_size {
+      0 => Some(idx),
+      _ => self.keymap.mapping[idx],
+    }
+  }
+
+  // cents of `key` above the middle note
+  fn key_cents(&self, key: u8) -> Option<f64> {
+    let (repetitions, _) = self.key_position(key)?;
+    let repeat_cents = match self.keymap.octave_degree {
+      0 => self.degree_cents(self.len()),
+      degree => self.degree_cents(degree),
+    };
+    Some(repetitions as f64 * repeat_cents + self.degree_cents(self.degree_of(key)?))
+  }
+
+  pub fn frequency(&self, key: u8) -> Option<f64> {
+    let cents = self.key_cents(key)? - self.key_cents(self.keymap.reference_note)?;
+    Some(self.keymap.reference_frequency * 2f64.powf(cents / 1200.0))
+  }
+
+  /// The key row `y` plays: rows walk up the mapped keys from a period below the middle note,
+  /// `root` rows higher when transposed
+  pub fn row_key(&self, y: usize, total_rows: usize, root: u8) -> Option<u8> {
+    let inverted_y = total_rows.saturating_sub(1).saturating_sub(y) + root as usize;
+    let period_keys = match self.keymap.map_size {
+      0 => self.len(),
+      size => size,
+    };
+    let start = (self.keymap.middle_note as usize).saturating_sub(period_keys) as u8;
+    (start..=self.keymap.last_note)
+      .filter(|key| self.degree_of(*key).is_some())
+      .nth(inverted_y)
+  }
+
+  /// The 12-TET key closest to the pitch of `key`, and how far off it is (in semitones)
+  pub fn nearest_key(&self, key: u8) -> Option<(u8, f64)> {
+    let note = frequency_to_note(self.frequency(key)?);
+    let nearest = note.round().clamp(0.0, 127.0);
+    Some((nearest as u8, note - nearest))
+  }
+
+  /// MIDI Tuning Standard real-time single note tuning changes retuning every mapped key
+  pub fn mts_messages(&self) -> Vec<Vec<u8>> {
+    let keys: Vec<(u8, f64)> = (0..=127)
+      .filter_map(|key| Some((key, frequency_to_note(self.frequency(key)?))))
+      .filter(|(_, note)| (0.0..128.0).contains(note))
+      .collect();
+    single_note_tuning_changes(&keys)
+  }
+}
+
+/// Tuning changes putting every key back to 12-TET
+pub fn mts_reset() -> Vec<Vec<u8>> {
+  let keys: Vec<(u8, f64)> = (0..=127).map(|key| (key, key as f64)).collect();
+  single_note_tuning_changes(&keys)
+}
+
+/// Offset (in 14-bit pitch bend steps) bending `detune` semitones with a bend range of `range`
+pub fn detune_bend(detune: f64, range: u8) -> i16 {
+  let offset = (detune / range.max(1) as f64 * PITCH_BEND_CENTER as f64).round();
+  offset.clamp(-PITCH_BEND_CENTER as f64, PITCH_BEND_CENTER as f64) as i16
+}
+
+/// `bend` moved by `offset`, kept within the 14-bit range
+pub fn offset_bend(bend: u16, offset: i16) -> u16 {
+  (bend as i32 + offset as i32).clamp(0, PITCH_BEND_MAX) as u16
+}
+
+fn parse_field<T: std::str::FromStr>(
+  field: Option<(usize, &str)>,
+  what: &str,
+) -> Result<T, String> {
+  let (line_no, field) = field.ok_or(format!("missing {}", what))?;
+  field
+    .parse::<T>()
+    .map_err(|_| format!("line {}: invalid {}", line_no + 1, what))
+}
+
+// pitch lines hold cents when there is a period, a ratio (`3/2`) or a whole number (`2`) otherwise
+fn parse_pitch(line: &str) -> Result<f64, String> {
+  let pitch = line.split_whitespace().next().ok_or("missing pitch")?;
+  if pitch.contains('.') {
+    return pitch
+      .parse::<f64>()
+      .map_err(|_| format!("invalid cents `{}`", pitch));
+  }
+  let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+  match (numerator.parse::<u64>(), denominator.parse::<u64>()) {
+    (Ok(numerator), Ok(denominator)) if numerator > 0 && denominator > 0 => {
+      Ok(1200.0 * (numerator as f64 / denominator as f64).log2())
+    }
+    _ => Err(format!("invalid ratio `{}`", pitch)),
+  }
+}
+
+// fractional MIDI note number of `frequency`, A4 (69) at 440 Hz
+fn frequency_to_note(frequency: f64) -> f64 {
+  69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+// F0 7F <all devices> 08 02 <program 0> <count> [<key> <semitone> <fraction msb> <fraction lsb>].. F7
+fn single_note_tuning_changes(keys: &[(u8, f64)]) -> Vec<Vec<u8>> {
+  keys
+    .chunks(MTS_KEYS_PER_MESSAGE)
+    .map(|chunk| {
+      let mut msg = vec![0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, chunk.len() as u8];
+      for (key, note) in chunk {
+        let semitone = note.floor();
+        let fraction = ((note - semitone) * 16384.0).round() as u16;
+        // a fraction rounding up to a whole semitone is the next one
+        let (semitone, fraction) = match fraction {
+          16384.. if semitone < 127.0 => (semitone as u8 + 1, 0),
+          16384.. => (127, 16383),
+          fraction => (semitone as u8, fraction),
+        };
+        msg.extend([
+          *key,
+          semitone,
+          (fraction >> 7) as u8 & 0x7F,
+          fraction as u8 & 0x7F,
+        ]);
+      }
+      msg.push(0xF7);
+      msg
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  static EDO_19: &str = "! 19edo.scl
+!
+19 equal divisions of the octave
+ 19
+!
+";
+
+  fn edo(divisions: usize) -> String {
+    let mut scl = EDO_19.replace("19", &divisions.to_string());
+    for degree in 1..=divisions {
+      scl.push_str(&format!(
+        "{:.5}\n",
+        degree as f64 * 1200.0 / divisions as f64
+      ));
+    }
+    scl
+  }
+
+  #[test]
+  fn test_parse_scl() {
+    let scl = "! just.scl\nJust major\n 3\n!\n 9/8 major second\n 386.31371\n 2\n";
+    let tuning = Tuning::parse("just", scl, None).unwrap();
+    assert_eq!(tuning.description, "Just major");
+    assert_eq!(tuning.len(), 3);
+    assert!((tuning.pitches[0] - 203.91).abs() < 0.01);
+    assert!((tuning.pitches[2] - 1200.0).abs() < 1e-9);
+
+    assert!(Tuning::parse("bad", "bad\n 2\n 3/0\n 2/1\n", None).is_err());
+    assert!(Tuning::parse("short", "short\n 3\n 2/1\n", None).is_err());
+  }
+
+  #[test]
+  fn test_12_tet_matches_midi() {
+    let tuning = Tuning::parse("12edo", &edo(12), None).unwrap();
+    assert!((tuning.frequency(69).unwrap() - 440.0).abs() < 1e-6);
+    let (key, detune) = tuning.nearest_key(61).unwrap();
+    assert_eq!(key, 61);
+    assert!(detune.abs() < 1e-6);
+    // the bottom row is a period below middle C, like the scale keyboards
+    assert_eq!(tuning.row_key(9, 10, 0), Some(48));
+    assert_eq!(tuning.row_key(9, 10, 2), Some(50));
+  }
+
+  #[test]
+  fn test_19_edo_degrees_and_retuning() {
+    let tuning = Tuning::parse("19edo", &edo(19), None).unwrap();
+    assert_eq!(tuning.degree_of(60), Some(0));
+    assert_eq!(tuning.degree_of(79), Some(0));
+    assert_eq!(tuning.degree_of(62), Some(2));
+    assert!((tuning.frequency(79).unwrap() / tuning.frequency(60).unwrap() - 2.0).abs() < 1e-9);
+
+    // one step of 19-EDO is 63 cents, closer to a semitone up than to the middle C
+    let (key, detune) = tuning.nearest_key(61).unwrap();
+    assert_eq!(key, 61);
+    assert!((detune - (1200.0 / 19.0 - 100.0) / 100.0).abs() < 1e-6);
+    assert_eq!(detune_bend(detune, 2), -1509);
+    assert_eq!(offset_bend(0x2000, detune_bend(detune, 2)), 0x2000 - 1509);
+    assert_eq!(offset_bend(0x3FFF, 100), 0x3FFF);
+  }
+
+  #[test]
+  fn test_keyboard_mapping() {
+    // a 7-note white-key mapping for a 12-note scale, D4 tuned to 293.66 Hz
+    let kbm = "! white.kbm\n12\n0\n127\n60\n62\n293.66\n12\n0\nx\n2\nx\n4\n5\nx\n7\nx\n9\nx\n11\n";
+    let tuning = Tuning::parse("white", &edo(12), Some(kbm)).unwrap();
+    assert_eq!(tuning.degree_of(61), None);
+    assert_eq!(tuning.degree_of(64), Some(4));
+    assert!((tuning.frequency(62).unwrap() - 293.66).abs() < 1e-9);
+    // rows skip the unmapped keys
+    assert_eq!(tuning.row_key(8, 10, 0), Some(50));
+
+    let unmapped_reference = "12\n0\n127\n60\n61\n440.0\n12\n0\nx\n";
+    assert!(Tuning::parse("white", &edo(12), Some(unmapped_reference)).is_err());
+  }
+
+  #[test]
+  fn test_mts_messages() {
+    let tuning = Tuning::parse("19edo", &edo(19), None).unwrap();
+    let msgs = tuning.mts_messages();
+    assert_eq!(msgs.len(), 2);
+    assert_eq!(msgs[0][..7], [0xF0, 0x7F, 0x7F, 0x08, 0x02, 0x00, 64]);
+    assert_eq!(*msgs[0].last().unwrap(), 0xF7);
+    // middle C stays where it is
+    assert_eq!(msgs[0][7 + 60 * 4..7 + 61 * 4], [60, 60, 0, 0]);
+
+    let reset = mts_reset();
+    assert_eq!(reset[1][7 + 5 * 4..7 + 6 * 4], [69, 69, 0, 0]);
+  }
+}
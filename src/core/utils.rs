@@ -26,10 +26,66 @@ pub fn build_bpm_status_str(bpm: usize) -> String {
   format!("{bpm}")
 }
 
-pub fn build_ratio_status_str((numerator, denominator): (i64, usize), tick_str: &str) -> String {
-  // Cap display at 1/16 since clock resolution is 4 ticks per beat (sixteenth note)
-  let display_denominator = std::cmp::min(denominator, 16);
-  format!("{numerator}/{display_denominator}, {tick_str}")
+pub fn build_ratio_status_str(
+  (numerator, denominator): (i64, usize),
+  loop_length: usize,
+) -> String {
+  match loop_length {
+    0 => format!("{numerator}/{denominator}"),
+    len => format!("{numerator}/{denominator}, L{len}"),
+  }
+}
+
+pub fn build_bbt_status_str((bar, beat, tick): (i64, i64, i64)) -> String {
+  format!("{bar}.{beat}.{tick}")
+}
+
+pub fn build_swing_status_str(swing: i64, groove_name: Option<&str>) -> String {
+  match groove_name {
+    Some(name) => format!("{swing}%, {name}"),
+    None => format!("{swing}%"),
+  }
+}
+
+pub fn build_launch_status_str(quantization: &str, pending: usize) -> String {
+  if pending > 0 {
+    format!("{quantization}, {pending} pending")
+  } else {
+    quantization.to_string()
+  }
+}
+
+pub fn build_ramp_status_str(ramp: Option<(i64, &str)>, lane_len: usize) -> String {
+  let ramp_str = match ramp {
+    Some((target, curve)) => format!("→{target} {curve}"),
+    None => "-".to_string(),
+  };
+  if lane_len > 0 {
+    format!("{ramp_str}, lane:{lane_len}")
+  } else {
+    ramp_str
+  }
+}
+
+pub fn build_note_status_str(note_source: &str, list_len: usize) -> String {
+  if list_len > 0 {
+    format!("{note_source}, {list_len} msg")
+  } else {
+    note_source.to_string()
+  }
+}
+
+pub fn build_gate_status_str(gate_mode: bool, gate_percent: usize) -> String {
+  let mode = if gate_mode { "match" } else { "off" };
+  format!("{mode}, {gate_percent}%")
+}
+
+pub fn build_record_status_str(take: Option<(bool, usize)>) -> String {
+  match take {
+    None => "off".to_string(),
+    Some((true, _)) => "rec".to_string(),
+    Some((false, events)) => format!("{events} msgs, unsaved"),
+  }
 }
 
 pub fn build_len_status_str((w, h): (usize, usize)) -> String {
@@ -2,14 +2,28 @@ mod app;
 mod core;
 mod view;
 
-use app::{initialize_components, setup_ui, spawn_background_threads};
+use app::{
+  initialize_components, install_panic_hook, setup_ui, spawn_background_threads,
+  spawn_signal_handler,
+};
+use core::{midi_output, render};
 use cursive::CursiveExt;
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
 
 fn main() {
+  // `anupars render ...` writes a .mid file without starting the TUI
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  if args.first().map(String::as_str) == Some("render") {
+    std::process::exit(render::main(&args[1..]));
+  }
+
   let mut components = initialize_components();
   setup_ui(&mut components);
 
+  let outputs = Arc::clone(&components.midi.outputs);
+  install_panic_hook(Arc::clone(&outputs));
+  spawn_signal_handler(components.cursive.cb_sink().clone());
+
   spawn_background_threads(
     Arc::clone(&components.last_key_time),
     Arc::clone(&components.current_tempo),
@@ -20,5 +34,10 @@ fn main() {
 
   components.marker.run();
   components.midi.run();
+  components.midi_in.run();
   components.cursive.run();
+
+  // quit from the menu, a keybinding or a signal: nothing is left sounding
+  let mut outputs = outputs.lock().unwrap_or_else(PoisonError::into_inner);
+  midi_output::all_notes_off(&mut outputs);
 }
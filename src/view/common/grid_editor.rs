@@ -17,7 +17,9 @@ use cursive::views::ResizedView;
 use cursive::Printer;
 use cursive::Vec2;
 
-use crate::core::{consts, traits::Matrix};
+use crate::core::midi::convert_from_midi_note_num;
+use crate::core::tuning::Tuning;
+use crate::core::{consts, traits::Matrix, transpose};
 use crate::view::common::playhead::MarkerUI;
 use crate::view::common::playhead::EVENT_OPERATORS;
 use crate::view::common::playhead::QUEUE_OPERATORS;
@@ -39,6 +41,8 @@ pub struct CanvasEditor {
   pub show_keyboard: bool,
   pub scale_mode_left: crate::core::scale::ScaleMode,
   pub scale_mode_top: crate::core::scale::ScaleMode,
+  pub root: u8,               // transpose from the MIDI keyboard, see `core::transpose`
+  pub tuning: Option<Tuning>, // replaces both scales, see `core::tuning`
   pub reverse_mode: bool,
   pub arpeggiator_mode: bool,
   pub random_mode: bool,
@@ -55,6 +59,8 @@ impl CanvasEditor {
       show_keyboard: true,
       scale_mode_left: crate::core::scale::ScaleMode::default(),
       scale_mode_top: crate::core::scale::ScaleMode::default(),
+      root: 0,
+      tuning: None,
       reverse_mode: false,
       arpeggiator_mode: false,
       random_mode: false,
@@ -69,9 +75,12 @@ impl CanvasEditor {
       return (0, BASE_OCTAVE, "C");
     }
 
-    let (note_index, octave) = self
-      .scale_mode_left
-      .y_to_scale_note(y, total_rows, BASE_OCTAVE);
+    let (note_index, octave) = transpose::transpose(
+      self
+        .scale_mode_left
+        .y_to_scale_note(y, total_rows, BASE_OCTAVE),
+      self.root,
+    );
 
     (note_index, octave, NOTE_NAMES[note_index as usize])
   }
@@ -84,18 +93,33 @@ impl CanvasEditor {
       return (0, BASE_OCTAVE, "C");
     }
 
-    let (note_index, octave) = self
-      .scale_mode_top
-      .y_to_scale_note(y, total_rows, BASE_OCTAVE);
+    let (note_index, octave) = transpose::transpose(
+      self
+        .scale_mode_top
+        .y_to_scale_note(y, total_rows, BASE_OCTAVE),
+      self.root,
+    );
 
     (note_index, octave, NOTE_NAMES[note_index as usize])
   }
 
+  /// Map Y position to the degree of the loaded tuning and the octave of its key, `None`
+  /// without a tuning or past its mapped keys
+  pub fn y_to_degree(&self, y: usize) -> Option<(usize, u8)> {
+    let tuning = self.tuning.as_ref()?;
+    let key = tuning.row_key(y, self.grid.height, self.root)?;
+    Some((tuning.degree_of(key)?, convert_from_midi_note_num(key).1))
+  }
+
   /// Draw the keyboard visualization on the top margin
   fn draw_keyboard_top(&self, printer: &Printer) {
     if !self.show_keyboard || self.grid.height == 0 || self.grid.width == 0 {
       return;
     }
+    if self.tuning.is_some() {
+      self.draw_degrees_top(printer);
+      return;
+    }
 
     let abs_active_x = self.marker_ui.marker_pos.x + self.marker_ui.actived_pos.x;
     for x in 0..self.grid.width {
@@ -135,10 +159,37 @@ impl CanvasEditor {
     }
   }
 
+  /// The top keyboard for a loaded tuning: the last digit of every degree, the octave under the
+  /// first degree
+  fn draw_degrees_top(&self, printer: &Printer) {
+    let abs_active_x = self.marker_ui.marker_pos.x + self.marker_ui.actived_pos.x;
+    for x in 0..self.grid.width {
+      let Some((degree, octave)) = self.y_to_degree(x % self.grid.height) else {
+        continue;
+      };
+
+      let color = if x == abs_active_x {
+        ColorType::rgb(255, 255, 255)
+      } else {
+        ColorType::rgb(100, 100, 100)
+      };
+      printer.with_style(Style::from(ColorStyle::front(color)), |printer| {
+        printer.print((x, 0), &(degree % 10).to_string());
+        if degree == 0 {
+          printer.print((x, 1), &octave.to_string());
+        }
+      });
+    }
+  }
+
   fn draw_keyboard_left(&self, printer: &Printer) {
     if !self.show_keyboard || self.grid.height == 0 {
       return;
     }
+    if self.tuning.is_some() {
+      self.draw_degrees_left(printer);
+      return;
+    }
 
     for y in 0..self.grid.height {
       let (note_index, octave, note_name) = self.y_to_note_left(y);
@@ -165,6 +216,39 @@ impl CanvasEditor {
     }
   }
 
+  /// The left keyboard for a loaded tuning: the degree of every row, a new period on the first
+  fn draw_degrees_left(&self, printer: &Printer) {
+    let style = Style::from(ColorStyle::front(ColorType::rgb(100, 100, 100)));
+    for y in 0..self.grid.height {
+      let Some((degree, _)) = self.y_to_degree(y) else {
+        continue;
+      };
+      let symbol = if degree == 0 { "┣" } else { "┃" };
+
+      printer.with_style(style, |printer| {
+        printer.print((0, y), &degree.to_string());
+        printer.print((2, y), symbol);
+      });
+    }
+  }
+
+  /// Draw the root the keyboards are transposed to, in the corner between them
+  fn draw_root(&self, printer: &Printer) {
+    let color = if self.root == 0 {
+      ColorType::rgb(100, 100, 100)
+    } else {
+      ColorType::rgb(255, 255, 255)
+    };
+
+    printer.with_style(Style::from(ColorStyle::front(color)), |printer| {
+      // with a tuning, the root moves the rows by degrees
+      match self.tuning {
+        Some(_) => printer.print((0, 0), &format!("+{}", self.root)),
+        None => printer.print((0, 0), NOTE_NAMES[self.root as usize % 12]),
+      }
+    });
+  }
+
   fn draw_queue_operators_bottom(&self, printer: &Printer) {
     if !self.show_keyboard || self.grid.width == 0 {
       return;
@@ -355,6 +439,8 @@ fn draw(canvas: &CanvasEditor, printer: &Printer) {
     let left_keyboard_printer = printer.offset((0, KEYBOARD_MARGIN_TOP));
     canvas.draw_keyboard_left(&left_keyboard_printer);
 
+    canvas.draw_root(printer);
+
     let bottom_y = KEYBOARD_MARGIN_TOP + canvas.grid.height;
     let bottom_operators_printer = printer.offset((KEYBOARD_MARGIN_LEFT, bottom_y));
     canvas.draw_queue_operators_bottom(&bottom_operators_printer);
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::ffi::OsString;
 use std::fs;
@@ -16,11 +17,15 @@ use cursive::menu::Tree;
 use cursive::view::Margins;
 use cursive::view::Nameable;
 use cursive::view::Resizable;
+use cursive::view::Scrollable;
 use cursive::views::Canvas;
+use cursive::views::Checkbox;
 use cursive::views::Dialog;
 use cursive::views::DummyView;
+use cursive::views::EditView;
 use cursive::views::HideableView;
 use cursive::views::LinearLayout;
+use cursive::views::ListView;
 use cursive::views::NamedView;
 use cursive::views::OnEventView;
 use cursive::views::ResizedView;
@@ -28,8 +33,17 @@ use cursive::views::SelectView;
 use cursive::views::TextView;
 use cursive::Cursive;
 use cursive::With;
+use num::rational::Ratio;
 
 use super::grid_editor::CanvasEditor;
+use crate::app::UserData;
+use crate::core::command::Command;
+use crate::core::midi_learn::Target;
+use crate::core::mpe::{Zone, ZoneKind};
+use crate::core::timing::clock::{Quantization, RampCurve, Signature, Tempo};
+use crate::core::timing::groove::{self, Groove};
+use crate::core::timing::metronome;
+use crate::core::tuning::{RetuneMode, Tuning};
 use crate::core::{consts, disspress, utils};
 
 #[derive(Clone, Copy)]
@@ -38,6 +52,18 @@ pub struct Menubar {
   pub show_doc: bool,
 }
 
+/// What the MIDI submenu shows: the available ports and how they are currently used
+#[derive(Clone, Debug, Default)]
+pub struct MidiMenuState {
+  pub devices: Vec<(String, usize)>,
+  pub connected: HashSet<String>,
+  pub outputs: Vec<(String, bool, Option<u8>)>, // (name, enabled, channel)
+  pub clock_out_ports: HashSet<String>,
+  pub virtual_port_name: String,
+  pub virtual_connected: bool,
+  pub mpe: Option<Zone>,
+}
+
 impl Default for Menubar {
   fn default() -> Self {
     Self::new()
@@ -101,8 +127,12 @@ impl Menubar {
   // }
 
   pub fn build_menu_app(
-    midi_devices: &[(String, usize)],
+    midi_state: &MidiMenuState,
+    midi_input_devices: &[(String, usize)],
+    controls_port: Option<usize>,
     midi_tx: Sender<crate::core::midi::Message>,
+    midi_in_tx: Sender<crate::core::midi_input::Message>,
+    metronome_tx: Sender<metronome::Message>,
   ) -> Tree {
     let midi_tx_reset = midi_tx.clone();
     menu::Tree::new()
@@ -123,12 +153,48 @@ impl Menubar {
       })
       .subtree(
         "MIDI",
-        build_midi_menu(midi_devices.to_vec(), midi_tx.clone()),
+        build_midi_menu(midi_state, midi_tx.clone()).with(|tree| {
+          tree.insert_subtree(
+            0,
+            "Sync",
+            build_sync_menu(midi_input_devices.to_vec(), None, midi_in_tx.clone()),
+          );
+          tree.insert_subtree(
+            1,
+            "Transpose",
+            build_transpose_menu(
+              midi_input_devices.to_vec(),
+              TransposeMenuState::default(),
+              midi_in_tx.clone(),
+            ),
+          );
+          tree.insert_subtree(
+            2,
+            "Controls",
+            build_controls_menu(midi_input_devices.to_vec(), controls_port, midi_in_tx),
+          );
+        }),
       )
       // .subtree("OSC", build_osc_menu())
       .delimiter()
       .subtree("Scale (Left)", build_scale_menu_left())
       .subtree("Scale (Top)", build_scale_menu_top())
+      .subtree(
+        "Tuning",
+        build_tuning_menu(load_tunings(), None, RetuneMode::default(), midi_tx.clone()),
+      )
+      .leaf("Signature", |s| {
+        if let Some(data) = s.user_data::<UserData>().cloned() {
+          data.cmd.handle(s, Command::EditSignature);
+        }
+      })
+      .subtree("Tempo", build_tempo_menu(metronome_tx.clone()))
+      .subtree("Launch Quantize", build_launch_menu())
+      .subtree("Swing", build_swing_menu(metronome_tx.clone()))
+      .subtree(
+        "Groove",
+        build_groove_menu(load_grooves(), None, metronome_tx),
+      )
       .delimiter()
       .leaf("Reverse", |s| {
         s.call_on_name(
@@ -173,32 +239,730 @@ impl Menubar {
 
 // ------------------------------------------------------------
 
+/// `[x] label` for a checked menu item, `[ ] label` otherwise
+fn checkbox_label(checked: bool, label: &str) -> String {
+  format!("[{}] {}", if checked { "x" } else { " " }, label)
+}
+
 fn build_midi_menu(
-  devices: Vec<(String, usize)>,
+  state: &MidiMenuState,
   midi_tx: Sender<crate::core::midi::Message>,
 ) -> cursive::menu::Tree {
   menu::Tree::new().with(|tree| {
-    if devices.is_empty() {
+    if cfg!(target_os = "linux") {
+      let midi_tx_clone = midi_tx.clone();
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(
+          state.virtual_connected,
+          &format!("Virtual: {}", state.virtual_port_name),
+        ),
+        move |_| {
+          let _ = midi_tx_clone.send(crate::core::midi::Message::ToggleVirtualPort());
+        },
+      ));
+      let midi_tx_clone = midi_tx.clone();
+      let virtual_port_name = state.virtual_port_name.clone();
+      tree.add_item(menu::Item::leaf("Virtual Port Name", move |s| {
+        let midi_tx_clone = midi_tx_clone.clone();
+        s.add_layer(build_virtual_port_view(
+          &virtual_port_name,
+          move |_, name| {
+            let _ = midi_tx_clone.send(crate::core::midi::Message::SetVirtualPortName(name));
+          },
+        ));
+      }));
+      tree.add_delimiter();
+    }
+
+    let midi_tx_clone = midi_tx.clone();
+    let mpe = state.mpe;
+    tree.add_item(menu::Item::leaf(
+      format!(
+        "MPE: {}",
+        mpe.map_or("off".to_string(), |zone| zone.to_string())
+      ),
+      move |s| {
+        let midi_tx_clone = midi_tx_clone.clone();
+        s.add_layer(build_mpe_view(mpe, move |_, zone| {
+          let _ = midi_tx_clone.send(crate::core::midi::Message::SetMpe(zone));
+        }));
+      },
+    ));
+
+    if !state.outputs.is_empty() {
+      tree.add_subtree(
+        "Outputs",
+        build_outputs_menu(&state.outputs, midi_tx.clone()),
+      );
+    }
+
+    let virtual_port = state
+      .virtual_connected
+      .then_some(state.virtual_port_name.as_str());
+    if !state.devices.is_empty() || virtual_port.is_some() {
+      tree.add_subtree(
+        "Clock Out",
+        build_clock_out_menu(
+          virtual_port,
+          &state.devices,
+          &state.clock_out_ports,
+          midi_tx.clone(),
+        ),
+      );
+    }
+
+    if state.devices.is_empty() {
       tree.add_item(menu::Item::leaf("No devices found", |_| ()));
     } else {
-      for (name, idx) in devices {
+      tree.add_delimiter();
+      // a checklist, every checked port receives the same notes
+      for (name, idx) in &state.devices {
         let midi_tx_clone = midi_tx.clone();
-        let name_clone = name.clone();
-        tree.add_item(menu::Item::leaf(format!("{}: {}", idx, name), move |s| {
-          // Send message to switch MIDI device
-          if let Err(e) = midi_tx_clone.send(crate::core::midi::Message::SwitchDevice(idx)) {
-            s.add_layer(Dialog::info(format!("Failed to switch device: {}", e)));
-          } else {
-            // Update the MIDI status display
-            s.call_on_name(consts::midi_status_unit_view, |c: &mut TextView| {
-              c.set_content(&name_clone);
-            });
+        let name = name.clone();
+        let label = checkbox_label(
+          state.connected.contains(&name),
+          &format!("{}: {}", idx, name),
+        );
+        tree.add_item(menu::Item::leaf(label, move |s| {
+          if let Err(e) = midi_tx_clone.send(crate::core::midi::Message::ToggleDevice(name.clone()))
+          {
+            s.add_layer(Dialog::info(format!("Failed to toggle device: {}", e)));
           }
         }));
       }
     }
   })
 }
+
+/// Rebuild the MIDI submenu after output ports were (un)plugged or toggled, keeping the MIDI input
+/// submenus
+pub fn rebuild_midi_menu(
+  siv: &mut Cursive,
+  state: &MidiMenuState,
+  midi_tx: Sender<crate::core::midi::Message>,
+) {
+  let midi_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("MIDI"));
+  if let Some(tree) = midi_menu {
+    let input_menus: Vec<_> = ["Sync", "Transpose", "Controls"]
+      .into_iter()
+      .filter_map(|label| Some((label, tree.find_subtree(label)?.clone())))
+      .collect();
+    *tree = build_midi_menu(state, midi_tx);
+    for (idx, (label, input_menu)) in input_menus.into_iter().enumerate() {
+      tree.insert_subtree(idx, label, input_menu);
+    }
+  }
+}
+
+// per connected output: enable toggle and channel remap
+fn build_outputs_menu(
+  outputs: &[(String, bool, Option<u8>)],
+  midi_tx: Sender<crate::core::midi::Message>,
+) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    for (name, enabled, channel) in outputs.iter().cloned() {
+      let midi_tx_clone = midi_tx.clone();
+      let label = checkbox_label(
+        enabled,
+        &format!(
+          "{} (ch: {})",
+          name,
+          channel.map_or("thru".to_string(), |c| (c + 1).to_string())
+        ),
+      );
+      tree.add_item(menu::Item::leaf(label, move |s| {
+        let midi_tx_clone = midi_tx_clone.clone();
+        let name = name.clone();
+        s.add_layer(build_output_view(
+          &name.clone(),
+          enabled,
+          channel,
+          move |_, enabled, channel| {
+            let _ = midi_tx_clone.send(crate::core::midi::Message::ConfigureOutput(
+              name.clone(),
+              enabled,
+              channel,
+            ));
+          },
+        ));
+      }));
+    }
+  })
+}
+
+// internal clock or follow the clock of one MIDI input port
+fn build_sync_menu(
+  devices: Vec<(String, usize)>,
+  selected: Option<usize>,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    let devices_clone = devices.clone();
+    let midi_in_tx_clone = midi_in_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(selected.is_none(), "Internal"),
+      move |s| {
+        let _ = midi_in_tx_clone.send(crate::core::midi_input::Message::Disconnect());
+        rebuild_sync_menu(s, devices_clone.clone(), None, midi_in_tx_clone.clone());
+      },
+    ));
+
+    for (name, idx) in devices.iter().cloned() {
+      let devices_clone = devices.clone();
+      let midi_in_tx_clone = midi_in_tx.clone();
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(selected == Some(idx), &format!("{}: {}", idx, name)),
+        move |s| {
+          let _ = midi_in_tx_clone.send(crate::core::midi_input::Message::Connect(idx));
+          rebuild_sync_menu(
+            s,
+            devices_clone.clone(),
+            Some(idx),
+            midi_in_tx_clone.clone(),
+          );
+        },
+      ));
+    }
+  })
+}
+
+fn rebuild_sync_menu(
+  siv: &mut Cursive,
+  devices: Vec<(String, usize)>,
+  selected: Option<usize>,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) {
+  let sync_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("MIDI"))
+    .and_then(|tree| tree.find_subtree("Sync"));
+  if let Some(tree) = sync_menu {
+    *tree = build_sync_menu(devices, selected, midi_in_tx);
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TransposeMenuState {
+  selected: Option<usize>,
+  latch: bool,
+  detect_scale: bool,
+}
+
+// the MIDI input port whose keys set the root of the grid, see `core::transpose`
+fn build_transpose_menu(
+  devices: Vec<(String, usize)>,
+  state: TransposeMenuState,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) -> cursive::menu::Tree {
+  use crate::core::midi_input::Message;
+  menu::Tree::new().with(|tree| {
+    let devices_clone = devices.clone();
+    let midi_in_tx_clone = midi_in_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(state.selected.is_none(), "Off"),
+      move |s| {
+        let _ = midi_in_tx_clone.send(Message::DisconnectKeys());
+        let state = TransposeMenuState {
+          selected: None,
+          ..state
+        };
+        rebuild_transpose_menu(s, devices_clone.clone(), state, midi_in_tx_clone.clone());
+      },
+    ));
+
+    for (name, idx) in devices.iter().cloned() {
+      let devices_clone = devices.clone();
+      let midi_in_tx_clone = midi_in_tx.clone();
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(state.selected == Some(idx), &format!("{}: {}", idx, name)),
+        move |s| {
+          let _ = midi_in_tx_clone.send(Message::ConnectKeys(idx));
+          let state = TransposeMenuState {
+            selected: Some(idx),
+            ..state
+          };
+          rebuild_transpose_menu(s, devices_clone.clone(), state, midi_in_tx_clone.clone());
+        },
+      ));
+    }
+
+    tree.add_delimiter();
+
+    let devices_clone = devices.clone();
+    let midi_in_tx_clone = midi_in_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(state.latch, "Latch"),
+      move |s| {
+        let _ = midi_in_tx_clone.send(Message::SetLatch(!state.latch));
+        let state = TransposeMenuState {
+          latch: !state.latch,
+          ..state
+        };
+        rebuild_transpose_menu(s, devices_clone.clone(), state, midi_in_tx_clone.clone());
+      },
+    ));
+
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(state.detect_scale, "Detect Scale"),
+      move |s| {
+        let _ = midi_in_tx.send(Message::SetScaleDetection(!state.detect_scale));
+        let state = TransposeMenuState {
+          detect_scale: !state.detect_scale,
+          ..state
+        };
+        rebuild_transpose_menu(s, devices.clone(), state, midi_in_tx.clone());
+      },
+    ));
+  })
+}
+
+fn rebuild_transpose_menu(
+  siv: &mut Cursive,
+  devices: Vec<(String, usize)>,
+  state: TransposeMenuState,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) {
+  let transpose_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("MIDI"))
+    .and_then(|tree| tree.find_subtree("Transpose"));
+  if let Some(tree) = transpose_menu {
+    *tree = build_transpose_menu(devices, state, midi_in_tx);
+  }
+}
+
+// the MIDI input port of the controller performing the learned bindings, see `core::midi_learn`
+fn build_controls_menu(
+  devices: Vec<(String, usize)>,
+  selected: Option<usize>,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) -> cursive::menu::Tree {
+  use crate::core::midi_input::Message;
+  menu::Tree::new().with(|tree| {
+    let devices_clone = devices.clone();
+    let midi_in_tx_clone = midi_in_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(selected.is_none(), "Off"),
+      move |s| {
+        let _ = midi_in_tx_clone.send(Message::DisconnectControls());
+        rebuild_controls_menu(s, devices_clone.clone(), None, midi_in_tx_clone.clone());
+      },
+    ));
+
+    for (name, idx) in devices.iter().cloned() {
+      let devices_clone = devices.clone();
+      let midi_in_tx_clone = midi_in_tx.clone();
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(selected == Some(idx), &format!("{}: {}", idx, name)),
+        move |s| {
+          let _ = midi_in_tx_clone.send(Message::ConnectControls(idx));
+          rebuild_controls_menu(
+            s,
+            devices_clone.clone(),
+            Some(idx),
+            midi_in_tx_clone.clone(),
+          );
+        },
+      ));
+    }
+
+    tree.add_delimiter();
+
+    let midi_in_tx_clone = midi_in_tx.clone();
+    tree.add_item(menu::Item::leaf("Learn...", move |s| {
+      s.add_layer(build_midi_learn_view(midi_in_tx_clone.clone()));
+    }));
+    tree.add_item(menu::Item::leaf("Clear Mappings", move |_| {
+      let _ = midi_in_tx.send(Message::ClearMappings());
+    }));
+  })
+}
+
+fn rebuild_controls_menu(
+  siv: &mut Cursive,
+  devices: Vec<(String, usize)>,
+  selected: Option<usize>,
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) {
+  let controls_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("MIDI"))
+    .and_then(|tree| tree.find_subtree("Controls"));
+  if let Some(tree) = controls_menu {
+    *tree = build_controls_menu(devices, selected, midi_in_tx);
+  }
+}
+
+/// Pick what to learn, then wait for a control to move, `MidiIn` closes it once learned
+pub fn build_midi_learn_view(
+  midi_in_tx: Sender<crate::core::midi_input::Message>,
+) -> NamedView<OnEventView<Dialog>> {
+  use crate::core::midi_input::Message;
+
+  let midi_in_tx_select = midi_in_tx.clone();
+  let targets = SelectView::new()
+    .with_all(
+      Target::all()
+        .into_iter()
+        .map(|target| (target.to_string(), target)),
+    )
+    .on_submit(move |s, target: &Target| {
+      let _ = midi_in_tx_select.send(Message::Learn(*target));
+      let waiting = TextView::new(format!("move a control for `{}`", target));
+      s.call_on_name(
+        consts::midi_learn_section_view,
+        |view: &mut OnEventView<Dialog>| view.get_inner_mut().set_content(waiting),
+      );
+    })
+    .scrollable()
+    .max_height(16);
+
+  let midi_in_tx_esc = midi_in_tx.clone();
+  OnEventView::new(
+    Dialog::around(targets)
+      .title("MIDI Learn")
+      .button("Cancel", move |s| {
+        let _ = midi_in_tx.send(Message::CancelLearn());
+        s.pop_layer();
+      }),
+  )
+  .on_event(Event::Key(Key::Esc), move |s| {
+    let _ = midi_in_tx_esc.send(Message::CancelLearn());
+    s.pop_layer();
+  })
+  .with_name(consts::midi_learn_section_view)
+}
+
+// the virtual port first, a DAW listening on it can follow the clock too
+fn build_clock_out_menu(
+  virtual_port: Option<&str>,
+  devices: &[(String, usize)],
+  clock_out_ports: &HashSet<String>,
+  midi_tx: Sender<crate::core::midi::Message>,
+) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    let names = virtual_port
+      .into_iter()
+      .chain(devices.iter().map(|(name, _)| name.as_str()));
+    for name in names {
+      tree.add_item(build_clock_out_item(
+        name.to_string(),
+        clock_out_ports.contains(name),
+        midi_tx.clone(),
+      ));
+    }
+  })
+}
+
+// each entry re-creates itself with the flipped label, since menu labels are static
+fn build_clock_out_item(
+  name: String,
+  enabled: bool,
+  midi_tx: Sender<crate::core::midi::Message>,
+) -> menu::Item {
+  let label = checkbox_label(enabled, &name);
+  menu::Item::leaf(label.clone(), move |s| {
+    let _ = midi_tx.send(crate::core::midi::Message::ToggleClockOut(name.clone()));
+
+    let clock_out_menu = s
+      .menubar()
+      .find_subtree("Anu")
+      .and_then(|tree| tree.find_subtree("MIDI"))
+      .and_then(|tree| tree.find_subtree("Clock Out"));
+    if let Some(tree) = clock_out_menu {
+      if let Some(idx) = tree.find_position(&label) {
+        tree.remove(idx);
+        tree.insert(
+          idx,
+          build_clock_out_item(name.clone(), !enabled, midi_tx.clone()),
+        );
+      }
+    }
+  })
+}
+
+fn build_tempo_menu(metronome_tx: Sender<metronome::Message>) -> cursive::menu::Tree {
+  let lane_item = |label: &'static str, msg: metronome::Message| {
+    let metronome_tx = metronome_tx.clone();
+    menu::Item::leaf(label, move |_| {
+      let _ = metronome_tx.send(msg.clone());
+    })
+  };
+
+  menu::Tree::new()
+    .leaf("Ramp", |s| {
+      if let Some(data) = s.user_data::<UserData>().cloned() {
+        data.cmd.handle(s, Command::StartTempoRamp);
+      }
+    })
+    .leaf("Cancel Ramp", |s| {
+      if let Some(data) = s.user_data::<UserData>().cloned() {
+        data.cmd.handle(s, Command::CancelTempoRamp);
+      }
+    })
+    .delimiter()
+    .with(|tree| {
+      tree.add_item(lane_item("Save Lane", metronome::Message::SaveTempoLane));
+      tree.add_item(lane_item("Load Lane", metronome::Message::LoadTempoLane));
+      tree.add_item(lane_item("Clear Lane", metronome::Message::ClearTempoLane));
+    })
+}
+
+fn build_launch_menu() -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    for quantization in Quantization::all() {
+      let quantization = *quantization;
+      tree.add_item(menu::Item::leaf(quantization.name(), move |s| {
+        s.call_on_name(
+          consts::canvas_editor_section_view,
+          |canvas: &mut Canvas<CanvasEditor>| {
+            canvas
+              .state_mut()
+              .marker_tx
+              .send(super::playhead_controller::Message::SetLaunchQuantization(
+                quantization,
+              ))
+              .unwrap();
+          },
+        );
+      }));
+    }
+  })
+}
+
+fn build_swing_menu(metronome_tx: Sender<metronome::Message>) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    // 5 divides the 50-75 range, so the hardest swing is offered too
+    for swing in (groove::MIN_SWING..=groove::MAX_SWING).step_by(5) {
+      let metronome_tx_clone = metronome_tx.clone();
+      let label = if swing == groove::MIN_SWING {
+        format!("{}% (straight)", swing)
+      } else {
+        format!("{}%", swing)
+      };
+      tree.add_item(menu::Item::leaf(label, move |_| {
+        let _ = metronome_tx_clone.send(metronome::Message::SetSwing(swing));
+      }));
+    }
+  })
+}
+
+// templates are read from ~/.anupars/grooves/*.groove
+fn load_grooves() -> Vec<Groove> {
+  dirs::home_dir()
+    .map(|p| {
+      Groove::load_dir(
+        &p.join(consts::DEFAULT_APP_DIRECTORY)
+          .join(consts::DEFAULT_GROOVE_DIRNAME),
+      )
+    })
+    .unwrap_or_default()
+}
+
+fn build_groove_menu(
+  grooves: Vec<Groove>,
+  selected: Option<String>,
+  metronome_tx: Sender<metronome::Message>,
+) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    let grooves_clone = grooves.clone();
+    let metronome_tx_clone = metronome_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(selected.is_none(), "None"),
+      move |s| {
+        let _ = metronome_tx_clone.send(metronome::Message::SetGroove(None));
+        rebuild_groove_menu(s, grooves_clone.clone(), None, metronome_tx_clone.clone());
+      },
+    ));
+
+    for groove in grooves.iter().cloned() {
+      let grooves_clone = grooves.clone();
+      let metronome_tx_clone = metronome_tx.clone();
+      let checked = selected.as_deref() == Some(groove.name.as_str());
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(checked, &groove.name),
+        move |s| {
+          let name = groove.name.clone();
+          let _ = metronome_tx_clone.send(metronome::Message::SetGroove(Some(groove.clone())));
+          rebuild_groove_menu(
+            s,
+            grooves_clone.clone(),
+            Some(name),
+            metronome_tx_clone.clone(),
+          );
+        },
+      ));
+    }
+
+    tree.add_delimiter();
+    let metronome_tx_clone = metronome_tx.clone();
+    tree.add_item(menu::Item::leaf("Reload", move |s| {
+      // the selection is kept only if the template is still there
+      let grooves = load_grooves();
+      let selected = selected
+        .clone()
+        .filter(|name| grooves.iter().any(|g| &g.name == name));
+      if selected.is_none() {
+        let _ = metronome_tx_clone.send(metronome::Message::SetGroove(None));
+      }
+      rebuild_groove_menu(s, grooves, selected, metronome_tx_clone.clone());
+    }));
+  })
+}
+
+fn rebuild_groove_menu(
+  siv: &mut Cursive,
+  grooves: Vec<Groove>,
+  selected: Option<String>,
+  metronome_tx: Sender<metronome::Message>,
+) {
+  let groove_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("Groove"));
+  if let Some(tree) = groove_menu {
+    *tree = build_groove_menu(grooves, selected, metronome_tx);
+  }
+}
+
+// Scala scales are read from ~/.anupars/tunings/*.scl, with a .kbm mapping of the same name
+fn load_tunings() -> Vec<Tuning> {
+  dirs::home_dir()
+    .map(|p| {
+      Tuning::load_dir(
+        &p.join(consts::DEFAULT_APP_DIRECTORY)
+          .join(consts::DEFAULT_TUNING_DIRNAME),
+      )
+    })
+    .unwrap_or_default()
+}
+
+// a tuning replaces both scales, on the MIDI output and on the keyboards
+fn set_tuning(
+  siv: &mut Cursive,
+  tuning: Option<Tuning>,
+  midi_tx: &Sender<crate::core::midi::Message>,
+) {
+  let _ = midi_tx.send(crate::core::midi::Message::SetTuning(tuning.clone()));
+  siv.call_on_name(
+    consts::canvas_editor_section_view,
+    |canvas: &mut Canvas<CanvasEditor>| {
+      canvas.state_mut().tuning = tuning;
+    },
+  );
+}
+
+fn build_tuning_menu(
+  tunings: Vec<Tuning>,
+  selected: Option<String>,
+  retune_mode: RetuneMode,
+  midi_tx: Sender<crate::core::midi::Message>,
+) -> cursive::menu::Tree {
+  menu::Tree::new().with(|tree| {
+    let tunings_clone = tunings.clone();
+    let midi_tx_clone = midi_tx.clone();
+    tree.add_item(menu::Item::leaf(
+      checkbox_label(selected.is_none(), "12-TET"),
+      move |s| {
+        set_tuning(s, None, &midi_tx_clone);
+        rebuild_tuning_menu(
+          s,
+          tunings_clone.clone(),
+          None,
+          retune_mode,
+          midi_tx_clone.clone(),
+        );
+      },
+    ));
+
+    for tuning in tunings.iter().cloned() {
+      let tunings_clone = tunings.clone();
+      let midi_tx_clone = midi_tx.clone();
+      let checked = selected.as_deref() == Some(tuning.name.as_str());
+      let label = match tuning.description.is_empty() {
+        true => checkbox_label(checked, &tuning.name),
+        false => checkbox_label(checked, &format!("{}: {}", tuning.name, tuning.description)),
+      };
+      tree.add_item(menu::Item::leaf(label, move |s| {
+        let name = tuning.name.clone();
+        set_tuning(s, Some(tuning.clone()), &midi_tx_clone);
+        rebuild_tuning_menu(
+          s,
+          tunings_clone.clone(),
+          Some(name),
+          retune_mode,
+          midi_tx_clone.clone(),
+        );
+      }));
+    }
+
+    tree.add_delimiter();
+    for mode in [RetuneMode::Mts, RetuneMode::PitchBend] {
+      let tunings_clone = tunings.clone();
+      let selected = selected.clone();
+      let midi_tx_clone = midi_tx.clone();
+      tree.add_item(menu::Item::leaf(
+        checkbox_label(
+          mode == retune_mode,
+          &format!(
+            "Retune: {}{}",
+            mode.name(),
+            match mode {
+              RetuneMode::PitchBend => " (with MPE)",
+              RetuneMode::Mts => "",
+            }
+          ),
+        ),
+        move |s| {
+          let _ = midi_tx_clone.send(crate::core::midi::Message::SetRetuneMode(mode));
+          rebuild_tuning_menu(
+            s,
+            tunings_clone.clone(),
+            selected.clone(),
+            mode,
+            midi_tx_clone.clone(),
+          );
+        },
+      ));
+    }
+
+    tree.add_delimiter();
+    let midi_tx_clone = midi_tx.clone();
+    tree.add_item(menu::Item::leaf("Reload", move |s| {
+      // the selection is kept only if the scale is still there, as it is now on disk
+      let tunings = load_tunings();
+      let tuning = selected
+        .as_ref()
+        .and_then(|name| tunings.iter().find(|t| &t.name == name).cloned());
+      let selected = tuning.as_ref().map(|t| t.name.clone());
+      set_tuning(s, tuning, &midi_tx_clone);
+      rebuild_tuning_menu(s, tunings, selected, retune_mode, midi_tx_clone.clone());
+    }));
+  })
+}
+
+fn rebuild_tuning_menu(
+  siv: &mut Cursive,
+  tunings: Vec<Tuning>,
+  selected: Option<String>,
+  retune_mode: RetuneMode,
+  midi_tx: Sender<crate::core::midi::Message>,
+) {
+  let tuning_menu = siv
+    .menubar()
+    .find_subtree("Anu")
+    .and_then(|tree| tree.find_subtree("Tuning"));
+  if let Some(tree) = tuning_menu {
+    *tree = build_tuning_menu(tunings, selected, retune_mode, midi_tx);
+  }
+}
+
 fn build_osc_menu() -> cursive::menu::Tree {
   menu::Tree::new().with(|tree| {
     for (osc, port) in consts::MENU_OSC.iter() {
@@ -257,6 +1021,348 @@ fn build_scale_menu_top() -> cursive::menu::Tree {
   })
 }
 
+pub fn build_signature_view<F>(signature: Signature, on_apply: F) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, Signature) + Send + Sync + 'static,
+{
+  let field = |value: Ratio<i64>, name: &str| {
+    EditView::new()
+      .content(value.to_integer().to_string())
+      .with_name(name)
+      .fixed_width(5)
+  };
+
+  let fields = ListView::new()
+    .child(
+      "beats per bar: ",
+      field(signature.beats_per_bar, "signature_beats"),
+    )
+    .child(
+      "ticks per beat: ",
+      field(signature.ticks_per_beat, "signature_ticks"),
+    )
+    .child(
+      "bars per loop: ",
+      field(signature.bars_per_loop, "signature_bars"),
+    );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title("Signature")
+      .button("Apply", move |s| {
+        let parsed = ["signature_beats", "signature_ticks", "signature_bars"].map(|name| {
+          s.call_on_name(name, |view: &mut EditView| view.get_content())
+            .and_then(|content| content.trim().parse::<i64>().ok())
+            .filter(|value| *value > 0)
+        });
+
+        match parsed {
+          [Some(beats), Some(ticks), Some(bars)] => {
+            s.pop_layer();
+            on_apply(
+              s,
+              Signature {
+                ticks_per_beat: Ratio::from_integer(ticks),
+                beats_per_bar: Ratio::from_integer(beats),
+                bars_per_loop: Ratio::from_integer(bars),
+              },
+            );
+          }
+          _ => s.add_layer(Dialog::info("signature values should be positive numbers")),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
+pub fn build_virtual_port_view<F>(port_name: &str, on_apply: F) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, String) + Send + Sync + 'static,
+{
+  let fields = ListView::new().child(
+    "port name: ",
+    EditView::new()
+      .content(port_name)
+      .with_name("virtual_port_name")
+      .fixed_width(20),
+  );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title("Virtual MIDI Port")
+      .button("Apply", move |s| {
+        let name = s
+          .call_on_name("virtual_port_name", |view: &mut EditView| {
+            view.get_content()
+          })
+          .map(|content| content.trim().to_string())
+          .filter(|name| !name.is_empty());
+
+        match name {
+          Some(name) => {
+            s.pop_layer();
+            on_apply(s, name);
+          }
+          None => s.add_layer(Dialog::info("port name should not be empty")),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
+pub fn build_recording_view<F>(file_name: &str, on_save: F) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, String) + Send + Sync + 'static,
+{
+  let fields = ListView::new().child(
+    "file name: ",
+    EditView::new()
+      .content(file_name)
+      .with_name("recording_file_name")
+      .fixed_width(30),
+  );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title("Save Recording")
+      .button("Save", move |s| {
+        let name = s
+          .call_on_name("recording_file_name", |view: &mut EditView| {
+            view.get_content()
+          })
+          .map(|content| content.trim().to_string())
+          .filter(|name| !name.is_empty());
+
+        match name {
+          Some(name) => {
+            s.pop_layer();
+            on_save(s, name);
+          }
+          None => s.add_layer(Dialog::info("file name should not be empty")),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
+pub fn build_output_view<F>(
+  port_name: &str,
+  enabled: bool,
+  channel: Option<u8>,
+  on_apply: F,
+) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, bool, Option<u8>) + Send + Sync + 'static,
+{
+  let fields = ListView::new()
+    .child(
+      "enabled: ",
+      Checkbox::new()
+        .with_checked(enabled)
+        .with_name("output_enabled"),
+    )
+    .child(
+      "channel (1-16, empty = thru): ",
+      EditView::new()
+        .content(channel.map_or(String::new(), |c| (c + 1).to_string()))
+        .with_name("output_channel")
+        .fixed_width(4),
+    );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title(port_name)
+      .button("Apply", move |s| {
+        let enabled = s
+          .call_on_name("output_enabled", |view: &mut Checkbox| view.is_checked())
+          .unwrap_or(true);
+        let channel = s
+          .call_on_name("output_channel", |view: &mut EditView| view.get_content())
+          .map(|content| content.trim().to_string())
+          .unwrap_or_default();
+
+        let channel = match channel.as_str() {
+          "" => Ok(None),
+          c => match c.parse::<u8>() {
+            Ok(c @ 1..=16) => Ok(Some(c - 1)),
+            _ => Err("channel should be between 1 and 16"),
+          },
+        };
+        match channel {
+          Ok(channel) => {
+            s.pop_layer();
+            on_apply(s, enabled, channel);
+          }
+          Err(e) => s.add_layer(Dialog::info(e)),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
+pub fn build_mpe_view<F>(zone: Option<Zone>, on_apply: F) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, Option<Zone>) + Send + Sync + 'static,
+{
+  let kinds = [
+    ("off", None),
+    ("lower (master ch 1)", Some(ZoneKind::Lower)),
+    ("upper (master ch 16)", Some(ZoneKind::Upper)),
+  ];
+  let selected = kinds
+    .iter()
+    .position(|(_, kind)| *kind == zone.map(|zone| zone.kind))
+    .unwrap_or(0);
+  let fields = ListView::new()
+    .child(
+      "zone: ",
+      SelectView::new()
+        .popup()
+        .with_all(kinds)
+        .selected(selected)
+        .with_name("mpe_zone"),
+    )
+    .child(
+      "member channels (1-15): ",
+      EditView::new()
+        .content(
+          zone
+            .map_or(consts::MAX_MPE_MEMBERS, |zone| zone.members)
+            .to_string(),
+        )
+        .with_name("mpe_members")
+        .fixed_width(4),
+    )
+    .child(
+      "pitch bend range (semitones): ",
+      EditView::new()
+        .content(
+          zone
+            .map_or(consts::DEFAULT_MPE_PITCH_BEND_RANGE, |zone| {
+              zone.pitch_bend_range
+            })
+            .to_string(),
+        )
+        .with_name("mpe_pitch_bend_range")
+        .fixed_width(4),
+    );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title("MPE")
+      .button("Apply", move |s| {
+        let kind = s
+          .call_on_name("mpe_zone", |view: &mut SelectView<Option<ZoneKind>>| {
+            view.selection().map(|kind| *kind)
+          })
+          .flatten()
+          .flatten();
+        let members = s
+          .call_on_name("mpe_members", |view: &mut EditView| view.get_content())
+          .and_then(|content| content.trim().parse::<u8>().ok())
+          .filter(|members| (1..=consts::MAX_MPE_MEMBERS).contains(members));
+        let pitch_bend_range = s
+          .call_on_name("mpe_pitch_bend_range", |view: &mut EditView| {
+            view.get_content()
+          })
+          .and_then(|content| content.trim().parse::<u8>().ok())
+          .filter(|range| (1..=96).contains(range));
+
+        match (kind, members, pitch_bend_range) {
+          (None, _, _) => {
+            s.pop_layer();
+            on_apply(s, None);
+          }
+          (Some(kind), Some(members), Some(pitch_bend_range)) => {
+            s.pop_layer();
+            on_apply(s, Some(Zone::new(kind, members, pitch_bend_range)));
+          }
+          _ => s.add_layer(Dialog::info(
+            "member channels should be between 1 and 15, the range between 1 and 96",
+          )),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
+pub fn build_ramp_view<F>(tempo: i64, on_apply: F) -> OnEventView<Dialog>
+where
+  F: Fn(&mut Cursive, Tempo, i64, RampCurve) + Send + Sync + 'static,
+{
+  let fields = ListView::new()
+    .child(
+      "target bpm: ",
+      EditView::new()
+        .content(tempo.to_string())
+        .with_name("ramp_target")
+        .fixed_width(7),
+    )
+    .child(
+      "bars: ",
+      EditView::new()
+        .content("4")
+        .with_name("ramp_bars")
+        .fixed_width(7),
+    )
+    .child(
+      "curve: ",
+      SelectView::new()
+        .popup()
+        .item("linear", RampCurve::Linear)
+        .item("exponential", RampCurve::Exponential)
+        .with_name("ramp_curve"),
+    );
+
+  OnEventView::new(
+    Dialog::around(fields)
+      .title("Tempo Ramp")
+      .button("Start", move |s| {
+        // bpm can be a ratio, eg. 281/2
+        let target = s
+          .call_on_name("ramp_target", |view: &mut EditView| view.get_content())
+          .and_then(|content| content.trim().parse::<Tempo>().ok())
+          .filter(|target| *target > Ratio::from_integer(0));
+        let bars = s
+          .call_on_name("ramp_bars", |view: &mut EditView| view.get_content())
+          .and_then(|content| content.trim().parse::<i64>().ok())
+          .filter(|bars| *bars > 0);
+        let curve = s
+          .call_on_name("ramp_curve", |view: &mut SelectView<RampCurve>| {
+            view.selection().map(|curve| *curve)
+          })
+          .flatten();
+
+        match (target, bars, curve) {
+          (Some(target), Some(bars), Some(curve)) => {
+            s.pop_layer();
+            on_apply(s, target, bars, curve);
+          }
+          _ => s.add_layer(Dialog::info("bpm and bars should be positive numbers")),
+        }
+      })
+      .dismiss_button("Cancel"),
+  )
+  .on_event(Event::Key(Key::Esc), |s| {
+    s.pop_layer();
+  })
+}
+
 fn dialog_file_explorer() -> OnEventView<ResizedView<Dialog>> {
   let default_path = get_default_database_path();
   let paths = fs::read_dir(default_path.unwrap())
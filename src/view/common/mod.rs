@@ -3,4 +3,4 @@ pub mod grid_editor;
 pub mod menubar;
 pub mod playhead;
 pub mod playhead_controller;
-// pub mod timing_diagnostic;
+pub mod timing_diagnostic;
@@ -18,6 +18,9 @@ use cursive::views::TextView;
 use cursive::Vec2;
 use cursive::XY;
 
+use crate::core::channel_msg::StepContext;
+use crate::core::midi_learn::{scale_value, Param};
+use crate::core::timing::clock::{Signature, Tick};
 use crate::core::{consts, midi, playback_modes, rect::Rect, regex::Match, utils};
 use crate::view::common::grid_editor::CanvasEditor;
 use crate::view::common::playhead_controller::Direction;
@@ -94,6 +97,7 @@ pub enum UIUpdate {
   OpQueueDisplay(String),
   EvQueueDisplay(String),
   MarkerPosAndArea(Vec2, Rect),
+  Regions(Vec<(Rect, Vec2)>), // (area, absolute active position) of every pinned region
 }
 
 struct GridParams<'a, R: rand::Rng> {
@@ -108,6 +112,7 @@ pub struct MarkerUI {
   pub marker_area: Rect,
   pub marker_pos: Vec2,
   pub actived_pos: Vec2,
+  pub regions: Vec<(Rect, Vec2)>, // (area, absolute active position)
   pub text_matcher: Option<HashMap<usize, Match>>,
   pub regex_indexes: Arc<Mutex<BTreeSet<usize>>>,
   pub reverse_mode: bool,
@@ -121,6 +126,7 @@ impl MarkerUI {
       marker_area: Rect::from_point(Vec2::zero()),
       marker_pos: Vec2::zero(),
       actived_pos: Vec2::zero(),
+      regions: Vec::new(),
       text_matcher: None,
       regex_indexes: Arc::new(Mutex::new(BTreeSet::new())),
       reverse_mode: false,
@@ -130,14 +136,35 @@ impl MarkerUI {
   }
 }
 
+/// A marker left in place over the text, stepping with its own ratio and loop length so that
+/// regions over the same text drift against each other
+#[derive(Clone, Debug)]
+pub struct Region {
+  area: Rect,
+  ratio: (i64, usize),
+  loop_length: usize,
+  actived_pos: Vec2,
+  prev_active_pos: Vec2,
+  last_step: Option<(usize, usize)>, // (tick, step)
+  controls: MarkerControls,
+}
+
+/// What a marker adds to the controls of the matches it plays
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MarkerControls {
+  program: Option<(u8, u8)>, // (channel, program), see `ChannelMsg::marker_program`
+  inside_match: bool,        // its last step, to bend back once it leaves the match
+}
+
 #[derive(Clone, Debug)]
 pub enum Message {
   Move(Direction, XY<usize>, cursive::CbSink),
   SetCurrentPos(XY<usize>, XY<usize>, cursive::CbSink),
   UpdateInfoStatusView(cursive::CbSink),
   SetGridArea(XY<usize>, cursive::CbSink),
-  SetActivePos(usize, cursive::CbSink),
+  SetActivePos(usize, f32, cursive::CbSink), // (tick, velocity_scale, _)
   Scale((i32, i32), cursive::CbSink),
+  SetMarkerParam(Param, u8, cursive::CbSink), // (x|y|w|h, controller value, _)
   SetMatcher(Option<HashMap<usize, Match>>, cursive::CbSink),
   SetGridSize(usize, usize),
   SetScaleModeLeft(crate::core::scale::ScaleMode),
@@ -148,6 +175,11 @@ pub enum Message {
   ToggleRandomMode(cursive::CbSink),
   SetTempo(usize),
   SetRatio((i64, usize), cursive::CbSink),
+  SetLoopLength(usize, cursive::CbSink),
+  SetSignature(Signature),
+  PinRegion(),
+  RemoveRegion(),
+  SetProgram(Option<(u8, u8)>), // (channel, program)
 }
 
 pub struct MarkerArea {
@@ -159,6 +191,8 @@ pub struct MarkerArea {
   regex_indexes: Arc<Mutex<BTreeSet<usize>>>,
   text_matcher: Arc<Mutex<Option<HashMap<usize, Match>>>>,
   midi_tx: Sender<midi::Message>,
+  sends_controls: Arc<AtomicBool>, // see `Midi::sends_controls`
+  controls: Mutex<MarkerControls>,
   grid_width: AtomicUsize,
   grid_height: AtomicUsize,
   tempo: AtomicUsize,
@@ -171,6 +205,10 @@ pub struct MarkerArea {
   arpeggiator_mode: AtomicBool,
   random_mode: AtomicBool,
   ratio: Arc<Mutex<(i64, usize)>>,
+  loop_length: AtomicUsize,
+  ticks_per_beat: Arc<Mutex<Tick>>,
+  last_step: Arc<Mutex<Option<(usize, usize)>>>, // (tick, step)
+  regions: Arc<Mutex<Vec<Region>>>,
   operator_queue: Arc<Mutex<VecDeque<QueueItem>>>,
   event_queue: Arc<Mutex<VecDeque<EventOperator>>>,
   pushed_positions: Arc<Mutex<HashMap<(usize, usize), bool>>>,
@@ -180,7 +218,7 @@ pub struct MarkerArea {
 }
 
 impl MarkerArea {
-  pub fn new(midi_tx: Sender<midi::Message>) -> Self {
+  pub fn new(midi_tx: Sender<midi::Message>, sends_controls: Arc<AtomicBool>) -> Self {
     MarkerArea {
       pos: Arc::new(Mutex::new(Vec2::zero())),
       area: Arc::new(Mutex::new(Rect::from_point(Vec2::zero()))),
@@ -190,6 +228,8 @@ impl MarkerArea {
       regex_indexes: Arc::new(Mutex::new(BTreeSet::new())),
       text_matcher: Arc::new(Mutex::new(None)),
       midi_tx,
+      sends_controls,
+      controls: Mutex::new(MarkerControls::default()),
       grid_width: AtomicUsize::new(0),
       grid_height: AtomicUsize::new(0),
       tempo: AtomicUsize::new(120),
@@ -202,12 +242,16 @@ impl MarkerArea {
       arpeggiator_mode: AtomicBool::new(false),
       random_mode: AtomicBool::new(false),
       ratio: Arc::new(Mutex::new((1, 16))),
+      loop_length: AtomicUsize::new(0),
+      ticks_per_beat: Arc::new(Mutex::new(Signature::default().ticks_per_beat)),
+      last_step: Arc::new(Mutex::new(None)),
+      regions: Arc::new(Mutex::new(Vec::new())),
       operator_queue: Arc::new(Mutex::new(VecDeque::new())),
       event_queue: Arc::new(Mutex::new(VecDeque::new())),
       pushed_positions: Arc::new(Mutex::new(HashMap::new())),
       ui_update_queue: Arc::new(Mutex::new(VecDeque::new())),
       // #[cfg(debug_assertions)]
-      // timing_stats: Arc::new(TimingStats::new()),
+      // timing_stats: Arc::new(TimingStats::new("SetActivePos")),
     }
   }
 
@@ -282,6 +326,14 @@ impl MarkerArea {
                     view.set_content(utils::build_len_status_str((area_size.x, area_size.y)));
                   });
                 }
+                UIUpdate::Regions(regions) => {
+                  siv.call_on_name(
+                    consts::canvas_editor_section_view,
+                    move |canvas: &mut Canvas<CanvasEditor>| {
+                      canvas.state_mut().marker_ui.regions = regions;
+                    },
+                  );
+                }
               }
             }
           }))
@@ -391,15 +443,39 @@ impl MarkerArea {
     self.drag_start_y.store(top_left.y, Ordering::Relaxed);
   }
 
-  fn calculate_adjusted_pos(&self, pos: usize) -> usize {
-    let ratio = *self.ratio.lock().unwrap();
-    let divisor = std::cmp::max(1, 16 / ratio.1);
-    pos / divisor
+  fn next_step(&self, tick: usize) -> Option<usize> {
+    next_step_at(
+      tick,
+      *self.ticks_per_beat.lock().unwrap(),
+      *self.ratio.lock().unwrap(),
+      self.loop_length.load(Ordering::Relaxed),
+      &mut self.last_step.lock().unwrap(),
+    )
   }
 
-  pub fn set_actived_pos(&self, pos: usize) {
-    let area = self.area.lock().unwrap();
+  pub fn set_actived_pos(&self, adjusted_pos: usize) {
+    let area = *self.area.lock().unwrap();
+    let loop_length = self.loop_length.load(Ordering::Relaxed);
+    let regex_indexes = self.regex_indexes.lock().unwrap();
     let mut actived_pos = self.actived_pos.lock().unwrap();
+    self.step_through(
+      adjusted_pos,
+      area,
+      loop_length,
+      &regex_indexes,
+      &mut actived_pos,
+    );
+  }
+
+  // move `actived_pos` to step `adjusted_pos` of `area`, for the marker and the pinned regions alike
+  fn step_through(
+    &self,
+    adjusted_pos: usize,
+    area: Rect,
+    loop_length: usize,
+    regex_indexes: &BTreeSet<usize>,
+    actived_pos: &mut Vec2,
+  ) {
     let reverse = self.reverse_mode.load(Ordering::Relaxed);
     let arpeggiator = self.arpeggiator_mode.load(Ordering::Relaxed);
     let random = self.random_mode.load(Ordering::Relaxed);
@@ -409,12 +485,13 @@ impl MarkerArea {
     let marker_y = area.top();
     let canvas_w = self.grid_width.load(Ordering::Relaxed);
 
-    let adjusted_pos = self.calculate_adjusted_pos(pos);
+    if adjusted_pos == 0 && loop_length > 0 {
+      *actived_pos = playback_modes::loop_start_position(marker_h, reverse);
+    }
 
     if arpeggiator {
-      let regex_indexes = self.regex_indexes.lock().unwrap();
       let matches = playback_modes::get_arpeggiator_matches(
-        &regex_indexes,
+        regex_indexes,
         marker_x,
         marker_y,
         marker_w,
@@ -422,7 +499,6 @@ impl MarkerArea {
         canvas_w,
         reverse,
       );
-      drop(regex_indexes);
 
       if !matches.is_empty() {
         let step = if random {
@@ -441,7 +517,7 @@ impl MarkerArea {
           marker_h,
           reverse,
           random,
-          &mut actived_pos,
+          actived_pos,
         );
       }
     } else {
@@ -452,13 +528,12 @@ impl MarkerArea {
         marker_h,
         reverse,
         random,
-        &mut actived_pos,
+        actived_pos,
       );
     }
   }
 
-  fn calculate_absolute_position(&self, active_pos: Vec2) -> (usize, usize, usize) {
-    let pos = self.pos.lock().unwrap();
+  fn calculate_absolute_position(&self, pos: Vec2, active_pos: Vec2) -> (usize, usize, usize) {
     let grid_width = self.grid_width.load(Ordering::Relaxed);
     let abs_y = pos.y + active_pos.y;
     let abs_x = pos.x + active_pos.x;
@@ -469,17 +544,16 @@ impl MarkerArea {
   fn determine_note_position_and_scale(
     &self,
     active_pos: Vec2,
+    prev_active: &mut Vec2,
     abs_x: usize,
     abs_y: usize,
   ) -> (usize, crate::core::scale::ScaleMode) {
-    let mut prev_active = self.prev_active_pos.lock().unwrap();
     let prev_active_pos = *prev_active;
 
     let x_diff = active_pos.x.abs_diff(prev_active_pos.x);
     let y_diff = active_pos.y.abs_diff(prev_active_pos.y);
 
     *prev_active = active_pos;
-    drop(prev_active);
 
     let grid_height = self.grid_height.load(Ordering::Relaxed);
 
@@ -504,12 +578,16 @@ impl MarkerArea {
     curr_running_marker: usize,
     note_position: usize,
     scale_mode: crate::core::scale::ScaleMode,
+    velocity_scale: f32,
+    ratio: (i64, usize),
   ) -> bool {
     if let Some(matcher) = self.text_matcher.lock().unwrap().as_ref() {
-      if matcher.get(&curr_running_marker).is_some() {
+      if let Some(matched) = matcher.get(&curr_running_marker) {
         let grid_width = self.grid_width.load(Ordering::Relaxed);
         let grid_height = self.grid_height.load(Ordering::Relaxed);
         let current_tempo = self.tempo.load(Ordering::Relaxed);
+        let step_ticks =
+          playback_modes::ticks_per_step(*self.ticks_per_beat.lock().unwrap(), ratio);
 
         let _ = self.midi_tx.send(midi::Message::TriggerWithPosition((
           curr_running_marker,
@@ -518,6 +596,9 @@ impl MarkerArea {
           grid_height,
           scale_mode,
           current_tempo,
+          velocity_scale,
+          matched.note_params(),
+          step_ticks,
         )));
         return true;
       }
@@ -525,6 +606,50 @@ impl MarkerArea {
     false
   }
 
+  // controls follow the playhead through the whole match, notes only start on its first cell
+  fn trigger_controls_if_inside_match(
+    &self,
+    curr_running_marker: usize,
+    abs_x: usize,
+    abs_y: usize,
+    controls: &mut MarkerControls,
+  ) {
+    // nothing would be sent, skip looking up the match
+    if !self.sends_controls.load(Ordering::Relaxed) && controls.program.is_none() {
+      controls.inside_match = false;
+      return;
+    }
+
+    let text_matcher = self.text_matcher.lock().unwrap();
+    let step = text_matcher.as_ref().and_then(|matcher| {
+      matcher.values().find_map(|matched| {
+        let offset = matched.offset_of(curr_running_marker)?;
+        Some(StepContext::new(
+          matched,
+          offset,
+          (abs_x, abs_y),
+          (
+            self.grid_width.load(Ordering::Relaxed),
+            self.grid_height.load(Ordering::Relaxed),
+          ),
+          controls.program,
+        ))
+      })
+    });
+    drop(text_matcher);
+
+    let was_inside_match = std::mem::replace(&mut controls.inside_match, step.is_some());
+    match step {
+      Some(step) => {
+        let _ = self.midi_tx.send(midi::Message::TriggerControls(step));
+      }
+      None if was_inside_match => {
+        let _ = self.midi_tx.send(midi::Message::ReleaseControls());
+      }
+      None => {}
+    }
+  }
+
   fn handle_accumulation_mode(&self, abs_x: usize, cb_sink: &cursive::CbSink) -> Option<Vec2> {
     if !self.accumulation_mode.load(Ordering::Relaxed) {
       return None;
@@ -694,6 +819,19 @@ impl MarkerArea {
     }
   }
 
+  fn update_ratio_status(&self, cb_sink: &cursive::CbSink) {
+    let ratio = *self.ratio.lock().unwrap();
+    let loop_length = self.loop_length.load(Ordering::Relaxed);
+
+    cb_sink
+      .send(Box::new(move |siv| {
+        siv.call_on_name(consts::ratio_status_unit_view, |view: &mut TextView| {
+          view.set_content(utils::build_ratio_status_str(ratio, loop_length));
+        });
+      }))
+      .unwrap();
+  }
+
   fn update_active_pos_ui(&self, active_pos: Vec2, _cb_sink: &cursive::CbSink) {
     // Queue UI update instead of immediate send (batched processing)
     let mut queue = self.ui_update_queue.lock().unwrap();
@@ -758,11 +896,157 @@ impl MarkerArea {
     );
   }
 
+  /// Place the marker along one axis of the grid, keeping it inside the grid
+  pub fn set_marker_param(&self, param: Param, value: u8) {
+    let grid_width = self.grid_width.load(Ordering::Relaxed);
+    let grid_height = self.grid_height.load(Ordering::Relaxed);
+    if grid_width == 0 || grid_height == 0 {
+      return;
+    }
+
+    let mut pos = self.pos.lock().unwrap();
+    let mut area = self.area.lock().unwrap();
+    let (mut x, mut y) = (area.left(), area.top());
+    let (mut w, mut h) = (area.width(), area.height());
+
+    match param {
+      Param::MarkerX => x = scale_value(value, 0, grid_width.saturating_sub(w)),
+      Param::MarkerY => y = scale_value(value, 0, grid_height.saturating_sub(h)),
+      Param::MarkerW => w = scale_value(value, 1, grid_width.saturating_sub(x)),
+      Param::MarkerH => h = scale_value(value, 1, grid_height.saturating_sub(y)),
+      _ => return,
+    }
+
+    *pos = Vec2::new(x, y);
+    *area = Rect::from_size((x, y), (w, h));
+  }
+
   pub fn set_text_matcher(&self, text_matcher: Option<HashMap<usize, Match>>) {
     let mut tm = self.text_matcher.lock().unwrap();
     *tm = text_matcher
   }
 
+  /// Put the marker over `area` of the grid
+  pub fn place(&self, area: Rect) {
+    *self.pos.lock().unwrap() = area.top_left();
+    *self.area.lock().unwrap() = area;
+  }
+
+  /// Matches under the marker, for the arpeggiator.
+  /// The grid editor keeps these up to date while drawing, without a screen they are collected here
+  pub fn collect_regex_indexes(&self) {
+    let area = *self.area.lock().unwrap();
+    *self.regex_indexes.lock().unwrap() = self.regex_indexes_within(area);
+  }
+
+  fn regex_indexes_within(&self, area: Rect) -> BTreeSet<usize> {
+    let grid_width = self.grid_width.load(Ordering::Relaxed).max(1);
+    match self.text_matcher.lock().unwrap().as_ref() {
+      Some(matcher) => matcher
+        .keys()
+        .filter(|index| area.contains(Vec2::new(*index % grid_width, *index / grid_width)))
+        .copied()
+        .collect(),
+      None => BTreeSet::new(),
+    }
+  }
+
+  /// Leave a copy of the marker where it is, stepping on in phase with its ratio and loop length
+  pub fn pin_region(&self) {
+    let region = Region {
+      area: *self.area.lock().unwrap(),
+      ratio: *self.ratio.lock().unwrap(),
+      loop_length: self.loop_length.load(Ordering::Relaxed),
+      actived_pos: *self.actived_pos.lock().unwrap(),
+      prev_active_pos: *self.prev_active_pos.lock().unwrap(),
+      last_step: *self.last_step.lock().unwrap(),
+      controls: *self.controls.lock().unwrap(),
+    };
+    let mut regions = self.regions.lock().unwrap();
+    if regions.len() < consts::MAX_REGIONS {
+      regions.push(region);
+    }
+    self.update_regions_ui(&regions);
+  }
+
+  /// Drop the region pinned last
+  pub fn remove_region(&self) {
+    let mut regions = self.regions.lock().unwrap();
+    regions.pop();
+    self.update_regions_ui(&regions);
+  }
+
+  // every pinned region walks its own steps over the same matches, the marker's modes apply
+  fn step_regions(&self, tick: usize, velocity_scale: f32) {
+    let mut regions = self.regions.lock().unwrap();
+    if regions.is_empty() {
+      return;
+    }
+    let ticks_per_beat = *self.ticks_per_beat.lock().unwrap();
+    let arpeggiator = self.arpeggiator_mode.load(Ordering::Relaxed);
+    let mut has_moved = false;
+
+    for region in regions.iter_mut() {
+      let Some(step) = next_step_at(
+        tick,
+        ticks_per_beat,
+        region.ratio,
+        region.loop_length,
+        &mut region.last_step,
+      ) else {
+        continue;
+      };
+      has_moved = true;
+
+      let regex_indexes = match arpeggiator {
+        true => self.regex_indexes_within(region.area),
+        false => BTreeSet::new(),
+      };
+      self.step_through(
+        step,
+        region.area,
+        region.loop_length,
+        &regex_indexes,
+        &mut region.actived_pos,
+      );
+
+      let (abs_x, abs_y, curr_running_marker) =
+        self.calculate_absolute_position(region.area.top_left(), region.actived_pos);
+      let (note_position, scale_mode) = self.determine_note_position_and_scale(
+        region.actived_pos,
+        &mut region.prev_active_pos,
+        abs_x,
+        abs_y,
+      );
+      self.trigger_controls_if_inside_match(
+        curr_running_marker,
+        abs_x,
+        abs_y,
+        &mut region.controls,
+      );
+      self.trigger_midi_if_matched(
+        curr_running_marker,
+        note_position,
+        scale_mode,
+        velocity_scale,
+        region.ratio,
+      );
+    }
+
+    if has_moved {
+      self.update_regions_ui(&regions);
+    }
+  }
+
+  fn update_regions_ui(&self, regions: &[Region]) {
+    let regions_ui = regions
+      .iter()
+      .map(|region| (region.area, region.area.top_left() + region.actived_pos))
+      .collect();
+    let mut queue = self.ui_update_queue.lock().unwrap();
+    queue.push_back(UIUpdate::Regions(regions_ui));
+  }
+
   // Queue operators: P (Push), S (Swap), O (pOp), D (Duplicate) with narrow spacing
   // Event operators: r, c, x with wider spacing
   fn check_operators(&self, abs_x: usize) {
@@ -957,298 +1241,389 @@ impl MarkerArea {
 
     thread::spawn(move || {
       for control_message in &rx {
-        match control_message {
-          Message::Move(direction, canvas_size, cb_sink) => {
-            self.set_move(direction.clone(), canvas_size);
-
-            // Reset accumulation counter on user interaction
-            let mut counter = self.accumulation_counter.lock().unwrap();
-            *counter = 0;
-            drop(counter);
-
-            let pos_mutex = self.pos.lock().unwrap();
-            let pos = *pos_mutex;
-
-            let area_mutex = self.area.lock().unwrap();
-            let area = *area_mutex;
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::pos_status_unit_view, move |view: &mut TextView| {
-                  view.set_content(utils::build_pos_status_str(pos));
-                });
-
-                siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
-                  view.set_content("-");
-                });
-
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.marker_ui.marker_pos = pos;
-                    editor.marker_ui.marker_area = area;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::SetCurrentPos(position, offset, cb_sink) => {
-            self.set_current_pos(position, offset);
-
-            // Reset accumulation counter on user interaction
-            let mut counter = self.accumulation_counter.lock().unwrap();
-            *counter = 0;
-            drop(counter);
-
-            let mutex_pos = self.pos.lock().unwrap();
-            let pos = *mutex_pos;
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
-                  view.set_content("-");
-                });
-
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.marker_ui.marker_pos = pos;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::UpdateInfoStatusView(cb_sink) => {
-            let pos = self.pos.lock().unwrap();
-            let area = self.area.lock().unwrap();
-            let pos_x = pos.x;
-            let pos_y = pos.y;
-            let w = area.width();
-            let h = area.height();
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::pos_status_unit_view, move |view: &mut TextView| {
-                  view.set_content(utils::build_pos_status_str((pos_x, pos_y).into()))
-                });
-
-                siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
-                  view.set_content(utils::build_len_status_str((w, h)));
-                });
-              }))
-              .unwrap();
-          }
-          Message::SetGridArea(current_pos, cb_sink) => {
-            self.set_grid_area(current_pos);
-
-            // Reset accumulation counter on user interaction
-            let mut counter = self.accumulation_counter.lock().unwrap();
-            *counter = 0;
-            drop(counter);
-
-            let area = self.area.lock().unwrap();
-            let w = area.width();
-            let h = area.height();
-            let marker_area = *area;
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
-                  view.set_content("-");
-                });
-
-                siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
-                  view.set_content(utils::build_len_status_str((w, h)));
-                });
-
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-
-                    editor.marker_ui.marker_area = marker_area;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::SetActivePos(tick, cb_sink) => {
-            // #[cfg(debug_assertions)]
-            // let start = Instant::now();
+        self.handle(control_message);
+      }
+    });
 
-            self.set_actived_pos(tick);
+    tx
+  }
 
-            let active_pos_mutex = self.actived_pos.lock().unwrap();
-            let mut active_pos = *active_pos_mutex;
-            drop(active_pos_mutex);
+  /// Act on one control message, `run` feeds it from the channel and the offline render calls it directly
+  pub fn handle(&self, control_message: Message) {
+    match control_message {
+      Message::Move(direction, canvas_size, cb_sink) => {
+        self.set_move(direction.clone(), canvas_size);
 
-            let (abs_x, abs_y, curr_running_marker) = self.calculate_absolute_position(active_pos);
+        // Reset accumulation counter on user interaction
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
 
-            let (note_position, scale_mode) =
-              self.determine_note_position_and_scale(active_pos, abs_x, abs_y);
+        let pos_mutex = self.pos.lock().unwrap();
+        let pos = *pos_mutex;
 
-            let matched =
-              self.trigger_midi_if_matched(curr_running_marker, note_position, scale_mode);
+        let area_mutex = self.area.lock().unwrap();
+        let area = *area_mutex;
 
-            if matched {
-              if let Some(new_active_pos) = self.handle_accumulation_mode(abs_x, &cb_sink) {
-                active_pos = new_active_pos;
-              }
-            }
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::pos_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_pos_status_str(pos));
+            });
+
+            siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
+              view.set_content("-");
+            });
+
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.marker_ui.marker_pos = pos;
+                editor.marker_ui.marker_area = area;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetCurrentPos(position, offset, cb_sink) => {
+        self.set_current_pos(position, offset);
 
-            self.update_active_pos_ui(active_pos, &cb_sink);
-
-            // #[cfg(debug_assertions)]
-            // {
-            //   let elapsed = start.elapsed().as_micros() as u64;
-            //   self.timing_stats.record(elapsed);
-
-            //   // Immediate warning for slow calls
-            //   if elapsed > 1000 {
-            //     eprintln!(
-            //       "⚠️  SLOW: SetActivePos took {}μs ({:.2}ms)",
-            //       elapsed,
-            //       elapsed as f64 / 1000.0
-            //     );
-            //   }
-            // }
-          }
-          Message::Scale(size, cb_sink) => {
-            self.scale(size);
-
-            // Reset accumulation counter on user interaction
-            let mut counter = self.accumulation_counter.lock().unwrap();
-            *counter = 0;
-            drop(counter);
-
-            let area = self.area.lock().unwrap();
-            let marker_area = *area;
-            let area_size = area.size();
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
-                  view.set_content("-");
-                });
-
-                siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
-                  view.set_content(utils::build_len_status_str((area_size.x, area_size.y)));
-                });
-
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.marker_ui.marker_area = marker_area;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::SetMatcher(matcher, cb_sink) => {
-            self.set_text_matcher(matcher);
-
-            let text_matcher = self.text_matcher.lock().unwrap();
-            let mm = text_matcher.clone();
-
-            let regex_indexes_cloned = self.regex_indexes.clone();
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.marker_ui.text_matcher = mm;
-                    editor.marker_ui.regex_indexes = regex_indexes_cloned;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::SetGridSize(width, height) => {
-            self.grid_width.store(width, Ordering::Relaxed);
-            self.grid_height.store(height, Ordering::Relaxed);
-          }
-          Message::SetScaleModeLeft(scale_mode) => {
-            let mut mode = self.scale_mode_left.lock().unwrap();
-            *mode = scale_mode;
-          }
-          Message::SetScaleModeTop(scale_mode) => {
-            let mut mode = self.scale_mode_top.lock().unwrap();
-            *mode = scale_mode;
-          }
-          Message::ToggleAccumulationMode(cb_sink) => {
-            let is_enabled = !self.accumulation_mode.load(Ordering::Relaxed);
-            self.accumulation_mode.store(is_enabled, Ordering::Relaxed);
+        // Reset accumulation counter on user interaction
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
 
-            // Reset counter when toggling mode
-            let mut counter = self.accumulation_counter.lock().unwrap();
-            *counter = 0;
-            drop(counter);
+        let mutex_pos = self.pos.lock().unwrap();
+        let pos = *mutex_pos;
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
+              view.set_content("-");
+            });
+
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.marker_ui.marker_pos = pos;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::UpdateInfoStatusView(cb_sink) => {
+        let pos = self.pos.lock().unwrap();
+        let area = self.area.lock().unwrap();
+        let pos_x = pos.x;
+        let pos_y = pos.y;
+        let w = area.width();
+        let h = area.height();
 
-            // Clear queue when disabling accumulation mode
-            if !is_enabled {
-              let mut queue = self.operator_queue.lock().unwrap();
-              queue.clear();
-              drop(queue);
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::pos_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_pos_status_str((pos_x, pos_y).into()))
+            });
 
-              let mut pushed = self.pushed_positions.lock().unwrap();
-              pushed.clear();
-              drop(pushed);
-            }
+            siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_len_status_str((w, h)));
+            });
+          }))
+          .unwrap();
+      }
+      Message::SetGridArea(current_pos, cb_sink) => {
+        self.set_grid_area(current_pos);
 
-            let mode_status = self.build_mode_status_string();
+        // Reset accumulation counter on user interaction
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
 
-            // Update UI to clear accumulation display and queue display
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
-                  view.set_content("-");
-                });
+        let area = self.area.lock().unwrap();
+        let w = area.width();
+        let h = area.height();
+        let marker_area = *area;
 
-                if !is_enabled {
-                  siv.call_on_name(consts::op_queue_status_unit_view, |view: &mut TextView| {
-                    view.set_content("[]");
-                  });
-                }
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
+              view.set_content("-");
+            });
+
+            siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_len_status_str((w, h)));
+            });
+
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+
+                editor.marker_ui.marker_area = marker_area;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetActivePos(tick, velocity_scale, cb_sink) => {
+        // #[cfg(debug_assertions)]
+        // let start = Instant::now();
 
-                siv.call_on_name(consts::osc_status_unit_view, |view: &mut TextView| {
-                  view.set_content(mode_status);
-                });
-              }))
-              .unwrap();
-          }
-          Message::SetTempo(bpm) => {
-            self.tempo.store(bpm, Ordering::Relaxed);
-          }
-          Message::SetRatio(new_ratio, cb_sink) => {
-            let mut ratio = self.ratio.lock().unwrap();
-            *ratio = new_ratio;
-            drop(ratio);
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(consts::ratio_status_unit_view, |view: &mut TextView| {
-                  view.set_content(utils::build_ratio_status_str(new_ratio, ""));
-                });
-              }))
-              .unwrap();
-          }
-          Message::ToggleReverseMode(cb_sink) => {
-            self.toggle_reverse_mode(cb_sink);
-          }
-          Message::ToggleArpeggiatorMode(cb_sink) => {
-            self.toggle_arpeggiator_mode(cb_sink);
-          }
-          Message::ToggleRandomMode(cb_sink) => {
-            self.toggle_random_mode(cb_sink);
+        // pinned regions keep their own steps, whether the marker moves on this tick or not
+        self.step_regions(tick, velocity_scale);
+
+        let Some(step) = self.next_step(tick) else {
+          return;
+        };
+        self.set_actived_pos(step);
+
+        let active_pos_mutex = self.actived_pos.lock().unwrap();
+        let mut active_pos = *active_pos_mutex;
+        drop(active_pos_mutex);
+
+        let pos = *self.pos.lock().unwrap();
+        let (abs_x, abs_y, curr_running_marker) = self.calculate_absolute_position(pos, active_pos);
+
+        let (note_position, scale_mode) = self.determine_note_position_and_scale(
+          active_pos,
+          &mut self.prev_active_pos.lock().unwrap(),
+          abs_x,
+          abs_y,
+        );
+
+        self.trigger_controls_if_inside_match(
+          curr_running_marker,
+          abs_x,
+          abs_y,
+          &mut self.controls.lock().unwrap(),
+        );
+
+        let matched = self.trigger_midi_if_matched(
+          curr_running_marker,
+          note_position,
+          scale_mode,
+          velocity_scale,
+          *self.ratio.lock().unwrap(),
+        );
+
+        if matched {
+          if let Some(new_active_pos) = self.handle_accumulation_mode(abs_x, &cb_sink) {
+            active_pos = new_active_pos;
           }
         }
+
+        self.update_active_pos_ui(active_pos, &cb_sink);
+
+        // #[cfg(debug_assertions)]
+        // {
+        //   let elapsed = start.elapsed().as_micros() as u64;
+        //   self.timing_stats.record(elapsed);
+
+        //   // Immediate warning for slow calls
+        //   if elapsed > 1000 {
+        //     eprintln!(
+        //       "⚠️  SLOW: SetActivePos took {}μs ({:.2}ms)",
+        //       elapsed,
+        //       elapsed as f64 / 1000.0
+        //     );
+        //   }
+        // }
       }
-    });
+      Message::SetMarkerParam(param, value, cb_sink) => {
+        self.set_marker_param(param, value);
 
-    tx
+        // Reset accumulation counter on user interaction
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
+
+        let pos = *self.pos.lock().unwrap();
+        let marker_area = *self.area.lock().unwrap();
+        let area_size = marker_area.size();
+
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::pos_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_pos_status_str(pos));
+            });
+
+            siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_len_status_str((area_size.x, area_size.y)));
+            });
+
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.marker_ui.marker_pos = pos;
+                editor.marker_ui.marker_area = marker_area;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::Scale(size, cb_sink) => {
+        self.scale(size);
+
+        // Reset accumulation counter on user interaction
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
+
+        let area = self.area.lock().unwrap();
+        let marker_area = *area;
+        let area_size = area.size();
+
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
+              view.set_content("-");
+            });
+
+            siv.call_on_name(consts::len_status_unit_view, move |view: &mut TextView| {
+              view.set_content(utils::build_len_status_str((area_size.x, area_size.y)));
+            });
+
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.marker_ui.marker_area = marker_area;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetMatcher(matcher, cb_sink) => {
+        self.set_text_matcher(matcher);
+
+        let text_matcher = self.text_matcher.lock().unwrap();
+        let mm = text_matcher.clone();
+
+        let regex_indexes_cloned = self.regex_indexes.clone();
+
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.marker_ui.text_matcher = mm;
+                editor.marker_ui.regex_indexes = regex_indexes_cloned;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetGridSize(width, height) => {
+        self.grid_width.store(width, Ordering::Relaxed);
+        self.grid_height.store(height, Ordering::Relaxed);
+      }
+      Message::SetScaleModeLeft(scale_mode) => {
+        let mut mode = self.scale_mode_left.lock().unwrap();
+        *mode = scale_mode;
+      }
+      Message::SetScaleModeTop(scale_mode) => {
+        let mut mode = self.scale_mode_top.lock().unwrap();
+        *mode = scale_mode;
+      }
+      Message::ToggleAccumulationMode(cb_sink) => {
+        let is_enabled = !self.accumulation_mode.load(Ordering::Relaxed);
+        self.accumulation_mode.store(is_enabled, Ordering::Relaxed);
+
+        // Reset counter when toggling mode
+        let mut counter = self.accumulation_counter.lock().unwrap();
+        *counter = 0;
+        drop(counter);
+
+        // Clear queue when disabling accumulation mode
+        if !is_enabled {
+          let mut queue = self.operator_queue.lock().unwrap();
+          queue.clear();
+          drop(queue);
+
+          let mut pushed = self.pushed_positions.lock().unwrap();
+          pushed.clear();
+          drop(pushed);
+        }
+
+        let mode_status = self.build_mode_status_string();
+
+        // Update UI to clear accumulation display and queue display
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(consts::input_status_unit_view, |view: &mut TextView| {
+              view.set_content("-");
+            });
+
+            if !is_enabled {
+              siv.call_on_name(consts::op_queue_status_unit_view, |view: &mut TextView| {
+                view.set_content("[]");
+              });
+            }
+
+            siv.call_on_name(consts::osc_status_unit_view, |view: &mut TextView| {
+              view.set_content(mode_status);
+            });
+          }))
+          .unwrap();
+      }
+      Message::SetTempo(bpm) => {
+        self.tempo.store(bpm, Ordering::Relaxed);
+      }
+      Message::SetRatio(new_ratio, cb_sink) => {
+        let mut ratio = self.ratio.lock().unwrap();
+        *ratio = new_ratio;
+        drop(ratio);
+
+        self.update_ratio_status(&cb_sink);
+      }
+      Message::SetLoopLength(loop_length, cb_sink) => {
+        self.loop_length.store(loop_length, Ordering::Relaxed);
+        self.update_ratio_status(&cb_sink);
+      }
+      Message::SetSignature(signature) => {
+        *self.ticks_per_beat.lock().unwrap() = signature.ticks_per_beat;
+      }
+      Message::PinRegion() => {
+        self.pin_region();
+      }
+      Message::RemoveRegion() => {
+        self.remove_region();
+      }
+      Message::SetProgram(program) => {
+        self.controls.lock().unwrap().program = program;
+      }
+      Message::ToggleReverseMode(cb_sink) => {
+        self.toggle_reverse_mode(cb_sink);
+      }
+      Message::ToggleArpeggiatorMode(cb_sink) => {
+        self.toggle_arpeggiator_mode(cb_sink);
+      }
+      Message::ToggleRandomMode(cb_sink) => {
+        self.toggle_random_mode(cb_sink);
+      }
+    }
   }
 }
+
+// the clock ticks much finer than any step, so a marker only moves when its own step changes
+fn next_step_at(
+  tick: usize,
+  ticks_per_beat: Tick,
+  ratio: (i64, usize),
+  loop_length: usize,
+  last_step: &mut Option<(usize, usize)>,
+) -> Option<usize> {
+  let step = playback_modes::step_at(tick, ticks_per_beat, ratio, loop_length);
+
+  let is_new_step = match *last_step {
+    // the clock went back (stop/reset), replay the step even if it is the same one
+    Some((last_tick, last)) => tick <= last_tick || step != last,
+    None => true,
+  };
+  *last_step = Some((tick, step));
+
+  is_new_step.then_some(step)
+}
@@ -1,11 +1,17 @@
 use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
 use std::thread;
 
-use cursive::{views::Canvas, XY};
+use cursive::{
+  views::{Canvas, TextView},
+  XY,
+};
 
-use crate::core::{consts, midi, regex::Match};
+use crate::core::midi_learn::Param;
+use crate::core::timing::clock::{Quantization, Signature};
+use crate::core::{consts, midi, regex::Match, utils};
 
 use super::grid_editor::CanvasEditor;
 use super::playhead;
@@ -26,18 +32,28 @@ pub enum Message {
   SetCurrentPos(XY<usize>, XY<usize>),
   UpdateInfoStatusView(),
   SetGridArea(XY<usize>),
-  SetActivePos(usize),
+  SetActivePos(usize, f32), // (tick, velocity_scale)
   Scale((i32, i32)),
   SetMatcher(Option<HashMap<usize, Match>>),
   SetGridSize(usize, usize),
   SetScaleModeLeft(crate::core::scale::ScaleMode),
   SetScaleModeTop(crate::core::scale::ScaleMode),
+  SetRoot(u8),               // transpose from the MIDI keyboard, see `core::transpose`
+  SetMarkerParam(Param, u8), // (x|y|w|h, controller value)
   ToggleAccumulationMode(),
   ToggleReverseMode(),
   ToggleArpeggiatorMode(),
   ToggleRandomMode(),
   SetTempo(usize),
   SetRatio((i64, usize)),
+  SetLoopLength(usize),
+  PinRegion(), // leave the marker in place with its ratio and loop length, see `playhead::Region`
+  RemoveRegion(),
+  SetProgram(Option<(u8, u8)>), // (channel, program) of the marker, pinned regions keep theirs
+  SetSignature(Signature),
+  SetLaunchQuantization(Quantization),
+  SetPlaying(bool),
+  Boundary(Quantization), // sent on every tick that starts a beat
 }
 
 pub struct Marker {
@@ -45,6 +61,7 @@ pub struct Marker {
   pub rx: Receiver<Message>,
   cb_sink: cursive::CbSink,
   midi_tx: Sender<midi::Message>,
+  sends_controls: Arc<AtomicBool>,
 }
 
 impl Direction {
@@ -60,7 +77,11 @@ impl Direction {
 }
 
 impl Marker {
-  pub fn new(cb_sink: cursive::CbSink, midi_tx: Sender<midi::Message>) -> Self {
+  pub fn new(
+    cb_sink: cursive::CbSink,
+    midi_tx: Sender<midi::Message>,
+    sends_controls: Arc<AtomicBool>,
+  ) -> Self {
     let (tx, rx) = channel();
 
     Marker {
@@ -68,11 +89,15 @@ impl Marker {
       rx,
       cb_sink,
       midi_tx,
+      sends_controls,
     }
   }
 
   pub fn run(self) {
-    let marker_area = Arc::new(MarkerArea::new(self.midi_tx.clone()));
+    let marker_area = Arc::new(MarkerArea::new(
+      self.midi_tx.clone(),
+      Arc::clone(&self.sends_controls),
+    ));
 
     // Spawn UI batch processor thread (60 FPS)
     playhead::MarkerArea::spawn_ui_processor(
@@ -83,138 +108,251 @@ impl Marker {
     let marker_area_tx = marker_area.run();
 
     thread::spawn(move || {
+      let mut launch_quantization = Quantization::Off;
+      let mut playing = false;
+      let mut pending: Vec<Message> = Vec::new();
+
       for control_message in &self.rx {
         match control_message {
-          Message::Move(direction, canvas_size) => {
-            marker_area_tx
-              .send(playhead::Message::Move(
-                direction,
-                canvas_size,
-                self.cb_sink.clone(),
-              ))
-              .unwrap();
-          }
-          Message::SetCurrentPos(position, offset) => {
-            marker_area_tx
-              .send(playhead::Message::SetCurrentPos(
-                position,
-                offset,
-                self.cb_sink.clone(),
-              ))
-              .unwrap();
-          }
-          Message::UpdateInfoStatusView() => {
-            marker_area_tx
-              .send(playhead::Message::UpdateInfoStatusView(
-                self.cb_sink.clone(),
-              ))
-              .unwrap();
-          }
-          Message::SetGridArea(current_pos) => {
-            marker_area_tx
-              .send(playhead::Message::SetGridArea(
-                current_pos,
-                self.cb_sink.clone(),
-              ))
-              .unwrap();
-          }
-          Message::SetActivePos(tick) => {
-            marker_area_tx
-              .send(playhead::Message::SetActivePos(tick, self.cb_sink.clone()))
-              .unwrap();
-          }
-          Message::Scale(dir) => {
-            marker_area_tx
-              .send(playhead::Message::Scale(dir, self.cb_sink.clone()))
-              .unwrap();
-          }
-          Message::SetMatcher(matcher) => {
-            marker_area_tx
-              .send(playhead::Message::SetMatcher(matcher, self.cb_sink.clone()))
-              .unwrap();
+          Message::SetLaunchQuantization(quantization) => {
+            launch_quantization = quantization;
+            if launch_quantization == Quantization::Off {
+              self.flush_pending(&mut pending, &marker_area_tx);
+            }
+            self.update_launch_status(launch_quantization, pending.len());
           }
-          Message::SetGridSize(width, height) => {
-            marker_area_tx
-              .send(playhead::Message::SetGridSize(width, height))
-              .unwrap();
-          }
-          Message::SetScaleModeLeft(scale_mode) => {
-            let cb_sink = self.cb_sink.clone();
-
-            marker_area_tx
-              .send(playhead::Message::SetScaleModeLeft(scale_mode))
-              .unwrap();
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.scale_mode_left = scale_mode;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::SetScaleModeTop(scale_mode) => {
-            let cb_sink = self.cb_sink.clone();
-
-            marker_area_tx
-              .send(playhead::Message::SetScaleModeTop(scale_mode))
-              .unwrap();
-
-            cb_sink
-              .send(Box::new(move |siv| {
-                siv.call_on_name(
-                  consts::canvas_editor_section_view,
-                  move |canvas: &mut Canvas<CanvasEditor>| {
-                    let editor = canvas.state_mut();
-                    editor.scale_mode_top = scale_mode;
-                  },
-                );
-              }))
-              .unwrap();
-          }
-          Message::ToggleAccumulationMode() => {
-            let cb_sink = self.cb_sink.clone();
-            marker_area_tx
-              .send(playhead::Message::ToggleAccumulationMode(cb_sink))
-              .unwrap();
-          }
-          Message::ToggleReverseMode() => {
-            let cb_sink = self.cb_sink.clone();
-            marker_area_tx
-              .send(playhead::Message::ToggleReverseMode(cb_sink))
-              .unwrap();
-          }
-          Message::SetTempo(bpm) => {
-            marker_area_tx
-              .send(playhead::Message::SetTempo(bpm))
-              .unwrap();
-
-            self.midi_tx.send(midi::Message::SetTempo(bpm)).unwrap();
+          Message::SetPlaying(is_playing) => {
+            playing = is_playing;
+            // no boundary is coming while stopped
+            if !playing && !pending.is_empty() {
+              self.flush_pending(&mut pending, &marker_area_tx);
+              self.update_launch_status(launch_quantization, 0);
+            }
           }
-          Message::SetRatio(ratio) => {
-            let cb_sink = self.cb_sink.clone();
-            marker_area_tx
-              .send(playhead::Message::SetRatio(ratio, cb_sink))
-              .unwrap();
+          Message::Boundary(boundary) => {
+            if !pending.is_empty() && boundary >= launch_quantization {
+              self.flush_pending(&mut pending, &marker_area_tx);
+              self.update_launch_status(launch_quantization, 0);
+            }
           }
-          Message::ToggleArpeggiatorMode() => {
-            let cb_sink = self.cb_sink.clone();
-            marker_area_tx
-              .send(playhead::Message::ToggleArpeggiatorMode(cb_sink))
-              .unwrap();
-          }
-          Message::ToggleRandomMode() => {
-            let cb_sink = self.cb_sink.clone();
-            marker_area_tx
-              .send(playhead::Message::ToggleRandomMode(cb_sink))
-              .unwrap();
+          msg @ (Message::SetMatcher(_)
+          | Message::SetScaleModeLeft(_)
+          | Message::SetScaleModeTop(_)
+          | Message::Move(..)
+          | Message::SetMarkerParam(..)
+          | Message::SetGridArea(_)
+          | Message::PinRegion()
+          | Message::RemoveRegion())
+            if playing && launch_quantization != Quantization::Off =>
+          {
+            pending.push(msg);
+            self.update_launch_status(launch_quantization, pending.len());
           }
+          msg => self.dispatch(msg, &marker_area_tx),
         }
       }
     });
   }
+
+  fn flush_pending(&self, pending: &mut Vec<Message>, marker_area_tx: &Sender<playhead::Message>) {
+    for msg in pending.drain(..) {
+      self.dispatch(msg, marker_area_tx);
+    }
+  }
+
+  fn update_launch_status(&self, quantization: Quantization, pending: usize) {
+    self
+      .cb_sink
+      .send(Box::new(move |siv| {
+        siv.call_on_name(consts::launch_status_unit_view, |view: &mut TextView| {
+          view.set_content(utils::build_launch_status_str(quantization.name(), pending));
+        });
+      }))
+      .unwrap();
+  }
+
+  fn dispatch(&self, control_message: Message, marker_area_tx: &Sender<playhead::Message>) {
+    match control_message {
+      Message::Move(direction, canvas_size) => {
+        marker_area_tx
+          .send(playhead::Message::Move(
+            direction,
+            canvas_size,
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::SetCurrentPos(position, offset) => {
+        marker_area_tx
+          .send(playhead::Message::SetCurrentPos(
+            position,
+            offset,
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::UpdateInfoStatusView() => {
+        marker_area_tx
+          .send(playhead::Message::UpdateInfoStatusView(
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::SetGridArea(current_pos) => {
+        marker_area_tx
+          .send(playhead::Message::SetGridArea(
+            current_pos,
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::SetActivePos(tick, velocity_scale) => {
+        marker_area_tx
+          .send(playhead::Message::SetActivePos(
+            tick,
+            velocity_scale,
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::Scale(dir) => {
+        marker_area_tx
+          .send(playhead::Message::Scale(dir, self.cb_sink.clone()))
+          .unwrap();
+      }
+      Message::SetMatcher(matcher) => {
+        marker_area_tx
+          .send(playhead::Message::SetMatcher(matcher, self.cb_sink.clone()))
+          .unwrap();
+      }
+      Message::SetGridSize(width, height) => {
+        marker_area_tx
+          .send(playhead::Message::SetGridSize(width, height))
+          .unwrap();
+      }
+      Message::SetScaleModeLeft(scale_mode) => {
+        let cb_sink = self.cb_sink.clone();
+
+        marker_area_tx
+          .send(playhead::Message::SetScaleModeLeft(scale_mode))
+          .unwrap();
+
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.scale_mode_left = scale_mode;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetScaleModeTop(scale_mode) => {
+        let cb_sink = self.cb_sink.clone();
+
+        marker_area_tx
+          .send(playhead::Message::SetScaleModeTop(scale_mode))
+          .unwrap();
+
+        cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                let editor = canvas.state_mut();
+                editor.scale_mode_top = scale_mode;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::SetMarkerParam(param, value) => {
+        marker_area_tx
+          .send(playhead::Message::SetMarkerParam(
+            param,
+            value,
+            self.cb_sink.clone(),
+          ))
+          .unwrap();
+      }
+      Message::SetRoot(root) => {
+        self
+          .cb_sink
+          .send(Box::new(move |siv| {
+            siv.call_on_name(
+              consts::canvas_editor_section_view,
+              move |canvas: &mut Canvas<CanvasEditor>| {
+                canvas.state_mut().root = root;
+              },
+            );
+          }))
+          .unwrap();
+      }
+      Message::ToggleAccumulationMode() => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::ToggleAccumulationMode(cb_sink))
+          .unwrap();
+      }
+      Message::ToggleReverseMode() => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::ToggleReverseMode(cb_sink))
+          .unwrap();
+      }
+      Message::SetTempo(bpm) => {
+        marker_area_tx
+          .send(playhead::Message::SetTempo(bpm))
+          .unwrap();
+
+        self.midi_tx.send(midi::Message::SetTempo(bpm)).unwrap();
+      }
+      Message::SetRatio(ratio) => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::SetRatio(ratio, cb_sink))
+          .unwrap();
+      }
+      Message::SetLoopLength(loop_length) => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::SetLoopLength(loop_length, cb_sink))
+          .unwrap();
+      }
+      Message::PinRegion() => {
+        marker_area_tx.send(playhead::Message::PinRegion()).unwrap();
+      }
+      Message::RemoveRegion() => {
+        marker_area_tx
+          .send(playhead::Message::RemoveRegion())
+          .unwrap();
+      }
+      Message::SetProgram(program) => {
+        marker_area_tx
+          .send(playhead::Message::SetProgram(program))
+          .unwrap();
+      }
+      Message::SetSignature(signature) => {
+        marker_area_tx
+          .send(playhead::Message::SetSignature(signature))
+          .unwrap();
+      }
+      Message::ToggleArpeggiatorMode() => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::ToggleArpeggiatorMode(cb_sink))
+          .unwrap();
+      }
+      Message::ToggleRandomMode() => {
+        let cb_sink = self.cb_sink.clone();
+        marker_area_tx
+          .send(playhead::Message::ToggleRandomMode(cb_sink))
+          .unwrap();
+      }
+      // handled in `run`, before anything is dispatched
+      Message::SetLaunchQuantization(_) | Message::SetPlaying(_) | Message::Boundary(_) => {}
+    }
+  }
 }
@@ -1,7 +1,12 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 
 #[cfg(debug_assertions)]
+pub const TIMING_LOG_ENV: &str = "ANUPARS_TIMING_LOG";
+
+#[cfg(debug_assertions)]
+#[derive(Debug)]
 pub struct TimingStats {
+  label: &'static str,
   min_micros: AtomicU64,
   max_micros: AtomicU64,
   total_micros: AtomicU64,
@@ -11,8 +16,9 @@ pub struct TimingStats {
 
 #[cfg(debug_assertions)]
 impl TimingStats {
-  pub fn new() -> Self {
+  pub fn new(label: &'static str) -> Self {
     Self {
+      label,
       min_micros: AtomicU64::new(u64::MAX),
       max_micros: AtomicU64::new(0),
       total_micros: AtomicU64::new(0),
@@ -59,16 +65,22 @@ impl TimingStats {
     }
   }
 
+  // opt-in, debug builds only write stats when the env var names a log file
   #[cfg(debug_assertions)]
   fn log_to_file_block(msg: &str) {
     use std::fs::OpenOptions;
     use std::io::Write;
-    let mut file = OpenOptions::new()
+    let Ok(path) = std::env::var(TIMING_LOG_ENV) else {
+      return;
+    };
+    let written = OpenOptions::new()
       .create(true)
       .append(true)
-      .open("./timing_debug.log")
-      .unwrap();
-    write!(file, "{}", msg).unwrap();
+      .open(&path)
+      .and_then(|mut file| write!(file, "{}", msg));
+    if let Err(e) = written {
+      eprintln!("Error writing timing log {}: {}", path, e);
+    }
   }
 
   pub fn print_stats(&self) {
@@ -86,13 +98,14 @@ impl TimingStats {
     let over_pct = (over * 100) / count;
 
     let block = format!(
-      "========== SetActivePos Timing Statistics ==========\n\
+      "========== {} Timing Statistics ==========\n\
     Calls:        {}\n\
     Min:          {}μs ({:.2}ms)\n\
     Max:          {}μs ({:.2}ms)    ← This is your jitter!\n\
     Avg:          {}μs ({:.2}ms)\n\
     Over 500μs:   {} ({}%)           ← {}% of calls are too slow\n\
     ============================================\n\n",
+      self.label,
       count,
       min,
       min as f64 / 1000.0,
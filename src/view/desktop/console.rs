@@ -27,6 +27,7 @@ pub enum RegexMode {
 pub struct TopSection {
   bpm: usize,
   ratio: (i64, usize),
+  swing: i64,
   pos: Vec2,
   len: (usize, usize),
 }
@@ -42,6 +43,7 @@ impl TopSection {
     TopSection {
       bpm: 120,
       ratio: (1, 16),
+      swing: consts::DEFAULT_SWING,
       pos: Vec2::zero(),
       len: (1, 1),
     }
@@ -114,9 +116,19 @@ impl TopSection {
       )
       .child(
         "RTO: ",
-        TextView::new(utils::build_ratio_status_str(app.top_section.ratio, ""))
+        TextView::new(utils::build_ratio_status_str(app.top_section.ratio, 0))
           .with_name(consts::ratio_status_unit_view),
       )
+      .child(
+        "SWG: ",
+        TextView::new(utils::build_swing_status_str(app.top_section.swing, None))
+          .with_name(consts::swing_status_unit_view),
+      )
+      .child(
+        "BBT: ",
+        TextView::new(utils::build_bbt_status_str((1, 1, 1)))
+          .with_name(consts::bbt_status_unit_view),
+      )
       .child(
         "LEN: ",
         TextView::new(utils::build_len_status_str(app.top_section.len))
@@ -143,6 +155,34 @@ impl TopSection {
         "EVQ:",
         TextView::new("[]").with_name(consts::ev_queue_status_unit_view),
       )
+      .child(
+        "LNC:",
+        TextView::new(utils::build_launch_status_str("off", 0))
+          .with_name(consts::launch_status_unit_view),
+      )
+      .child(
+        "RMP:",
+        TextView::new(utils::build_ramp_status_str(None, 0))
+          .with_name(consts::ramp_status_unit_view),
+      )
+      .child(
+        "NTE:",
+        TextView::new(utils::build_note_status_str("scale", 0))
+          .with_name(consts::note_status_unit_view),
+      )
+      .child(
+        "GTE:",
+        TextView::new(utils::build_gate_status_str(
+          false,
+          consts::DEFAULT_GATE_PERCENT,
+        ))
+        .with_name(consts::gate_status_unit_view),
+      )
+      .child(
+        "REC:",
+        TextView::new(utils::build_record_status_str(None))
+          .with_name(consts::record_status_unit_view),
+      )
       .full_width();
 
     FocusTracker::new(
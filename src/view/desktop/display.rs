@@ -1,11 +1,13 @@
 use crate::{
   app::UserData,
   core::{
+    channel_msg::ChannelMsg,
     consts,
     midi::{self, MidiMsg},
     parser::{self},
     utils,
   },
+  view::common::playhead_controller,
 };
 use cursive::{
   theme::Style,
@@ -127,17 +129,38 @@ impl MiddleSection {
                 )
                 .full_width(),
             )
+            .child(
+              ListView::new()
+                .child(
+                  "   CTL:",
+                  EditView::new()
+                    .content("")
+                    .style(Style::highlight_inactive())
+                    .with_name("midi_ctl"),
+                )
+                .full_width(),
+            )
             .child(
               Button::new_raw("[ SET ]", |s| {
                 let nte = get_input_msg(s, "midi_note");
                 let len = get_input_msg(s, "midi_len");
                 let vel = get_input_msg(s, "midi_vel");
                 let chn = get_input_msg(s, "midi_chan");
+                let ctl = get_input_msg(s, "midi_ctl");
                 let mut midi_msg_list = Vec::new();
+                let mut control_msg_list = Vec::new();
 
-                if [&nte, &len, &vel, &chn].iter().any(|s| s.is_empty()) {
-                  println!("midi msg should not left blank");
-                } else {
+                // notes need all of their fields, controls only a channel
+                let has_notes = [&nte, &len, &vel].iter().any(|s| !s.is_empty());
+                if chn.is_empty()
+                  || (!has_notes && ctl.is_empty())
+                  || (has_notes && [&nte, &len, &vel].iter().any(|s| s.is_empty()))
+                {
+                  s.add_layer(Dialog::info("midi msg should not be left blank"));
+                  return;
+                }
+
+                if has_notes {
                   let midi_msg_str = [&nte, &len, &vel, &chn]
                     .iter()
                     .map(|arc_str| arc_str.as_str()) // Convert Arc<String> to &str
@@ -146,6 +169,8 @@ impl MiddleSection {
 
                   match parser::midi::parser::parse_midi_msg(&midi_msg_str) {
                     Ok((_remaining, (note_n_oct, length, velocity, channel))) => {
+                      // the tab counts channels from 1, the status byte from 0
+                      let channel = channel - 1;
                       for (i, (note, octave)) in note_n_oct.iter().enumerate() {
                         let note_idx = [
                           "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
@@ -169,8 +194,28 @@ impl MiddleSection {
 
                         midi_msg_list.push(midi_msg);
                       }
+                    }
+                    Err(e) => {
+                      s.add_layer(Dialog::around(TextView::new(e.to_string())).button(
+                        "Close",
+                        |s| {
+                          s.pop_layer();
+                        },
+                      ));
+                      return;
+                    }
+                  }
+                }
 
-                      input_submit_note(s, &midi_msg_list);
+                if !ctl.is_empty() {
+                  let control_msg_str = format!("{} {}", ctl, chn);
+                  match parser::midi::parser::parse_control_msg(&control_msg_str) {
+                    Ok((_remaining, (controls, channel))) => {
+                      let channel = channel - 1;
+                      control_msg_list = controls
+                        .into_iter()
+                        .map(|(kind, group)| ChannelMsg::new(kind, channel, group))
+                        .collect();
                     }
                     Err(e) => {
                       s.add_layer(Dialog::around(TextView::new(e.to_string())).button(
@@ -179,9 +224,12 @@ impl MiddleSection {
                           s.pop_layer();
                         },
                       ));
+                      return;
                     }
                   }
                 }
+
+                input_submit_note(s, &midi_msg_list, &control_msg_list);
               })
               .with_name("midi_submit_config")
               .full_width(),
@@ -196,7 +244,7 @@ impl MiddleSection {
   pub fn build_tab() -> NamedView<TabPanel> {
     let mut tab = TabPanel::new()
       .with_tab(Self::build_welcome_msg())
-      // .with_tab(Self::build_midi_input())
+      .with_tab(Self::build_midi_input())
       // .with_tab(Self::build_osc_input())
       .with_bar_alignment(Align::End)
       .with_name(consts::interactive_display_section_view);
@@ -214,13 +262,25 @@ impl MiddleSection {
     FocusTracker::new(tab).fixed_height(8)
   }
 }
-fn input_submit_note(s: &mut Cursive, midi_msg: &[MidiMsg]) {
+fn input_submit_note(s: &mut Cursive, midi_msg: &[MidiMsg], control_msg: &[ChannelMsg]) {
   if let Some(data) = s.user_data::<UserData>().cloned() {
     let _ = data.midi_tx.send(midi::Message::ClearMsgConfig());
     midi_msg.iter().for_each(|msg| {
       let set_midi_conf = midi::Message::SetMsgConfig(msg.clone());
       let _ = data.midi_tx.send(set_midi_conf);
     });
+    // `pc12@marker` goes to the marker being edited, the rest is sent from the matches
+    let program = control_msg.iter().find_map(ChannelMsg::marker_program);
+    let _ = data
+      .marker_tx
+      .send(playhead_controller::Message::SetProgram(program));
+    control_msg
+      .iter()
+      .filter(|msg| msg.marker_program().is_none())
+      .for_each(|msg| {
+        let set_control_conf = midi::Message::SetControlConfig(msg.clone());
+        let _ = data.midi_tx.send(set_control_conf);
+      });
   };
 }
 
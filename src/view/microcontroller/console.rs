@@ -22,11 +22,14 @@ use crate::app::UserData;
 use crate::core::regex;
 
 use super::app::Anu;
+use crate::core::channel_msg::ChannelMsg;
+use crate::core::command::Command;
 use crate::core::consts;
 use crate::core::midi::{self, MidiMsg};
 use crate::core::parser::{self};
 use crate::core::utils;
 use crate::view::common::grid_editor::CanvasEditor;
+use crate::view::common::playhead_controller;
 use cursive::theme::Style;
 use cursive::view::{Nameable, Resizable};
 use cursive::Cursive;
@@ -50,6 +53,7 @@ pub enum RegexMode {
 pub struct Console {
   bpm: usize,
   ratio: (i64, usize),
+  swing: i64,
   pos: Vec2,
   len: (usize, usize),
 }
@@ -65,6 +69,7 @@ impl Console {
     Console {
       bpm: 120,
       ratio: (1, 16),
+      swing: consts::DEFAULT_SWING,
       pos: Vec2::zero(),
       len: (1, 1),
     }
@@ -122,14 +127,33 @@ impl Console {
     let status_controller_section_view = ListView::new()
       .child(
         "BPM:",
-        TextView::new(utils::build_bpm_status_str(app.top_section.bpm))
-          .with_name(consts::bpm_status_unit_view),
+        // a front panel button taps like the `t` key
+        LinearLayout::horizontal()
+          .child(
+            TextView::new(utils::build_bpm_status_str(app.top_section.bpm))
+              .with_name(consts::bpm_status_unit_view),
+          )
+          .child(Button::new_raw(" [TAP]", |s| {
+            if let Some(data) = s.user_data::<UserData>().cloned() {
+              data.cmd.handle(s, Command::TapTempo);
+            }
+          })),
       )
       .child(
         "RTO:",
-        TextView::new(utils::build_ratio_status_str(app.top_section.ratio, ""))
+        TextView::new(utils::build_ratio_status_str(app.top_section.ratio, 0))
           .with_name(consts::ratio_status_unit_view),
       )
+      .child(
+        "SWG:",
+        TextView::new(utils::build_swing_status_str(app.top_section.swing, None))
+          .with_name(consts::swing_status_unit_view),
+      )
+      .child(
+        "BBT:",
+        TextView::new(utils::build_bbt_status_str((1, 1, 1)))
+          .with_name(consts::bbt_status_unit_view),
+      )
       .child(
         "LEN:",
         TextView::new(utils::build_len_status_str(app.top_section.len))
@@ -162,6 +186,34 @@ impl Console {
         "EVQ:",
         TextView::new("[]").with_name(consts::ev_queue_status_unit_view),
       )
+      .child(
+        "LNC:",
+        TextView::new(utils::build_launch_status_str("off", 0))
+          .with_name(consts::launch_status_unit_view),
+      )
+      .child(
+        "RMP:",
+        TextView::new(utils::build_ramp_status_str(None, 0))
+          .with_name(consts::ramp_status_unit_view),
+      )
+      .child(
+        "NTE:",
+        TextView::new(utils::build_note_status_str("scale", 0))
+          .with_name(consts::note_status_unit_view),
+      )
+      .child(
+        "GTE:",
+        TextView::new(utils::build_gate_status_str(
+          false,
+          consts::DEFAULT_GATE_PERCENT,
+        ))
+        .with_name(consts::gate_status_unit_view),
+      )
+      .child(
+        "REC:",
+        TextView::new(utils::build_record_status_str(None))
+          .with_name(consts::record_status_unit_view),
+      )
       .fixed_width(100);
 
     let padding_section_1 = DummyView::new().fixed_width(2);
@@ -276,17 +328,38 @@ impl Console {
                 )
                 .full_width(),
             )
+            .child(
+              ListView::new()
+                .child(
+                  " M:",
+                  EditView::new()
+                    .content("")
+                    .style(Style::highlight_inactive())
+                    .with_name("midi_ctl"),
+                )
+                .full_width(),
+            )
             .child(
               Button::new_raw("[SET]", |s| {
                 let nte = get_input_msg(s, "midi_note");
                 let len = get_input_msg(s, "midi_len");
                 let vel = get_input_msg(s, "midi_vel");
                 let chn = get_input_msg(s, "midi_chan");
+                let ctl = get_input_msg(s, "midi_ctl");
                 let mut midi_msg_list = Vec::new();
+                let mut control_msg_list = Vec::new();
+
+                // notes need all of their fields, controls only a channel
+                let has_notes = [&nte, &len, &vel].iter().any(|s| !s.is_empty());
+                if chn.is_empty()
+                  || (!has_notes && ctl.is_empty())
+                  || (has_notes && [&nte, &len, &vel].iter().any(|s| s.is_empty()))
+                {
+                  s.add_layer(Dialog::info("midi msg should not be left blank"));
+                  return;
+                }
 
-                if [&nte, &len, &vel, &chn].iter().any(|s| s.is_empty()) {
-                  println!("midi msg should not left blank");
-                } else {
+                if has_notes {
                   let midi_msg_str = [&nte, &len, &vel, &chn]
                     .iter()
                     .map(|arc_str| arc_str.as_str()) // Convert Arc<String> to &str
@@ -295,6 +368,8 @@ impl Console {
 
                   match parser::midi::parser::parse_midi_msg(&midi_msg_str) {
                     Ok((_remaining, (note_n_oct, length, velocity, channel))) => {
+                      // the tab counts channels from 1, the status byte from 0
+                      let channel = channel - 1;
                       for (i, (note, octave)) in note_n_oct.iter().enumerate() {
                         let note_idx = [
                           "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
@@ -318,8 +393,28 @@ impl Console {
 
                         midi_msg_list.push(midi_msg);
                       }
+                    }
+                    Err(e) => {
+                      s.add_layer(Dialog::around(TextView::new(e.to_string())).button(
+                        "Close",
+                        |s| {
+                          s.pop_layer();
+                        },
+                      ));
+                      return;
+                    }
+                  }
+                }
 
-                      input_submit_note(s, &midi_msg_list);
+                if !ctl.is_empty() {
+                  let control_msg_str = format!("{} {}", ctl, chn);
+                  match parser::midi::parser::parse_control_msg(&control_msg_str) {
+                    Ok((_remaining, (controls, channel))) => {
+                      let channel = channel - 1;
+                      control_msg_list = controls
+                        .into_iter()
+                        .map(|(kind, group)| ChannelMsg::new(kind, channel, group))
+                        .collect();
                     }
                     Err(e) => {
                       s.add_layer(Dialog::around(TextView::new(e.to_string())).button(
@@ -328,9 +423,12 @@ impl Console {
                           s.pop_layer();
                         },
                       ));
+                      return;
                     }
                   }
                 }
+
+                input_submit_note(s, &midi_msg_list, &control_msg_list);
               })
               .with_name("midi_submit_config")
               .full_width(),
@@ -343,7 +441,7 @@ impl Console {
 
   pub fn build_tab(app: &mut Anu, regex_tx: Sender<regex::Message>) -> NamedView<TabPanel> {
     let mut tab = TabPanel::new()
-      // .with_tab(Self::build_midi_input())
+      .with_tab(Self::build_midi_input())
       .with_tab(Self::build_main(app, regex_tx))
       .with_bar_alignment(Align::End)
       .with_name(consts::interactive_display_section_view);
@@ -365,13 +463,25 @@ impl Console {
     FocusTracker::new(tab).fixed_height(8)
   }
 }
-fn input_submit_note(s: &mut Cursive, midi_msg: &[MidiMsg]) {
+fn input_submit_note(s: &mut Cursive, midi_msg: &[MidiMsg], control_msg: &[ChannelMsg]) {
   if let Some(data) = s.user_data::<UserData>().cloned() {
     let _ = data.midi_tx.send(midi::Message::ClearMsgConfig());
     midi_msg.iter().for_each(|msg| {
       let set_midi_conf = midi::Message::SetMsgConfig(msg.clone());
       let _ = data.midi_tx.send(set_midi_conf);
     });
+    // `pc12@marker` goes to the marker being edited, the rest is sent from the matches
+    let program = control_msg.iter().find_map(ChannelMsg::marker_program);
+    let _ = data
+      .marker_tx
+      .send(playhead_controller::Message::SetProgram(program));
+    control_msg
+      .iter()
+      .filter(|msg| msg.marker_program().is_none())
+      .for_each(|msg| {
+        let set_control_conf = midi::Message::SetControlConfig(msg.clone());
+        let _ = data.midi_tx.send(set_control_conf);
+      });
   };
 }
 